@@ -0,0 +1,372 @@
+//! Contains a local HTTP server with a JSON API for queueing downloads.
+//!
+//! The API has the following endpoints:
+//! - `POST /jobs`: Submits a job (see [`JobRequest`] for its options) and returns its status.
+//! - `GET /jobs`: Lists the status of all jobs.
+//! - `GET /jobs/{id}`: Returns the status of a job, including its progress.
+//! - `GET /jobs/{id}/log`: Returns the messages reported by a job.
+//! - `POST /jobs/{id}/cancel`: Cancels a job that hasn't finished yet, removing its output.
+//! - `GET /jobs/{id}/archive`: Returns the output of a successful job as a tar archive.
+//!
+//! Since browsers let any web page send requests to local servers, requests from other origins
+//! (or, unless a token is required, for other hosts) are rejected, and jobs must be submitted as
+//! `application/json`, which web pages can't send to other origins without our consent.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use aaoffline::MainContext;
+use aaoffline::args::Args;
+use aaoffline::fs::TokioFsWriter;
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use tokio::io::BufReader;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+use tokio::task::{AbortHandle, LocalSet};
+
+use crate::http::{self, Connection, Request, Response};
+use archive::Archive;
+use job::{JobReporter, JobRequest, JobState, JobStatus, NoInteraction};
+
+mod archive;
+mod job;
+
+/// The configuration of the API server.
+#[derive(Debug)]
+pub(crate) struct ApiConfig {
+    /// The address to listen on.
+    pub(crate) address: SocketAddr,
+    /// How many jobs may run at the same time.
+    pub(crate) max_jobs: usize,
+    /// The directory containing the output of each job, in a subdirectory named after its ID.
+    pub(crate) dir: PathBuf,
+    /// The base URL that was explicitly set, if any.
+    pub(crate) base_url: Option<String>,
+    /// The token that requests need to pass as `Authorization: Bearer {token}`, if any.
+    pub(crate) token: Option<String>,
+}
+
+impl ApiConfig {
+    /// Returns the hosts (in the form `host:port`) that requests may be made to.
+    fn hosts(&self) -> Vec<String> {
+        let port = self.address.port();
+        let mut hosts = vec![self.address.to_string()];
+        if self.address.ip().is_loopback() || self.address.ip().is_unspecified() {
+            hosts.extend([
+                format!("localhost:{port}"),
+                format!("127.0.0.1:{port}"),
+                format!("[::1]:{port}"),
+            ]);
+        }
+        hosts
+    }
+
+    /// Returns an error response if the given [request] may not be handled.
+    fn check_access(&self, request: &Request) -> Option<Response> {
+        if let Some(token) = &self.token {
+            let authorization = request.header("authorization").unwrap_or_default();
+            if authorization.strip_prefix("Bearer ") != Some(token.as_str()) {
+                return Some(Response::error(401, "Missing or invalid token."));
+            }
+        } else if !request.is_for_host(&self.hosts()) {
+            return Some(Response::error(403, "Unknown host."));
+        }
+        if let Some(origin) = request.header("origin") {
+            let host = request.header("host").unwrap_or_default();
+            if !origin.eq_ignore_ascii_case(&format!("http://{host}")) {
+                return Some(Response::error(
+                    403,
+                    "Requests from other origins are not allowed.",
+                ));
+            }
+        }
+        None
+    }
+}
+
+/// The state of the API server.
+#[derive(Debug)]
+struct Server {
+    /// The configuration of the server.
+    config: ApiConfig,
+    /// The arguments that jobs are based on.
+    defaults: Args,
+    /// The jobs that were submitted, keyed by their ID.
+    jobs: BTreeMap<u64, Job>,
+    /// The ID of the next job.
+    next_id: u64,
+    /// Limits how many jobs may run at the same time.
+    permits: Arc<Semaphore>,
+}
+
+/// A job that was submitted to the server.
+#[derive(Debug)]
+struct Job {
+    /// The status of the job, which is updated while it runs.
+    status: Arc<Mutex<JobStatus>>,
+    /// The output directory of the job.
+    output: PathBuf,
+    /// Allows cancelling the job.
+    handle: AbortHandle,
+}
+
+/// Runs the API server with the given [config] until it is stopped, basing jobs on the given
+/// [defaults].
+///
+/// The downloads of aaoffline can't be moved between threads, so all jobs run on this thread.
+pub(crate) async fn serve(config: ApiConfig, defaults: Args) -> Result<()> {
+    let listener = TcpListener::bind(config.address)
+        .await
+        .with_context(|| format!("Could not listen on {}", config.address))?;
+    if config.token.is_none() && !config.address.ip().is_loopback() {
+        warn!(
+            "The API is reachable from other machines, but no token is required (set AAOFFLINE_API_TOKEN to require one)."
+        );
+    }
+    info!(
+        "Listening on http://{}/ (running at most {} jobs at a time, writing to {}).",
+        config.address,
+        config.max_jobs,
+        config.dir.display()
+    );
+    let server = Rc::new(RefCell::new(Server {
+        permits: Arc::new(Semaphore::new(config.max_jobs.max(1))),
+        config,
+        defaults,
+        jobs: BTreeMap::new(),
+        next_id: 1,
+    }));
+    LocalSet::new()
+        .run_until(async move {
+            let connections = Arc::new(Semaphore::new(http::MAX_CONNECTIONS));
+            loop {
+                let permit = Arc::clone(&connections)
+                    .acquire_owned()
+                    .await
+                    .expect("semaphore is never closed");
+                let stream = match listener.accept().await {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        warn!("Could not accept connection: {e}");
+                        continue;
+                    }
+                };
+                let server = Rc::clone(&server);
+                tokio::task::spawn_local(async move {
+                    if let Err(e) = handle_connection(stream, &server).await {
+                        debug!("Could not handle request: {e:#}");
+                    }
+                    drop(permit);
+                });
+            }
+        })
+        .await
+}
+
+/// Handles the request on the given [stream].
+async fn handle_connection(stream: TcpStream, server: &RefCell<Server>) -> Result<()> {
+    let mut stream = BufReader::new(Connection::new(stream));
+    let request = match Request::read(&mut stream).await {
+        Ok(request) => request,
+        Err(e) => {
+            return Response::error(400, format!("{e:#}"))
+                .write(stream.get_mut())
+                .await;
+        }
+    };
+    debug!("API request: {} {}", request.method, request.path);
+    let denied = server.borrow().config.check_access(&request);
+    if let Some(response) = denied {
+        return response.write(stream.get_mut()).await;
+    }
+    let segments: Vec<_> = request
+        .path
+        .trim_matches('/')
+        .split('/')
+        .filter(|x| !x.is_empty())
+        .collect();
+    let response = match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["jobs"]) => list_jobs(server),
+        ("POST", ["jobs"]) if !is_json(&request) => {
+            Response::error(415, "Jobs must be submitted as application/json.")
+        }
+        ("POST", ["jobs"]) => submit_job(server, &request.body).await,
+        (method, ["jobs", id, rest @ ..]) => {
+            let Some(id) = id
+                .parse()
+                .ok()
+                .filter(|x| server.borrow().jobs.contains_key(x))
+            else {
+                return Response::error(404, format!("There is no job {id}."))
+                    .write(stream.get_mut())
+                    .await;
+            };
+            match (method, rest) {
+                ("GET", []) => job_status(server, id),
+                ("GET", ["log"]) => job_log(server, id),
+                ("POST", ["cancel"]) => cancel_job(server, id).await,
+                ("GET", ["archive"]) => return send_archive(server, id, stream.get_mut()).await,
+                _ => Response::error(405, "Method not allowed."),
+            }
+        }
+        _ => Response::error(404, "Not found."),
+    };
+    response.write(stream.get_mut()).await
+}
+
+/// Whether the body of the given [request] is declared to be JSON.
+fn is_json(request: &Request) -> bool {
+    request.header("content-type").is_some_and(|x| {
+        x.split(';')
+            .next()
+            .is_some_and(|x| x.trim().eq_ignore_ascii_case("application/json"))
+    })
+}
+
+/// Lists the status of all jobs.
+fn list_jobs(server: &RefCell<Server>) -> Response {
+    let server = server.borrow();
+    let statuses: Vec<_> = server
+        .jobs
+        .values()
+        .map(|x| x.status.lock().unwrap())
+        .collect();
+    Response::json(200, &statuses.iter().map(|x| &**x).collect::<Vec<_>>())
+}
+
+/// Submits the job described by the given [body], starting it as soon as possible.
+async fn submit_job(server: &RefCell<Server>, body: &[u8]) -> Response {
+    let request: JobRequest = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(e) => return Response::error(400, format!("Invalid job: {e}")),
+    };
+    let (id, output, args, permits) = {
+        let mut server = server.borrow_mut();
+        let id = server.next_id;
+        server.next_id += 1;
+        let output = server.config.dir.join(id.to_string());
+        match request.into_args(
+            &server.defaults,
+            server.config.base_url.as_deref(),
+            output.clone(),
+        ) {
+            Ok(args) => (id, output, args, Arc::clone(&server.permits)),
+            Err(e) => return Response::error(400, format!("Invalid job: {e}")),
+        }
+    };
+    // The output directory needs to exist, so that a single case is put inside of it.
+    if let Err(e) = tokio::fs::create_dir_all(&output).await {
+        return Response::error(500, format!("Could not create {}: {e}", output.display()));
+    }
+    let status = Arc::new(Mutex::new(JobStatus::new(id, &args)));
+    let reporter = JobReporter(Arc::clone(&status));
+    let handle = tokio::task::spawn_local(async move {
+        let _permit = permits
+            .acquire_owned()
+            .await
+            .expect("semaphore is never closed");
+        reporter.0.lock().unwrap().start();
+        info!("Starting job {id}.");
+        let mut ctx = MainContext::new(
+            args,
+            Box::new(TokioFsWriter),
+            Box::new(NoInteraction),
+            Box::new(reporter.clone()),
+        );
+        let result = ctx.run_all_steps().await;
+        info!(
+            "Job {id} {}.",
+            if result.is_ok() {
+                "succeeded"
+            } else {
+                "failed"
+            }
+        );
+        reporter
+            .0
+            .lock()
+            .unwrap()
+            .finish(result.map(|()| ctx.written_cases().to_vec()));
+    })
+    .abort_handle();
+    let response = Response::json(201, &*status.lock().unwrap());
+    server.borrow_mut().jobs.insert(
+        id,
+        Job {
+            status,
+            output,
+            handle,
+        },
+    );
+    response
+}
+
+/// Returns the status of the job with the given [id].
+fn job_status(server: &RefCell<Server>, id: u64) -> Response {
+    Response::json(200, &*server.borrow().jobs[&id].status.lock().unwrap())
+}
+
+/// Returns the log of the job with the given [id].
+fn job_log(server: &RefCell<Server>, id: u64) -> Response {
+    Response::json(200, &server.borrow().jobs[&id].status.lock().unwrap().log)
+}
+
+/// Cancels the job with the given [id] and removes its output.
+async fn cancel_job(server: &RefCell<Server>, id: u64) -> Response {
+    let output = {
+        let server = server.borrow();
+        let job = &server.jobs[&id];
+        let mut status = job.status.lock().unwrap();
+        if status.is_finished() {
+            return Response::error(409, format!("Job {id} has already finished."));
+        }
+        job.handle.abort();
+        status.cancel();
+        job.output.clone()
+    };
+    info!("Cancelled job {id}.");
+    if let Err(e) = tokio::fs::remove_dir_all(&output).await {
+        warn!("Could not remove output of cancelled job {id}: {e}");
+    }
+    job_status(server, id)
+}
+
+/// Sends the output of the job with the given [id] as a tar archive to the given [stream].
+async fn send_archive(
+    server: &RefCell<Server>,
+    id: u64,
+    stream: &mut Connection<TcpStream>,
+) -> Result<()> {
+    let (state, output) = {
+        let job = &server.borrow().jobs[&id];
+        let state = job.status.lock().unwrap().state;
+        (state, job.output.clone())
+    };
+    if state != JobState::Succeeded {
+        return Response::error(409, format!("Job {id} has not succeeded."))
+            .write(stream)
+            .await;
+    }
+    let name = format!("aaoffline-job-{id}");
+    let archive = match Archive::of(&output, &name) {
+        Ok(archive) => archive,
+        Err(e) => return Response::error(500, format!("{e:#}")).write(stream).await,
+    };
+    http::write_head(
+        stream,
+        200,
+        "application/x-tar",
+        archive.len(),
+        &[(
+            "Content-Disposition",
+            &format!("attachment; filename=\"{name}.tar\""),
+        )],
+    )
+    .await?;
+    archive.write_to(stream).await
+}
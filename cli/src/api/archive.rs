@@ -0,0 +1,165 @@
+//! Packs the output of a job into an (uncompressed) tar archive.
+//!
+//! Most of the output consists of already compressed media, so compressing it again isn't worth
+//! the effort.
+
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use anyhow::{Context, Result};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// The size of a block in a tar archive.
+const BLOCK_SIZE: u64 = 512;
+
+/// The maximum length of a name that fits into the header of an entry.
+const MAX_NAME_LENGTH: usize = 100;
+
+/// A tar archive of the files in a directory.
+#[derive(Debug)]
+pub(crate) struct Archive {
+    /// The files in the archive, ordered by their name.
+    entries: Vec<Entry>,
+}
+
+/// A file in an [Archive].
+#[derive(Debug)]
+struct Entry {
+    /// The name of the file within the archive.
+    name: String,
+    /// The file on disk.
+    source: PathBuf,
+    /// The size of the file.
+    size: u64,
+    /// The modification time of the file, in seconds since the epoch.
+    mtime: u64,
+}
+
+impl Archive {
+    /// Collects the files in the given [dir], which are put under the given [prefix] in the
+    /// archive.
+    ///
+    /// Symbolic links are followed, so that the archive can be unpacked anywhere.
+    pub(crate) fn of(dir: &Path, prefix: &str) -> Result<Archive> {
+        let mut entries = vec![];
+        collect(dir, prefix, &mut entries)?;
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Archive { entries })
+    }
+
+    /// Returns the size of the archive in bytes.
+    pub(crate) fn len(&self) -> u64 {
+        self.entries.iter().map(Entry::len).sum::<u64>() + 2 * BLOCK_SIZE
+    }
+
+    /// Writes the archive to the given [writer].
+    pub(crate) async fn write_to(&self, writer: &mut (impl AsyncWrite + Unpin)) -> Result<()> {
+        for entry in &self.entries {
+            if entry.name.len() > MAX_NAME_LENGTH {
+                // Longer names are stored in a separate entry before the file (a GNU extension).
+                let name = format!("{}\0", entry.name);
+                writer
+                    .write_all(&header("././@LongLink", name.len() as u64, 0, b'L'))
+                    .await?;
+                writer.write_all(name.as_bytes()).await?;
+                write_padding(writer, name.len() as u64).await?;
+            }
+            writer
+                .write_all(&header(&entry.name, entry.size, entry.mtime, b'0'))
+                .await?;
+            let mut file = tokio::fs::File::open(&entry.source)
+                .await
+                .with_context(|| format!("Could not open {}", entry.source.display()))?;
+            // The file must not be longer than announced in its header.
+            let written = tokio::io::copy(
+                &mut tokio::io::AsyncReadExt::take(&mut file, entry.size),
+                writer,
+            )
+            .await?;
+            anyhow::ensure!(
+                written == entry.size,
+                "{} changed while it was being archived.",
+                entry.source.display()
+            );
+            write_padding(writer, entry.size).await?;
+        }
+        writer
+            .write_all(&[0; 2 * BLOCK_SIZE as usize])
+            .await
+            .map_err(Into::into)
+    }
+}
+
+impl Entry {
+    /// Returns the number of bytes this entry takes up in the archive.
+    fn len(&self) -> u64 {
+        let long_name = if self.name.len() > MAX_NAME_LENGTH {
+            BLOCK_SIZE + padded(self.name.len() as u64 + 1)
+        } else {
+            0
+        };
+        long_name + BLOCK_SIZE + padded(self.size)
+    }
+}
+
+/// Adds the files in the given [dir] to the given [entries], putting them under [prefix].
+fn collect(dir: &Path, prefix: &str, entries: &mut Vec<Entry>) -> Result<()> {
+    for child in dir
+        .read_dir()
+        .with_context(|| format!("Could not read {}", dir.display()))?
+    {
+        let path = child?.path();
+        let name = format!("{prefix}/{}", path.file_name().unwrap().to_string_lossy());
+        let metadata = std::fs::metadata(&path)
+            .with_context(|| format!("Could not read {}", path.display()))?;
+        if metadata.is_dir() {
+            collect(&path, &name, entries)?;
+        } else if metadata.is_file() {
+            entries.push(Entry {
+                name,
+                source: path,
+                size: metadata.len(),
+                mtime: metadata
+                    .modified()
+                    .ok()
+                    .and_then(|x| x.duration_since(UNIX_EPOCH).ok())
+                    .map_or(0, |x| x.as_secs()),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Returns the given [size] rounded up to whole blocks.
+fn padded(size: u64) -> u64 {
+    size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE
+}
+
+/// Writes the zeroes needed to fill up the last block of data with the given [size].
+async fn write_padding(writer: &mut (impl AsyncWrite + Unpin), size: u64) -> Result<()> {
+    let padding = usize::try_from(padded(size) - size).expect("padding is below block size");
+    Ok(writer.write_all(&vec![0; padding]).await?)
+}
+
+/// Returns the (ustar) header of an entry with the given [name], [size], [mtime], and [kind].
+fn header(name: &str, size: u64, mtime: u64, kind: u8) -> [u8; BLOCK_SIZE as usize] {
+    let mut header = [0; BLOCK_SIZE as usize];
+    let mut set = |offset: usize, value: &[u8]| {
+        header[offset..offset + value.len()].copy_from_slice(value);
+    };
+    let name = name.as_bytes();
+    set(0, &name[..name.len().min(MAX_NAME_LENGTH)]);
+    set(100, b"0000644\0");
+    set(108, b"0000000\0");
+    set(116, b"0000000\0");
+    set(124, format!("{size:011o}\0").as_bytes());
+    set(136, format!("{mtime:011o}\0").as_bytes());
+    // The checksum is computed with its own field filled with spaces.
+    set(148, b"        ");
+    set(156, &[kind]);
+    set(257, b"ustar\0");
+    set(263, b"00");
+    let checksum: u32 = header.iter().map(|x| u32::from(*x)).sum();
+    header[148..156].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+    header
+}
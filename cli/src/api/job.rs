@@ -0,0 +1,386 @@
+//! Contains the jobs of the API server, i.e., the downloads that were submitted to it.
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use aaoffline::args::{
+    Args, DownloadSequence, SequenceErrorHandling, SequenceSelection, Userscripts,
+};
+use aaoffline::{InteractiveDialog, MAX_STEPS, ProgressReporter};
+use serde::{Deserialize, Serialize};
+
+use crate::args::{
+    CliAssetCategory, CliDownloadSequence, CliOmittedAssetHandling, CliUserscripts, CliVoiceFormat,
+};
+
+/// A download submitted to the API, along with the options that differ from the defaults the
+/// server was started with.
+///
+/// The fields are named like the corresponding command-line options, so that the documentation
+/// of `serve-api` doesn't need to list them.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct JobRequest {
+    /// The URLs or IDs of the cases to download.
+    #[serde(default)]
+    cases: Vec<String>,
+    /// The ID of an author (or the URL to their profile) whose cases shall all be downloaded.
+    author: Option<String>,
+    /// The backed-up revision of the case to download.
+    revision: Option<String>,
+    /// The branch or commit name of Ace Attorney Online to use for the player.
+    player_version: Option<String>,
+    /// The language to download the player in.
+    language: Option<String>,
+    /// Whether to download all cases contained in a sequence.
+    sequence: Option<CliDownloadSequence>,
+    /// The positions of the cases of a sequence to download (e.g., `9-12`).
+    sequence_range: Option<String>,
+    /// Whether to output only a single HTML file.
+    one_html_file: Option<bool>,
+    /// Whether to compress the assets of the single HTML file.
+    compress_assets: Option<bool>,
+    /// The size above which assets are written next to the single HTML file (e.g., `2M`).
+    sidecar_threshold: Option<String>,
+    /// Whether to put all cases into the single HTML file.
+    bundle_cases: Option<bool>,
+    /// The userscripts to apply.
+    with_userscripts: Option<Vec<CliUserscripts>>,
+    /// Whether to continue when an asset could not be downloaded.
+    continue_on_asset_error: Option<bool>,
+    /// How many concurrent downloads to use.
+    concurrent_downloads: Option<usize>,
+    /// Whether to disable the use of HTML5 audio.
+    disable_html5_audio: Option<bool>,
+    /// Whether to embed voice blips and short sound effects into the case data.
+    embed_short_audio: Option<bool>,
+    /// The format in which the voice blips shall be downloaded.
+    voice_format: Option<CliVoiceFormat>,
+    /// Whether to make the downloaded cases installable as web apps.
+    web_app: Option<bool>,
+    /// The categories of assets that shall not be downloaded.
+    omit_assets: Option<Vec<CliAssetCategory>>,
+    /// What to put in place of the omitted assets.
+    omitted_asset_handling: Option<CliOmittedAssetHandling>,
+    /// Whether to disable the automatic fixing of photobucket watermarks.
+    disable_photobucket_fix: Option<bool>,
+}
+
+impl JobRequest {
+    /// Returns the arguments for this job, based on the given [defaults] and writing to the given
+    /// [output]. The [base_url] is the one that was explicitly set for the server, if any.
+    pub(crate) fn into_args(
+        self,
+        defaults: &Args,
+        base_url: Option<&str>,
+        output: PathBuf,
+    ) -> Result<Args, String> {
+        let cases = self
+            .cases
+            .iter()
+            .map(|x| Args::accept_case(x))
+            .collect::<Result<Vec<_>, _>>()?;
+        let author = self
+            .author
+            .as_deref()
+            .map(Args::accept_author)
+            .transpose()?;
+        if cases.is_empty() && author.is_none() {
+            return Err("At least one case or an author must be given.".into());
+        }
+        let (resolved_base_url, warning) = Args::resolve_base_url(base_url, &cases)?;
+        let mut args = defaults.clone();
+        let (case_hosts, case_hosts_warning) =
+            Args::resolve_case_hosts(base_url, &cases, &resolved_base_url)?;
+        args.case_hosts = case_hosts;
+        args.base_url = resolved_base_url;
+        args.warnings = warning.into_iter().chain(case_hosts_warning).collect();
+        args.cases = cases.into_iter().map(|(id, _)| id).collect();
+        args.case_files.clear();
+        args.case_overrides.clear();
+        args.author = author;
+        args.revision = self.revision;
+        args.list_revisions = false;
+        args.library_command = None;
+        args.output = Some(output);
+        // Jobs must not share a library or link to each other's cases, since the outputs of all
+        // jobs are next to each other.
+        args.isolated = true;
+        if let Some(version) = self.player_version {
+            args.player_version = version;
+        }
+        if let Some(language) = self.language {
+            args.language = language;
+        }
+        if let Some(sequence) = self.sequence {
+            args.sequence = sequence.into();
+        }
+        if let Some(range) = self.sequence_range {
+            args.sequence_selection = Some(SequenceSelection::Range(Args::accept_sequence_range(
+                &range,
+            )?));
+        }
+        if let Some(scripts) = self.with_userscripts {
+            args.with_userscripts = scripts.into_iter().map(Into::into).collect();
+            Userscripts::validate_combination(&args.with_userscripts)?;
+        }
+        args.one_html_file = self.one_html_file.unwrap_or(args.one_html_file);
+        args.compress_assets = self.compress_assets.unwrap_or(args.compress_assets);
+        if let Some(threshold) = self.sidecar_threshold {
+            args.sidecar_threshold = Some(Args::accept_size(&threshold)?);
+        }
+        args.bundle_cases = self.bundle_cases.unwrap_or(args.bundle_cases);
+        args.continue_on_asset_error = self
+            .continue_on_asset_error
+            .unwrap_or(args.continue_on_asset_error);
+        args.concurrent_downloads = self
+            .concurrent_downloads
+            .unwrap_or(args.concurrent_downloads);
+        args.disable_html5_audio = self.disable_html5_audio.unwrap_or(args.disable_html5_audio);
+        args.embed_short_audio = self.embed_short_audio.unwrap_or(args.embed_short_audio);
+        if let Some(format) = self.voice_format {
+            args.voice_format = format.into();
+        }
+        args.web_app = self.web_app.unwrap_or(args.web_app);
+        if let Some(categories) = self.omit_assets {
+            args.omit_assets = categories.into_iter().map(Into::into).collect();
+        }
+        if let Some(handling) = self.omitted_asset_handling {
+            args.omitted_asset_handling = handling.into();
+        }
+        args.disable_photobucket_fix = self
+            .disable_photobucket_fix
+            .unwrap_or(args.disable_photobucket_fix);
+        // Nobody is around to answer questions, so we pick the answers that a non-interactive
+        // terminal would get.
+        if args.sequence == DownloadSequence::Ask {
+            args.sequence = DownloadSequence::Single;
+        }
+        if args.sequence_error_handling == SequenceErrorHandling::Ask {
+            args.sequence_error_handling = SequenceErrorHandling::Abort;
+        }
+        Ok(args)
+    }
+}
+
+/// The status of a job, as reported by the API.
+#[derive(Debug, Serialize)]
+pub(crate) struct JobStatus {
+    /// The ID of the job.
+    id: u64,
+    /// The state the job is in.
+    pub(crate) state: JobState,
+    /// The IDs of the cases that were requested.
+    cases: Vec<u32>,
+    /// The ID of the author whose cases were requested, if any.
+    author: Option<u32>,
+    /// When the job was submitted, in seconds since the epoch.
+    submitted: u64,
+    /// When the job started running, in seconds since the epoch.
+    started: Option<u64>,
+    /// When the job finished, in seconds since the epoch.
+    finished: Option<u64>,
+    /// The error the job failed with, if any.
+    error: Option<String>,
+    /// The progress of the job.
+    progress: Progress,
+    /// The IDs of the cases that were written.
+    written_cases: Vec<u32>,
+    /// The messages reported by the job so far.
+    #[serde(skip)]
+    pub(crate) log: Vec<LogEntry>,
+}
+
+/// The state of a job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum JobState {
+    /// Waiting for other jobs to finish.
+    Queued,
+    /// Downloading the cases.
+    Running,
+    /// All cases were downloaded.
+    Succeeded,
+    /// Downloading the cases failed.
+    Failed,
+    /// The job was cancelled.
+    Cancelled,
+}
+
+/// The progress of a job.
+#[derive(Debug, Default, Serialize)]
+struct Progress {
+    /// The number of the current step.
+    step: u8,
+    /// The total number of steps.
+    steps: u8,
+    /// The description of the current step.
+    text: String,
+    /// The position of the progress within the current step.
+    position: u64,
+    /// The total length of the progress within the current step, if it is known.
+    length: u64,
+}
+
+/// A message reported by a job.
+#[derive(Debug, Serialize)]
+pub(crate) struct LogEntry {
+    /// When the message was reported, in seconds since the epoch.
+    time: u64,
+    /// The message.
+    message: String,
+}
+
+impl JobStatus {
+    /// Creates the status of a newly submitted job with the given [id] and [args].
+    pub(crate) fn new(id: u64, args: &Args) -> JobStatus {
+        JobStatus {
+            id,
+            state: JobState::Queued,
+            cases: args.cases.clone(),
+            author: args.author,
+            submitted: now(),
+            started: None,
+            finished: None,
+            error: None,
+            progress: Progress {
+                steps: MAX_STEPS,
+                ..Progress::default()
+            },
+            written_cases: vec![],
+            log: vec![],
+        }
+    }
+
+    /// Whether the job has finished (successfully or not).
+    pub(crate) fn is_finished(&self) -> bool {
+        !matches!(self.state, JobState::Queued | JobState::Running)
+    }
+
+    /// Records that the job started running.
+    pub(crate) fn start(&mut self) {
+        self.state = JobState::Running;
+        self.started = Some(now());
+        self.log("Started.".to_string());
+    }
+
+    /// Records that the job finished with the given [result], which contains the IDs of the
+    /// written cases if it was successful.
+    pub(crate) fn finish(&mut self, result: anyhow::Result<Vec<u32>>) {
+        self.finished = Some(now());
+        match result {
+            Ok(written) => {
+                self.state = JobState::Succeeded;
+                self.written_cases = written;
+            }
+            Err(e) => {
+                self.state = JobState::Failed;
+                self.log(format!("Error: {e:#}"));
+                self.error = Some(format!("{e:#}"));
+            }
+        }
+    }
+
+    /// Records that the job was cancelled.
+    pub(crate) fn cancel(&mut self) {
+        self.state = JobState::Cancelled;
+        self.finished = Some(now());
+        self.log("Cancelled.".to_string());
+    }
+
+    /// Adds the given [message] to the log of the job.
+    fn log(&mut self, message: String) {
+        self.log.push(LogEntry {
+            time: now(),
+            message,
+        });
+    }
+}
+
+/// Reports the progress of a job to its [JobStatus].
+#[derive(Debug, Clone)]
+pub(crate) struct JobReporter(pub(crate) Arc<Mutex<JobStatus>>);
+
+impl JobReporter {
+    fn with_progress(&self, f: impl FnOnce(&mut Progress)) {
+        f(&mut self.0.lock().unwrap().progress);
+    }
+}
+
+impl ProgressReporter for JobReporter {
+    fn inc(&self, delta: u64) {
+        self.with_progress(|x| x.position += delta);
+    }
+
+    fn inc_length(&self, delta: u64) {
+        self.with_progress(|x| x.length += delta);
+    }
+
+    fn next_step(&self, step: u8, text: &str, _hidden: bool) {
+        let mut status = self.0.lock().unwrap();
+        status.progress.step = step;
+        status.progress.text = text.to_string();
+        status.log(format!("[{step}/{MAX_STEPS}] {text}"));
+    }
+
+    fn new_progress(&self, max: u64, _hidden: bool) {
+        self.with_progress(|x| {
+            x.position = 0;
+            x.length = max;
+        });
+    }
+
+    fn suspend(&self, f: &dyn Fn() -> Option<bool>) -> Option<bool> {
+        f()
+    }
+
+    fn finish_progress(&self, msg: String) {
+        self.0.lock().unwrap().log(strip_ansi(&msg));
+    }
+
+    fn finish_and_clear(&self) {}
+}
+
+/// Answers no questions, cancelling the job instead.
+///
+/// Jobs are set up so that they don't need to ask anything (see [`JobRequest::into_args`]).
+#[derive(Debug)]
+pub(crate) struct NoInteraction;
+
+impl InteractiveDialog for NoInteraction {
+    fn confirm(&mut self, _prompt: &str, _default_value: bool) -> Option<bool> {
+        None
+    }
+
+    fn select(
+        &mut self,
+        _prompt: &str,
+        _items: &[String],
+        _defaults: &[bool],
+    ) -> Option<Vec<usize>> {
+        None
+    }
+}
+
+/// Removes the escape sequences used for colors in terminals from the given [text].
+fn strip_ansi(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Skips everything up to (and including) the final letter of the sequence.
+            chars.by_ref().find(char::is_ascii_alphabetic);
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// Returns the current time in seconds since the epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs())
+}
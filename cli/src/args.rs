@@ -1,25 +1,102 @@
-use std::path::PathBuf;
-
-use aaoffline::args::{Args, DownloadSequence, HttpHandling, SequenceErrorHandling, Userscripts};
-use clap::{CommandFactory, Parser, ValueEnum, error::ErrorKind};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use aaoffline::args::{
+    Args, AssetCategory, Authentication, AuthorFilter, CaseEntry, DownloadSequence, HttpHandling,
+    LibraryCommand, OmittedAssetHandling, SequenceErrorHandling, SequenceSelection, Userscripts,
+    VoiceFormat,
+};
+use aaoffline::fs;
+use chrono::NaiveDate;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum, error::ErrorKind};
 #[cfg(debug_assertions)]
 use clap_verbosity_flag::DebugLevel;
 #[cfg(not(debug_assertions))]
 use clap_verbosity_flag::InfoLevel;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 /// Downloads an Ace Attorney Online case to be playable offline.
 ///
 /// Simply pass the URL (i.e., `https://aaonline.fr/player.php?trial_id=YOUR_ID`) to this script.
 /// You can also directly pass the ID instead.
 #[derive(Debug, Clone, Parser)]
-#[command(version, about, arg_required_else_help(true))]
+#[command(
+    version,
+    about,
+    arg_required_else_help(true),
+    subcommand_negates_reqs(true)
+)]
 #[allow(clippy::struct_excessive_bools)]
 pub(crate) struct CliArgs {
     /// The URL to the case, or its ID. May be passed multiple times.
-    #[arg(required=true, num_args = 1.., value_parser = Args::accept_case)]
+    #[arg(required_unless_present_any = ["case_files", "author", "input_file"], num_args = 1.., value_parser = Args::accept_case)]
     pub(crate) cases: Vec<(u32, Option<String>)>,
 
+    /// A file containing the URLs or IDs of cases to download, one per line (use `-` to read
+    /// from standard input).
+    ///
+    /// Lines starting with `#` are comments. Each case may be followed by settings that only
+    /// apply to it, in the form `key=value` (use double quotes for values containing spaces):
+    /// `output` sets the name of the case's output file or directory, `version` sets the player
+    /// version. For example: `12345 output="My Case" version=master`
+    #[arg(short, long, value_name = "FILE")]
+    pub(crate) input_file: Option<PathBuf>,
+
+    /// A local trial file to import as a case instead of downloading it from Ace Attorney Online.
+    /// May be passed multiple times.
+    ///
+    /// The file may either be the output of AAO's `trial.js.php` for the case, or a JSON file
+    /// containing an object with its `trial_information` and `initial_trial_data`.
+    #[arg(long = "from-file", value_name = "FILE")]
+    pub(crate) case_files: Vec<PathBuf>,
+
+    /// The ID of an author (or the URL to their profile) whose public cases shall all be
+    /// downloaded.
+    ///
+    /// The cases are put into a directory for the author, with a subdirectory for each sequence.
+    #[arg(long, value_name = "ID_OR_URL", value_parser = Args::accept_author)]
+    pub(crate) author: Option<u32>,
+
+    /// Only download the cases of the author that are in the given language (e.g., `en`).
+    #[arg(long, value_name = "LANGUAGE", requires = "author")]
+    pub(crate) author_language: Option<String>,
+
+    /// Only download the cases of the author that were last edited on or after the given day
+    /// (in the form `YYYY-MM-DD`).
+    #[arg(long, value_name = "DATE", requires = "author", value_parser = Args::accept_date)]
+    pub(crate) edited_since: Option<NaiveDate>,
+
+    /// The URL at which the cases of an author are listed, for AAO instances that list them
+    /// elsewhere. Any `{author}` in it is replaced by the ID of the author.
+    ///
+    /// If the URL contains `{page}`, it is replaced by the page number (starting at 1), and pages
+    /// are read until one lists no new cases. If not set, the trial search of the AAO instance is
+    /// used, of which only the first page of results is read.
+    #[arg(long, value_name = "URL", requires = "author")]
+    pub(crate) author_cases_url: Option<String>,
+
+    /// A backed-up revision of the case to download instead of its current version.
+    ///
+    /// This is either the name of a revision (as shown by `--list-revisions`) or a date in the
+    /// form `YYYY-MM-DD`, in which case the latest revision backed up on or before that day is
+    /// used. Only a single case may be given when using this.
+    #[arg(long, value_name = "REVISION")]
+    pub(crate) revision: Option<String>,
+
+    /// Only list the backed-up revisions of the given case instead of downloading it.
+    #[arg(long, conflicts_with = "revision")]
+    pub(crate) list_revisions: bool,
+
+    /// The URL at which the revisions of a case are listed, for AAO instances that make their
+    /// backups available elsewhere. Any `{id}` in it is replaced by the ID of the case.
+    ///
+    /// If not set, the backups directory configured on the AAO instance is used.
+    #[arg(long, value_name = "URL")]
+    pub(crate) revisions_url: Option<String>,
+
     /// The output directory (or filename, if `-1` was used) for the case.
     ///
     /// If this is not passed, will use the title + ID of the case.
@@ -32,6 +109,39 @@ pub(crate) struct CliArgs {
     #[arg(short, long, default_value_t = String::from("master"))]
     pub(crate) player_version: String,
 
+    /// The directory in which the code of the player is cached between runs.
+    ///
+    /// Defaults to an `aaoffline` directory within your user's cache directory.
+    #[arg(long, value_name = "DIR")]
+    pub(crate) cache_dir: Option<PathBuf>,
+
+    /// Whether to always download the player code instead of using a cached version.
+    #[arg(long, default_value_t = false, conflicts_with = "cache_dir")]
+    pub(crate) no_cache: bool,
+
+    /// A local checkout of the AAO repository to use as the source for the player's code.
+    ///
+    /// If this is a git checkout, the player version is resolved within it. Otherwise, the files
+    /// in the given directory are used as they are.
+    #[arg(long, value_name = "PATH")]
+    pub(crate) player_source: Option<PathBuf>,
+
+    /// The URL under which the raw files of the AAO repository can be found.
+    ///
+    /// This is useful for self-hosted AAO instances whose code differs from the official one.
+    /// Any `{version}` in the URL is replaced by the player version.
+    /// If not set, will default to the official AAO repository on Bitbucket.
+    #[arg(long, value_name = "URL", conflicts_with = "player_source")]
+    pub(crate) player_code_url: Option<String>,
+
+    /// Whether to retrieve the player's scripts from the AAO instance (see `--base-url`) instead
+    /// of from the AAO repository.
+    ///
+    /// The player itself is still retrieved from the repository, since the instance only serves
+    /// its rendered output.
+    #[arg(long, default_value_t = false, conflicts_with = "player_source")]
+    pub(crate) player_from_instance: bool,
+
     /// The language to download the player in.
     #[arg(short, long, default_value_t = String::from("en"))]
     pub(crate) language: String,
@@ -44,11 +154,35 @@ pub(crate) struct CliArgs {
     #[arg(short('r'), long, default_value_t = false)]
     pub(crate) replace_existing: bool,
 
+    /// Whether to start a library at the root of the output (i.e., the output directory, or the
+    /// directory containing the case if only one is downloaded) if there is none yet.
+    ///
+    /// A library keeps track of the cases downloaded to it in an `aaoffline-library.json` file
+    /// and lists them in an `index.html` page, so that cases downloaded later can be linked to the
+    /// other cases of their sequence. Libraries that already exist are always updated.
+    #[arg(long)]
+    pub(crate) create_library: bool,
+
     /// Whether to download all trials contained in a sequence (if the given case is part of a
     /// sequence).
     #[arg(short('s'), long, value_enum, default_value_t)]
     pub(crate) sequence: CliDownloadSequence,
 
+    /// Only download the cases at the given positions of the sequence, in the form `START-END`
+    /// (e.g., `9-12`, or `9-` for everything from the ninth case on).
+    ///
+    /// Cases of the sequence that were already downloaded to the same directory before are still
+    /// linked to. This takes precedence over `--sequence`.
+    #[arg(long, value_name = "RANGE", value_parser = Args::accept_sequence_range, conflicts_with = "sequence_entries")]
+    pub(crate) sequence_range: Option<RangeInclusive<usize>>,
+
+    /// Only download the given cases (as URLs or IDs, separated by commas) of the sequence.
+    ///
+    /// Cases of the sequence that were already downloaded to the same directory before are still
+    /// linked to. This takes precedence over `--sequence`.
+    #[arg(long, value_name = "CASES", value_delimiter = ',', value_parser = Args::accept_case)]
+    pub(crate) sequence_entries: Vec<(u32, Option<String>)>,
+
     /// Whether to output only a single HTML file, with the assets embedded as data URLs.
     ///
     /// WARNING: Browsers may not like HTML files very much that are
@@ -56,6 +190,31 @@ pub(crate) struct CliArgs {
     #[arg(short('1'), long, default_value_t = false)]
     pub(crate) one_html_file: bool,
 
+    /// Whether to pack the assets into a single compressed blob when using `--one-html-file`.
+    ///
+    /// A small script in the HTML file decompresses the assets when the player needs them, which
+    /// makes the file considerably smaller and faster to load than one with data URLs.
+    #[arg(long, requires = "one_html_file")]
+    pub(crate) compress_assets: bool,
+
+    /// Write assets larger than the given size (e.g., `2M`) into a directory next to the HTML
+    /// file when using `--one-html-file`, instead of embedding them.
+    ///
+    /// This keeps the HTML file small enough for browsers when a case uses large media (such as
+    /// music), while the case stays nearly a single file. The directory is named like the HTML
+    /// file, with a `_files` suffix.
+    #[arg(long, value_name = "SIZE", value_parser = Args::accept_size, requires = "one_html_file")]
+    pub(crate) sidecar_threshold: Option<u64>,
+
+    /// Put all cases into a single HTML file when using `--one-html-file`, with a list to choose
+    /// a case from.
+    ///
+    /// Assets used by several cases are only embedded once, and redirections to the next case of
+    /// a sequence open it from the same file. When downloading the cases of an author, each
+    /// sequence gets its own file.
+    #[arg(long, requires = "one_html_file")]
+    pub(crate) bundle_cases: bool,
+
     /// Whether to apply any userscripts to the downloaded case. Can be passed multiple times.
     ///
     /// Scripts were created by Time Axis, with only the expanded keyboard controls written by me,
@@ -71,6 +230,13 @@ pub(crate) struct CliArgs {
     )]
     pub(crate) with_userscripts: Vec<CliUserscripts>,
 
+    /// The URL under which the userscripts can be found, if they shall not be retrieved from
+    /// their usual locations (e.g., for a self-hosted AAO instance without internet access).
+    ///
+    /// Userscripts are looked up by their filename (e.g., `aaobacklog.user.js`) within this URL.
+    #[arg(long, value_name = "URL")]
+    pub(crate) userscripts_url: Option<String>,
+
     /// How many concurrent downloads to use.
     #[arg(short('j'), long, default_value_t = 5)]
     pub(crate) concurrent_downloads: usize,
@@ -112,6 +278,43 @@ pub(crate) struct CliArgs {
     #[arg(long)]
     pub(crate) disable_html5_audio: bool,
 
+    /// Whether to embed voice blips and short sound effects into the case data, even when not
+    /// using `--one-html-file`.
+    ///
+    /// Browsers can then play these sounds via Web Audio when opening the HTML file locally,
+    /// avoiding both the CORS errors and the problems Firefox has with HTML5 audio. Music is
+    /// still saved as separate files.
+    #[arg(long)]
+    pub(crate) embed_short_audio: bool,
+
+    /// The format in which the voice blips shall be downloaded.
+    ///
+    /// By default, every format is downloaded, and the browser plays the first one it supports.
+    /// Choosing a single format makes each case smaller, and the player then uses it everywhere.
+    #[arg(long, value_enum, default_value_t)]
+    pub(crate) voice_format: CliVoiceFormat,
+
+    /// Whether to make the downloaded case installable as a progressive web app.
+    ///
+    /// This adds a web app manifest, icons (based on the case's first profile or evidence), and a
+    /// service worker that caches every file of the case. Once the case has been opened from a
+    /// web server (e.g., on an intranet), it can be installed and played offline, such as on
+    /// phones or tablets.
+    #[arg(long, conflicts_with = "one_html_file")]
+    pub(crate) web_app: bool,
+
+    /// Categories of assets that shall not be downloaded (separated by commas), e.g., to get a
+    /// lighter copy of a case without its music.
+    ///
+    /// The player then marks which assets it lacks (with an `aaoffline-omitted-assets` meta tag),
+    /// and the library index lists them next to the case.
+    #[arg(long, value_name = "CATEGORIES", value_enum, value_delimiter = ',')]
+    pub(crate) omit_assets: Vec<CliAssetCategory>,
+
+    /// What to put in place of the assets omitted via `--omit-assets`.
+    #[arg(long, value_enum, default_value_t)]
+    pub(crate) omitted_asset_handling: CliOmittedAssetHandling,
+
     /// Whether to disable the automatic fixing of photobucket watermarks.
     #[arg(long)]
     pub(crate) disable_photobucket_fix: bool,
@@ -131,6 +334,53 @@ pub(crate) struct CliArgs {
     #[arg(long)]
     pub base_url: Option<String>,
 
+    /// A host that is equivalent to the base URL (i.e., a mirror of it), to be used if a request
+    /// to the base URL fails. May be passed multiple times, in which case the mirrors are tried
+    /// in the given order.
+    ///
+    /// For example, `--mirror http://aceattorney.sparklin.org/` would fall back to the legacy
+    /// domain of Ace Attorney Online if aaonline.fr is unavailable.
+    #[arg(long = "mirror", value_name = "URL")]
+    pub(crate) mirrors: Vec<String>,
+
+    /// A cookie file containing a session on Ace Attorney Online, e.g., to download cases that
+    /// were privately shared with you.
+    ///
+    /// The file may either be in the Netscape format (as exported by most browser extensions) or
+    /// contain the value of a `Cookie` header. Cookies in the Netscape format are only sent to
+    /// the hosts of Ace Attorney Online within their domain, while those from a `Cookie` header
+    /// are only sent to the `--base-url`. This can't be used together with `--proxy`.
+    #[arg(long, value_name = "FILE", conflicts_with = "proxy")]
+    pub(crate) cookies: Option<PathBuf>,
+
+    /// The name of a forum user to log in to Ace Attorney Online as, e.g., to download cases
+    /// that were privately shared with you.
+    ///
+    /// The password is read from the `AAOFFLINE_PASSWORD` environment variable, or asked for
+    /// interactively if that isn't set. Can't be used together with `--proxy`.
+    #[arg(long, value_name = "USERNAME", conflicts_with_all = ["cookies", "proxy"])]
+    pub(crate) login: Option<String>,
+
+    /// Keep running and download the configured cases again every given interval (e.g., `30m`,
+    /// `6h`, or `1d`), so that the output directory mirrors them.
+    ///
+    /// Only cases that are new (e.g., new cases of the author or new parts of a sequence) or were
+    /// edited since their last download are downloaded. Every case of a sequence is downloaded
+    /// unless only some are selected. Failed runs are retried with increasing delays. A lock file
+    /// in the output directory makes sure that only one instance keeps it up to date at a time.
+    #[arg(long, value_name = "INTERVAL", value_parser = Args::accept_interval, conflicts_with_all = ["list_revisions", "revision"])]
+    pub(crate) watch: Option<Duration>,
+
+    /// The file to write the status of `--watch` to, as JSON.
+    ///
+    /// Defaults to `aaoffline-status.json` in the output directory.
+    #[arg(long, value_name = "FILE", requires = "watch")]
+    pub(crate) status_file: Option<PathBuf>,
+
+    /// A command to run instead of downloading cases.
+    #[command(subcommand)]
+    pub(crate) command: Option<CliCommand>,
+
     #[cfg(not(debug_assertions))]
     #[command(flatten)]
     pub(crate) verbose: clap_verbosity_flag::Verbosity<InfoLevel>,
@@ -141,6 +391,94 @@ pub(crate) struct CliArgs {
     pub(crate) verbose: clap_verbosity_flag::Verbosity<DebugLevel>,
 }
 
+/// Commands that can be run instead of downloading cases.
+#[derive(Debug, Clone, Subcommand)]
+pub(crate) enum CliCommand {
+    /// Manages a library, i.e., a directory that cases were downloaded to.
+    Library {
+        /// The root directory of the library.
+        #[arg(short, long, default_value = ".")]
+        dir: PathBuf,
+
+        #[command(subcommand)]
+        #[allow(missing_docs)]
+        command: CliLibraryCommand,
+    },
+    /// Runs a local HTTP server with a JSON API for queueing downloads.
+    ///
+    /// Jobs are submitted with `POST /jobs`, passing a JSON object with the `cases` to download
+    /// (as URLs or IDs) and optionally further download options. Their fields are named like the
+    /// long options of this program in snake case (e.g., `one_html_file` for `--one-html-file`),
+    /// and options given before this command are used as their defaults.
+    ///
+    /// `GET /jobs` and `GET /jobs/{id}` return the status and progress of jobs,
+    /// `GET /jobs/{id}/log` returns their messages, `POST /jobs/{id}/cancel` cancels them, and
+    /// `GET /jobs/{id}/archive` returns their output as a tar archive.
+    ServeApi {
+        /// The address to listen on.
+        ///
+        /// If the `AAOFFLINE_API_TOKEN` environment variable is set, every request needs to pass
+        /// its value as `Authorization: Bearer {token}`. Without a token, only requests for this
+        /// address (or `localhost`) are accepted, so it should only be reachable by trusted
+        /// clients.
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        address: SocketAddr,
+
+        /// How many jobs may run at the same time.
+        #[arg(long, default_value_t = 2)]
+        max_jobs: usize,
+
+        /// The directory to put the output of the jobs in, with a subdirectory for each job.
+        #[arg(short, long, default_value = "aaoffline-jobs")]
+        dir: PathBuf,
+    },
+    /// Plays a downloaded case by serving it on a local web server and opening it in the
+    /// browser.
+    ///
+    /// This avoids the problems browsers have with local files (e.g., with HTML5 audio in
+    /// Firefox). Only the given case is served; to be able to reach the other cases of its
+    /// sequence, pass the directory of their library instead.
+    Play {
+        /// The directory or HTML file of the case, or the directory of a library.
+        #[arg(default_value = ".")]
+        path: PathBuf,
+
+        /// The port to listen on (any free port if not set).
+        #[arg(long, default_value_t = 0)]
+        port: u16,
+
+        /// Only serve the case, without opening it in the browser.
+        #[arg(long)]
+        no_browser: bool,
+    },
+}
+
+/// Commands that manage a library.
+#[derive(Debug, Clone, Subcommand)]
+pub(crate) enum CliLibraryCommand {
+    /// Lists the cases in the library, along with their size.
+    List,
+    /// Removes a case from the library, deleting its files.
+    Remove {
+        /// The URL to the case, or its ID.
+        #[arg(value_parser = Args::accept_case)]
+        case: (u32, Option<String>),
+    },
+    /// Checks whether the cases in the library are outdated, or have been deleted or made
+    /// inaccessible on Ace Attorney Online.
+    Status,
+}
+
+impl From<CliLibraryCommand> for LibraryCommand {
+    fn from(value: CliLibraryCommand) -> Self {
+        match value {
+            CliLibraryCommand::List => LibraryCommand::List,
+            CliLibraryCommand::Remove { case } => LibraryCommand::Remove(case.0),
+            CliLibraryCommand::Status => LibraryCommand::Status,
+        }
+    }
+}
+
 /// How to handle insecure HTTP requests.
 #[derive(Debug, Clone, Copy, Serialize, Default, PartialEq, Eq, ValueEnum)]
 #[serde(rename_all = "kebab-case")]
@@ -156,8 +494,56 @@ pub(crate) enum CliHttpHandling {
     RedirectToHttps,
 }
 
+/// A category of case assets that can be omitted from a download.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum CliAssetCategory {
+    /// Icons and sprites of the profiles, including the default sprites.
+    Profiles,
+    /// Icons of the evidence and the images shown when checking it.
+    Evidence,
+    /// Backgrounds and foreground objects of places.
+    Places,
+    /// Popups, such as "Objection!".
+    Popups,
+    /// Background music.
+    Music,
+    /// Sound effects.
+    Sounds,
+    /// Voice blips.
+    Voices,
+}
+
+/// The format in which the voice blips are downloaded.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum CliVoiceFormat {
+    /// Download every format, so that each browser can pick the one it supports best.
+    #[default]
+    All,
+    /// Opus, which is the smallest, but not supported by some older browsers.
+    Opus,
+    /// WAV, which is supported by every browser, but the largest.
+    Wav,
+    /// MP3, which is supported by every browser.
+    Mp3,
+}
+
+/// What to put in place of omitted assets.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum CliOmittedAssetHandling {
+    /// Keep referencing the assets at their online location, so that they're still available
+    /// while there is an internet connection.
+    #[default]
+    Online,
+    /// Replace the assets with empty images and silent sounds, so that the case never accesses
+    /// the internet.
+    Placeholder,
+}
+
 /// Whether to download every case in a sequence if the given case is part of one.
-#[derive(Debug, Clone, Copy, Serialize, Default, ValueEnum)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, ValueEnum)]
 #[serde(rename_all = "kebab-case")]
 pub(crate) enum CliDownloadSequence {
     /// Automatically download every case in the sequence.
@@ -182,7 +568,7 @@ pub(crate) enum CliSequenceErrorHandling {
 }
 
 /// Whether to apply any userscripts to the downloaded case.
-#[derive(Debug, Clone, Copy, Serialize, Default, PartialEq, Eq, ValueEnum)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq, ValueEnum)]
 #[serde(rename_all = "kebab-case")]
 pub(crate) enum CliUserscripts {
     /// Apply all userscripts.
@@ -214,6 +600,40 @@ impl From<CliHttpHandling> for HttpHandling {
     }
 }
 
+impl From<CliAssetCategory> for AssetCategory {
+    fn from(value: CliAssetCategory) -> Self {
+        match value {
+            CliAssetCategory::Profiles => AssetCategory::Profiles,
+            CliAssetCategory::Evidence => AssetCategory::Evidence,
+            CliAssetCategory::Places => AssetCategory::Places,
+            CliAssetCategory::Popups => AssetCategory::Popups,
+            CliAssetCategory::Music => AssetCategory::Music,
+            CliAssetCategory::Sounds => AssetCategory::Sounds,
+            CliAssetCategory::Voices => AssetCategory::Voices,
+        }
+    }
+}
+
+impl From<CliVoiceFormat> for VoiceFormat {
+    fn from(value: CliVoiceFormat) -> Self {
+        match value {
+            CliVoiceFormat::All => VoiceFormat::All,
+            CliVoiceFormat::Opus => VoiceFormat::Opus,
+            CliVoiceFormat::Wav => VoiceFormat::Wav,
+            CliVoiceFormat::Mp3 => VoiceFormat::Mp3,
+        }
+    }
+}
+
+impl From<CliOmittedAssetHandling> for OmittedAssetHandling {
+    fn from(value: CliOmittedAssetHandling) -> Self {
+        match value {
+            CliOmittedAssetHandling::Online => OmittedAssetHandling::Online,
+            CliOmittedAssetHandling::Placeholder => OmittedAssetHandling::Placeholder,
+        }
+    }
+}
+
 impl From<CliDownloadSequence> for DownloadSequence {
     fn from(value: CliDownloadSequence) -> Self {
         match value {
@@ -249,34 +669,169 @@ impl From<CliUserscripts> for Userscripts {
 
 impl TryFrom<CliArgs> for Args {
     type Error = clap::Error;
-    fn try_from(value: CliArgs) -> Result<Self, Self::Error> {
+    fn try_from(mut value: CliArgs) -> Result<Self, Self::Error> {
+        let mut case_overrides = HashMap::new();
+        if let Some(path) = &value.input_file {
+            for entry in read_case_list(path)? {
+                value.cases.push((entry.id, entry.host));
+                case_overrides.insert(entry.id, entry.overrides);
+            }
+        }
         let (base_url, base_url_warning) =
             Args::resolve_base_url(value.base_url.as_deref(), &value.cases)
                 .map_err(|e| CliArgs::command().error(ErrorKind::ValueValidation, e))?;
-        let warnings: Vec<String> = base_url_warning.into_iter().collect();
+        let (case_hosts, case_hosts_warning) =
+            Args::resolve_case_hosts(value.base_url.as_deref(), &value.cases, &base_url)
+                .map_err(|e| CliArgs::command().error(ErrorKind::ValueValidation, e))?;
+        let warnings: Vec<String> = base_url_warning
+            .into_iter()
+            .chain(case_hosts_warning)
+            .collect();
 
         Ok(Args {
             cases: value.cases.into_iter().map(|(id, _)| id).collect(),
+            case_files: value.case_files,
+            case_overrides,
+            author: value.author,
+            author_filter: AuthorFilter {
+                language: value.author_language,
+                edited_since: value.edited_since,
+            },
+            author_cases_url: value.author_cases_url,
+            revision: value.revision,
+            list_revisions: value.list_revisions,
+            library_command: value.command.as_ref().and_then(|x| match x {
+                CliCommand::Library { command, .. } => Some(command.clone().into()),
+                CliCommand::ServeApi { .. } | CliCommand::Play { .. } => None,
+            }),
+            revisions_url: value.revisions_url,
+            library_dir: match &value.command {
+                Some(CliCommand::Library { dir, .. }) => Some(dir.clone()),
+                Some(CliCommand::ServeApi { .. } | CliCommand::Play { .. }) | None => None,
+            },
             output: value.output,
             player_version: value.player_version,
+            player_cache_dir: if value.no_cache {
+                None
+            } else {
+                value.cache_dir.or_else(fs::default_cache_dir)
+            },
+            player_source: value.player_source,
+            player_code_url: value.player_code_url,
+            player_from_instance: value.player_from_instance,
             language: value.language,
             continue_on_asset_error: value.continue_on_asset_error,
             replace_existing: value.replace_existing,
+            create_library: value.create_library,
+            skip_unchanged: false,
+            isolated: false,
             sequence: value.sequence.into(),
+            sequence_selection: value
+                .sequence_range
+                .map(SequenceSelection::Range)
+                .or_else(|| {
+                    Some(SequenceSelection::Entries(
+                        value.sequence_entries.iter().map(|x| x.0).collect(),
+                    ))
+                    .filter(|_| !value.sequence_entries.is_empty())
+                }),
             one_html_file: value.one_html_file,
+            compress_assets: value.compress_assets,
+            sidecar_threshold: value.sidecar_threshold,
+            bundle_cases: value.bundle_cases,
             with_userscripts: value.with_userscripts.into_iter().map(Into::into).collect(),
+            userscripts_url: value
+                .userscripts_url
+                .map(|x| x.parse())
+                .transpose()
+                .map_err(|e| {
+                    CliArgs::command().error(
+                        ErrorKind::ValueValidation,
+                        format!("Invalid userscripts URL: {e}"),
+                    )
+                })?,
             concurrent_downloads: value.concurrent_downloads,
             retries: value.retries,
             connect_timeout: value.connect_timeout,
             read_timeout: value.read_timeout,
             http_handling: value.http_handling.into(),
             disable_html5_audio: value.disable_html5_audio,
+            embed_short_audio: value.embed_short_audio,
+            voice_format: value.voice_format.into(),
+            web_app: value.web_app,
+            omit_assets: value.omit_assets.into_iter().map(Into::into).collect(),
+            omitted_asset_handling: value.omitted_asset_handling.into(),
             disable_photobucket_fix: value.disable_photobucket_fix,
             proxy: value.proxy,
             log_level: value.verbose.log_level_filter(),
             sequence_error_handling: value.sequence_error_handling.into(),
             base_url,
+            case_hosts,
+            mirrors: value
+                .mirrors
+                .iter()
+                .map(|x| x.parse())
+                .collect::<Result<_, _>>()
+                .map_err(|e| {
+                    CliArgs::command().error(
+                        ErrorKind::ValueValidation,
+                        format!("Invalid mirror URL: {e}"),
+                    )
+                })?,
+            authentication: match (value.cookies, value.login) {
+                (Some(path), _) => Some(Authentication::CookieFile(path)),
+                (None, Some(username)) => Some(Authentication::Login {
+                    password: login_password(&username)?,
+                    username,
+                }),
+                (None, None) => None,
+            },
             warnings,
         })
     }
 }
+
+/// Reads the list of cases from the file at the given [path] (or from stdin, if it is `-`).
+fn read_case_list(path: &Path) -> Result<Vec<CaseEntry>, clap::Error> {
+    let content = if path == Path::new("-") {
+        std::io::read_to_string(std::io::stdin())
+    } else {
+        std::fs::read_to_string(path)
+    }
+    .map_err(|e| {
+        CliArgs::command().error(
+            ErrorKind::Io,
+            format!("Could not read case list {}: {e}", path.display()),
+        )
+    })?;
+    let cases = Args::accept_case_list(&content).map_err(|e| {
+        CliArgs::command().error(
+            ErrorKind::ValueValidation,
+            format!("Invalid case list {}: {e}", path.display()),
+        )
+    })?;
+    if cases.is_empty() {
+        return Err(CliArgs::command().error(
+            ErrorKind::ValueValidation,
+            format!("Case list {} does not contain any cases.", path.display()),
+        ));
+    }
+    Ok(cases)
+}
+
+/// Returns the password for logging in as the given [username], either from the environment or
+/// by asking for it.
+fn login_password(username: &str) -> Result<String, clap::Error> {
+    if let Ok(password) = std::env::var("AAOFFLINE_PASSWORD") {
+        return Ok(password);
+    }
+    dialoguer::Password::new()
+        .with_prompt(format!("Password for {username}"))
+        .interact()
+        .map_err(|e| {
+            CliArgs::command().error(
+                ErrorKind::MissingRequiredArgument,
+                format!("Could not read password (you can also set AAOFFLINE_PASSWORD): {e}"),
+            )
+        })
+}
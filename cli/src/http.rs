@@ -0,0 +1,340 @@
+//! A minimal implementation of HTTP/1.1, which is all that our local servers need.
+//!
+//! Each connection handles a single request and is closed afterwards. Connections that stall
+//! (or take too long to send their request) are dropped, so that slow clients can't tie up the
+//! server.
+
+use std::fmt::Display;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context as TaskContext, Poll};
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+use tokio::io::{
+    AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, ReadBuf,
+};
+use tokio::time::{Instant, Sleep};
+
+/// The maximum size of the request line and headers of a request.
+const MAX_HEAD_SIZE: u64 = 64 * 1024;
+
+/// The maximum size of the body of a request.
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// The maximum number of connections that are handled at the same time.
+///
+/// Further connections wait in the backlog of the listener until one of them is closed.
+pub(crate) const MAX_CONNECTIONS: usize = 64;
+
+/// The time within which a client has to send its whole request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The time after which a connection that neither sends nor receives any data is dropped.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A request made to the API.
+#[derive(Debug)]
+pub(crate) struct Request {
+    /// The method of the request (e.g., `GET`).
+    pub(crate) method: String,
+    /// The path of the request, without its query.
+    pub(crate) path: String,
+    /// The headers of the request, with lowercase names.
+    headers: Vec<(String, String)>,
+    /// The body of the request.
+    pub(crate) body: Vec<u8>,
+}
+
+/// A response to a request made to the API.
+#[derive(Debug)]
+pub(crate) struct Response {
+    /// The status code of the response.
+    status: u16,
+    /// The content type of the body of the response.
+    content_type: &'static str,
+    /// The body of the response.
+    body: Vec<u8>,
+}
+
+/// A connection whose reads and writes fail once they made no progress for [IDLE_TIMEOUT].
+pub(crate) struct Connection<S> {
+    /// The underlying stream.
+    stream: S,
+    /// The timer that runs out once the connection was idle for too long.
+    idle: Pin<Box<Sleep>>,
+    /// Whether the last read or write had to wait (i.e., whether [idle] is running).
+    waiting: bool,
+}
+
+impl<S> Connection<S> {
+    /// Wraps the given [stream].
+    pub(crate) fn new(stream: S) -> Connection<S> {
+        Connection {
+            stream,
+            idle: Box::pin(tokio::time::sleep(IDLE_TIMEOUT)),
+            waiting: false,
+        }
+    }
+
+    /// Returns the given [poll] of the underlying stream, or an error if the connection has been
+    /// waiting on it for too long.
+    fn check_idle<T>(
+        &mut self,
+        cx: &mut TaskContext<'_>,
+        poll: Poll<io::Result<T>>,
+    ) -> Poll<io::Result<T>> {
+        if poll.is_ready() {
+            self.waiting = false;
+            return poll;
+        }
+        if !self.waiting {
+            // Time spent on our side between reads or writes doesn't count.
+            self.waiting = true;
+            self.idle.as_mut().reset(Instant::now() + IDLE_TIMEOUT);
+        }
+        match self.idle.as_mut().poll(cx) {
+            Poll::Ready(()) => Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "Connection was idle for too long.",
+            ))),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Connection<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.stream).poll_read(cx, buf);
+        this.check_idle(cx, poll)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Connection<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.stream).poll_write(cx, buf);
+        this.check_idle(cx, poll)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.stream).poll_flush(cx);
+        this.check_idle(cx, poll)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.stream).poll_shutdown(cx);
+        this.check_idle(cx, poll)
+    }
+}
+
+impl Request {
+    /// Reads a request from the given [reader], failing if it isn't complete within
+    /// [REQUEST_TIMEOUT].
+    pub(crate) async fn read(reader: &mut BufReader<impl AsyncRead + Unpin>) -> Result<Request> {
+        tokio::time::timeout(REQUEST_TIMEOUT, Self::read_untimed(reader))
+            .await
+            .context("Request was not received in time.")?
+    }
+
+    /// Reads a request from the given [reader], however long that takes.
+    async fn read_untimed(reader: &mut BufReader<impl AsyncRead + Unpin>) -> Result<Request> {
+        let mut head = reader.take(MAX_HEAD_SIZE);
+        let mut line = String::new();
+        head.read_line(&mut line).await?;
+        let mut parts = line.split_whitespace();
+        let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+            bail!("Invalid request line: {}", line.trim());
+        };
+        let method = method.to_string();
+        let path = target.split('?').next().unwrap_or_default().to_string();
+
+        let mut headers = vec![];
+        loop {
+            line.clear();
+            if head.read_line(&mut line).await? == 0 {
+                bail!("Request ended before its headers were complete.");
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            } else if let Some((name, value)) = line.split_once(':') {
+                headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+            }
+        }
+        let mut request = Request {
+            method,
+            path,
+            headers,
+            body: vec![],
+        };
+        let length: usize = request
+            .header("content-length")
+            .map(str::parse)
+            .transpose()
+            .context("Invalid Content-Length")?
+            .unwrap_or_default();
+        if length > MAX_BODY_SIZE {
+            bail!("Request body is too large ({length} bytes).");
+        }
+        request.body = vec![0; length];
+        reader.read_exact(&mut request.body).await?;
+        Ok(request)
+    }
+
+    /// Whether this request was made to one of the given [hosts] (in the form `host:port`).
+    ///
+    /// Checking this prevents DNS rebinding attacks, where a web page makes the browser send
+    /// requests to our server under the page's own (attacker-controlled) host name.
+    pub(crate) fn is_for_host(&self, hosts: &[String]) -> bool {
+        self.header("host")
+            .is_some_and(|x| hosts.iter().any(|y| y.eq_ignore_ascii_case(x)))
+    }
+
+    /// Returns the value of the header with the given (lowercase) [name], if it was sent.
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(x, _)| x == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl Response {
+    /// Creates a response with the given [status] and the given [value] as its JSON body.
+    pub(crate) fn json(status: u16, value: &impl Serialize) -> Response {
+        Response {
+            status,
+            content_type: "application/json",
+            body: serde_json::to_vec_pretty(value).expect("value must be serializable"),
+        }
+    }
+
+    /// Creates a response with the given [status] and the given [text] as its body.
+    pub(crate) fn text(status: u16, text: impl Display) -> Response {
+        Response {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: text.to_string().into_bytes(),
+        }
+    }
+
+    /// Creates an error response with the given [status] and [message].
+    pub(crate) fn error(status: u16, message: impl Display) -> Response {
+        Self::json(status, &serde_json::json!({ "error": message.to_string() }))
+    }
+
+    /// Writes this response to the given [writer].
+    pub(crate) async fn write(self, writer: &mut (impl AsyncWrite + Unpin)) -> Result<()> {
+        write_head(
+            writer,
+            self.status,
+            self.content_type,
+            self.body.len() as u64,
+            &[],
+        )
+        .await?;
+        writer.write_all(&self.body).await?;
+        Ok(writer.flush().await?)
+    }
+}
+
+/// Writes the status line and headers of a response with the given [status], [content_type],
+/// [length], and [extra_headers] to the given [writer].
+pub(crate) async fn write_head(
+    writer: &mut (impl AsyncWrite + Unpin),
+    status: u16,
+    content_type: &str,
+    length: u64,
+    extra_headers: &[(&str, &str)],
+) -> Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {status} {}\r\nContent-Type: {content_type}\r\nContent-Length: {length}\r\nConnection: close\r\n",
+        reason(status)
+    );
+    for (name, value) in extra_headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+    Ok(writer.write_all(head.as_bytes()).await?)
+}
+
+/// Returns the reason phrase for the given [status] code.
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        206 => "Partial Content",
+        301 => "Moved Permanently",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        415 => "Unsupported Media Type",
+        416 => "Range Not Satisfiable",
+        _ => "Internal Server Error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::duplex;
+
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn slow_requests_time_out() {
+        let (mut client, server) = duplex(1024);
+        tokio::spawn(async move {
+            let mut sent: &[u8] = b"GET / HTTP/1.1\r\n";
+            while client.write_all(sent).await.is_ok() {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                sent = b"X";
+            }
+        });
+        let mut reader = BufReader::new(Connection::new(server));
+        let error = Request::read(&mut reader).await.unwrap_err();
+        assert_eq!(error.to_string(), "Request was not received in time.");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stalled_writes_time_out() {
+        let (_client, server) = duplex(16);
+        let mut connection = Connection::new(server);
+        let error = connection.write_all(&[0; 1024]).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn time_between_operations_does_not_count() {
+        let (mut client, server) = duplex(1024);
+        let mut reader = BufReader::new(Connection::new(server));
+        client
+            .write_all(b"POST /jobs HTTP/1.1\r\nContent-Length: 2\r\n\r\n{}")
+            .await
+            .unwrap();
+        let request = Request::read(&mut reader).await.unwrap();
+        assert_eq!(request.body, b"{}");
+        // Responding may take a while (e.g., while a job is being submitted).
+        tokio::time::sleep(IDLE_TIMEOUT * 2).await;
+        Response::text(200, "Done.")
+            .write(reader.get_mut())
+            .await
+            .unwrap();
+    }
+}
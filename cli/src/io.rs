@@ -23,6 +23,24 @@ impl InteractiveDialog for CliInteraction {
             .interact_opt()
             .unwrap_or(Some(default_value))
     }
+
+    fn select(&mut self, prompt: &str, items: &[String], defaults: &[bool]) -> Option<Vec<usize>> {
+        dialoguer::MultiSelect::new()
+            .with_prompt(prompt)
+            .items(items)
+            .defaults(defaults)
+            .interact_opt()
+            .unwrap_or_else(|_| {
+                Some(
+                    defaults
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, x)| **x)
+                        .map(|(i, _)| i)
+                        .collect(),
+                )
+            })
+    }
 }
 
 impl CliProgressBar {
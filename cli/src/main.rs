@@ -1,8 +1,10 @@
 use aaoffline::MainContext;
 use aaoffline::args::{Args, Userscripts};
+use aaoffline::daemon::Daemon;
 use aaoffline::fs::TokioFsWriter;
 use anyhow::Result;
-use args::CliArgs;
+use api::ApiConfig;
+use args::{CliArgs, CliCommand};
 use clap::error::ErrorKind;
 use clap::{CommandFactory, Parser};
 use human_panic::setup_panic;
@@ -10,13 +12,21 @@ use human_panic::setup_panic;
 use io::{CliInteraction, CliProgressBar};
 use log::error;
 
+mod api;
 mod args;
+mod http;
 mod io;
+mod play;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     setup_panic!();
-    let args: Args = CliArgs::parse().try_into()?;
+    let cli_args = CliArgs::parse();
+    let command = cli_args.command.clone();
+    let base_url = cli_args.base_url.clone();
+    let watch = cli_args.watch.filter(|_| cli_args.command.is_none());
+    let status_file = cli_args.status_file.clone();
+    let args: Args = cli_args.try_into()?;
     Userscripts::validate_combination(&args.with_userscripts)
         .map_err(|x| CliArgs::command().error(ErrorKind::ArgumentConflict, x))?;
     env_logger::builder()
@@ -25,9 +35,49 @@ async fn main() -> Result<()> {
         .filter_level(args.log_level)
         .init();
 
-    let writer = Box::new(TokioFsWriter);
-    let pb = Box::new(CliProgressBar::new());
-    let interact = Box::new(CliInteraction);
-    let mut ctx = MainContext::new(args, writer, interact, pb);
-    ctx.run_all_steps().await.inspect_err(|e| error!("{e}"))
+    let new_context = |args| {
+        let writer = Box::new(TokioFsWriter);
+        let pb = Box::new(CliProgressBar::new());
+        let interact = Box::new(CliInteraction);
+        MainContext::new(args, writer, interact, pb)
+    };
+    if let Some(CliCommand::Play {
+        path,
+        port,
+        no_browser,
+    }) = &command
+    {
+        return play::play(path, *port, !no_browser)
+            .await
+            .inspect_err(|e| error!("{e}"));
+    }
+    if let Some(CliCommand::ServeApi {
+        address,
+        max_jobs,
+        dir,
+    }) = command
+    {
+        let config = ApiConfig {
+            address,
+            max_jobs,
+            dir,
+            base_url,
+            token: std::env::var("AAOFFLINE_API_TOKEN")
+                .ok()
+                .filter(|x| !x.is_empty()),
+        };
+        return api::serve(config, args)
+            .await
+            .inspect_err(|e| error!("{e}"));
+    }
+    if let Some(interval) = watch {
+        return Daemon::new(args, interval, status_file)
+            .run(new_context)
+            .await
+            .inspect_err(|e| error!("{e}"));
+    }
+    new_context(args)
+        .run_all_steps()
+        .await
+        .inspect_err(|e| error!("{e}"))
 }
@@ -0,0 +1,300 @@
+//! Contains a local web server for playing downloaded cases.
+//!
+//! Browsers restrict what pages opened from local files may do (which, e.g., breaks HTML5 audio
+//! in Firefox), so opening cases through a web server avoids a lot of trouble.
+
+use std::io::SeekFrom;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use aaoffline::{LIBRARY_FILE, sidecar_dir};
+use anyhow::{Context, Result, ensure};
+use log::{debug, info, warn};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+
+use crate::http::{self, Connection, Request, Response};
+
+/// A range of bytes requested from a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ByteRange {
+    /// The whole file (also used for ranges we don't support).
+    Whole,
+    /// The bytes from the first to the second position (inclusive).
+    Part(u64, u64),
+    /// A range that lies outside of the file.
+    Unsatisfiable,
+}
+
+/// The files that are served.
+#[derive(Debug)]
+struct Served {
+    /// The directory that the served files are in.
+    root: PathBuf,
+    /// The only files and directories within the [root] that may be served, if not all of them.
+    allowed: Option<Vec<PathBuf>>,
+    /// The hosts (in the form `host:port`) under which we may be accessed.
+    hosts: Vec<String>,
+}
+
+/// Serves the case (or library) at the given [path] on the given [port] of localhost until
+/// stopped, opening it in the default browser if [open_browser] is set.
+///
+/// A port of 0 picks any free port.
+pub(crate) async fn play(path: &Path, port: u16, open_browser: bool) -> Result<()> {
+    let path = std::path::absolute(path)
+        .with_context(|| format!("Could not resolve {}", path.display()))?;
+    let (root, page, allowed) = serve_location(&path)?;
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+        .await
+        .with_context(|| format!("Could not listen on port {port}"))?;
+    let port = listener.local_addr()?.port();
+    let url = format!("http://{}:{port}/{page}", Ipv4Addr::LOCALHOST);
+    info!(
+        "Serving {} at {url} (press Ctrl+C to stop).",
+        allowed
+            .as_ref()
+            .and_then(|x| x.first())
+            .unwrap_or(&root)
+            .display()
+    );
+    if open_browser && let Err(e) = webbrowser::open(&url) {
+        warn!("Could not open browser ({e}), please open {url} yourself.");
+    }
+    let root = Arc::new(Served {
+        root,
+        allowed,
+        hosts: vec![
+            format!("{}:{port}", Ipv4Addr::LOCALHOST),
+            format!("localhost:{port}"),
+        ],
+    });
+    let connections = Arc::new(Semaphore::new(http::MAX_CONNECTIONS));
+    loop {
+        let permit = Arc::clone(&connections)
+            .acquire_owned()
+            .await
+            .expect("semaphore is never closed");
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                warn!("Could not accept connection: {e}");
+                continue;
+            }
+        };
+        let root = Arc::clone(&root);
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, &root).await {
+                // This commonly happens when the browser stops loading some audio early.
+                debug!("Could not handle request: {e:#}");
+            }
+            drop(permit);
+        });
+    }
+}
+
+/// Returns the directory to serve for the given [path], along with the (URL-encoded) path of
+/// the page to open within it and the only files within the directory that may be served.
+///
+/// A directory (i.e., a case directory or a library) is served as a whole. Of a single HTML
+/// file, only the file itself and its sidecar directory are served, since other files next to
+/// it (e.g., in the home directory) are none of the browser's business.
+fn serve_location(path: &Path) -> Result<(PathBuf, String, Option<Vec<PathBuf>>)> {
+    if path.is_dir() {
+        ensure!(
+            path.join("index.html").is_file() || path.join(LIBRARY_FILE).is_file(),
+            "{} is neither a downloaded case nor a library.",
+            path.display()
+        );
+        return Ok((path.to_path_buf(), String::new(), None));
+    }
+    ensure!(
+        path.is_file(),
+        "{} is neither a downloaded case nor a library.",
+        path.display()
+    );
+    let root = path.parent().expect("file must have a parent");
+    if path.file_name().is_some_and(|x| x == "index.html") {
+        // This is the player of a case directory.
+        return Ok((root.to_path_buf(), String::new(), None));
+    }
+    let page = path.file_name().expect("file must have a name");
+    Ok((
+        root.to_path_buf(),
+        urlencoding::encode(&page.to_string_lossy()).into_owned(),
+        Some(vec![path.to_path_buf(), sidecar_dir(path)]),
+    ))
+}
+
+/// Handles the request on the given [stream], serving files from the given [served] location.
+async fn handle_connection(stream: TcpStream, served: &Served) -> Result<()> {
+    let mut stream = BufReader::new(Connection::new(stream));
+    let request = match Request::read(&mut stream).await {
+        Ok(request) => request,
+        Err(e) => {
+            return Response::text(400, format!("{e:#}"))
+                .write(stream.get_mut())
+                .await;
+        }
+    };
+    let stream = stream.get_mut();
+    if !request.is_for_host(&served.hosts) {
+        return Response::text(403, "Forbidden.").write(stream).await;
+    }
+    let head_only = match request.method.as_str() {
+        "GET" => false,
+        "HEAD" => true,
+        _ => {
+            return Response::text(405, "Method not allowed.")
+                .write(stream)
+                .await;
+        }
+    };
+    let Some(mut path) = resolve(&served.root, &request.path).filter(|x| {
+        served
+            .allowed
+            .as_ref()
+            .is_none_or(|allowed| allowed.iter().any(|y| x.starts_with(y)))
+    }) else {
+        return Response::text(404, "Not found.").write(stream).await;
+    };
+    if path.is_dir() {
+        if !request.path.ends_with('/') {
+            // Relative links within the page only work if the URL ends with a slash.
+            let location = format!("{}/", request.path);
+            http::write_head(stream, 301, "text/plain", 0, &[("Location", &location)]).await?;
+            return Ok(());
+        }
+        path.push("index.html");
+    }
+    let Ok(mut file) = File::open(&path).await else {
+        return Response::text(404, "Not found.").write(stream).await;
+    };
+    let size = file.metadata().await?.len();
+    let mime = mime_guess::from_path(&path).first_or_octet_stream();
+    // Our pages and scripts are always encoded in UTF-8.
+    let content_type = match (mime.type_().as_str(), mime.subtype().as_str()) {
+        ("text", _) | (_, "javascript" | "json") => format!("{mime}; charset=utf-8"),
+        _ => mime.essence_str().to_string(),
+    };
+    let range = request
+        .header("range")
+        .map_or(ByteRange::Whole, |x| parse_range(x, size));
+    let (status, start, length, content_range) = match range {
+        ByteRange::Whole => (200, 0, size, None),
+        ByteRange::Part(start, end) => (
+            206,
+            start,
+            end - start + 1,
+            Some(format!("bytes {start}-{end}/{size}")),
+        ),
+        ByteRange::Unsatisfiable => {
+            let content_range = format!("bytes */{size}");
+            http::write_head(
+                stream,
+                416,
+                "text/plain",
+                0,
+                &[("Content-Range", &content_range)],
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    let mut headers = vec![("Accept-Ranges", "bytes")];
+    if let Some(content_range) = &content_range {
+        headers.push(("Content-Range", content_range));
+    }
+    http::write_head(stream, status, &content_type, length, &headers).await?;
+    if !head_only {
+        file.seek(SeekFrom::Start(start)).await?;
+        tokio::io::copy(&mut file.take(length), stream).await?;
+    }
+    Ok(())
+}
+
+/// Returns the file or directory within the given [root] that the given URL [path] refers to.
+///
+/// Returns `None` for paths that would lead outside of the root.
+fn resolve(root: &Path, path: &str) -> Option<PathBuf> {
+    let path = urlencoding::decode(path).ok()?;
+    let mut result = root.to_path_buf();
+    for segment in path.split('/').filter(|x| !x.is_empty() && *x != ".") {
+        if segment == ".." || segment.contains(['\\', ':']) {
+            return None;
+        }
+        result.push(segment);
+    }
+    Some(result)
+}
+
+/// Parses the given value of a `Range` header for a file of the given [size].
+///
+/// Only single ranges are supported, since browsers don't request more for media.
+fn parse_range(value: &str, size: u64) -> ByteRange {
+    let Some((start, end)) = value
+        .trim()
+        .strip_prefix("bytes=")
+        .filter(|x| !x.contains(','))
+        .and_then(|x| x.split_once('-'))
+    else {
+        return ByteRange::Whole;
+    };
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", "") => return ByteRange::Whole,
+        // The last bytes of the file.
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => return ByteRange::Unsatisfiable,
+            Ok(length) => (size.saturating_sub(length), size.saturating_sub(1)),
+            Err(_) => return ByteRange::Whole,
+        },
+        (start, end) => {
+            let Ok(start) = start.parse::<u64>() else {
+                return ByteRange::Whole;
+            };
+            let end = if end.is_empty() {
+                Ok(u64::MAX)
+            } else {
+                end.parse::<u64>()
+            };
+            match end {
+                Ok(end) if end >= start => (start, end.min(size.saturating_sub(1))),
+                _ => return ByteRange::Whole,
+            }
+        }
+    };
+    if size == 0 || start >= size {
+        ByteRange::Unsatisfiable
+    } else {
+        ByteRange::Part(start, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("bytes=0-99", 1000, ByteRange::Part(0, 99))]
+    #[case("bytes=500-", 1000, ByteRange::Part(500, 999))]
+    #[case("bytes=-100", 1000, ByteRange::Part(900, 999))]
+    #[case("bytes=-2000", 1000, ByteRange::Part(0, 999))]
+    #[case("bytes=900-5000", 1000, ByteRange::Part(900, 999))]
+    #[case(" bytes=1-1 ", 2, ByteRange::Part(1, 1))]
+    #[case("bytes=1000-", 1000, ByteRange::Unsatisfiable)]
+    #[case("bytes=-0", 1000, ByteRange::Unsatisfiable)]
+    #[case("bytes=0-", 0, ByteRange::Unsatisfiable)]
+    #[case("bytes=-", 1000, ByteRange::Whole)]
+    #[case("bytes=99-0", 1000, ByteRange::Whole)]
+    #[case("bytes=0-1,5-6", 1000, ByteRange::Whole)]
+    #[case("items=0-1", 1000, ByteRange::Whole)]
+    #[case("bytes=a-b", 1000, ByteRange::Whole)]
+    fn test_parse_range(#[case] value: &str, #[case] size: u64, #[case] expected: ByteRange) {
+        assert_eq!(parse_range(value, size), expected);
+    }
+}
@@ -1,12 +1,17 @@
 //! Contains data structures and methods related to the arguments passed to aaoffline.
 
 use anyhow::Result;
+use chrono::NaiveDate;
 use itertools::Itertools;
 use log::LevelFilter;
 use reqwest::Url;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Display;
+use std::ops::RangeInclusive;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use crate::constants::re::{self, AAONLINE_MAIN_HOST};
 
@@ -17,6 +22,61 @@ pub struct Args {
     /// The IDs of the cases that shall be downloaded.
     pub cases: Vec<u32>,
 
+    /// Local trial files that shall be imported as cases, in addition to the [`Args::cases`].
+    ///
+    /// Each file is either the output of AAO's `trial.js.php` for the case, or a JSON object
+    /// containing its `trial_information` and `initial_trial_data`.
+    pub case_files: Vec<PathBuf>,
+
+    /// Settings that apply only to single cases, keyed by case ID.
+    pub case_overrides: HashMap<u32, CaseOverrides>,
+
+    /// The ID of an author whose public cases shall all be downloaded, in addition to
+    /// [`Args::cases`].
+    ///
+    /// The cases are put into a directory for the author, with a subdirectory for each sequence.
+    pub author: Option<u32>,
+
+    /// Filters that the cases of the [`Args::author`] need to match to be downloaded.
+    pub author_filter: AuthorFilter,
+
+    /// The URL at which the cases of an author are listed, for AAO instances that list them
+    /// elsewhere.
+    ///
+    /// Any `{author}` in this URL will be replaced by the ID of the author. Relative URLs are
+    /// resolved against the [`Args::base_url`]. If this is `None`, the trial search of the AAO
+    /// instance is used, of which only the first page of results is read.
+    ///
+    /// If the URL contains `{page}`, it is replaced by the number of the page (starting at 1),
+    /// and the following pages are read until one doesn't list any new cases.
+    pub author_cases_url: Option<String>,
+
+    /// The backed-up revision of the case that shall be downloaded instead of its current version.
+    ///
+    /// This is either the name of a revision or a date in the form `YYYY-MM-DD`, in which case the
+    /// latest revision backed up on or before that day is used. Only a single case may be given
+    /// in [`Args::cases`] if this is set.
+    pub revision: Option<String>,
+
+    /// Whether to only list the available revisions of the case instead of downloading it.
+    pub list_revisions: bool,
+
+    /// A command to run on the library at [`Args::library_dir`] instead of downloading cases.
+    pub library_command: Option<LibraryCommand>,
+
+    /// The root directory of the library that the [`Args::library_command`] is run on.
+    ///
+    /// If this is `None`, the current directory is used.
+    pub library_dir: Option<PathBuf>,
+
+    /// The URL at which the revisions of a case are listed, for AAO instances that make their
+    /// backups available elsewhere.
+    ///
+    /// Any `{id}` in this URL will be replaced by the ID of the case. Relative URLs are resolved
+    /// against the [`Args::base_url`]. If this is `None`, the backups directory configured on
+    /// the AAO instance is used.
+    pub revisions_url: Option<String>,
+
     /// The output directory (or filename, if `-1` was used) for the case.
     ///
     /// If this is not passed, will use the title + ID of the case.
@@ -27,6 +87,33 @@ pub struct Args {
     /// The branch or commit name of Ace Attorney Online that shall be used for the player.
     pub player_version: String,
 
+    /// The directory in which the code of the player is cached between runs.
+    ///
+    /// The cache is keyed by the AAO commit the player version resolves to, so cached files are
+    /// reused as long as the player version doesn't change. If this is `None`, no cache is used.
+    pub player_cache_dir: Option<PathBuf>,
+
+    /// A local copy of the AAO repository to use as the source for the player's code.
+    ///
+    /// If this is a git checkout, files are read from the commit that the player version points
+    /// to within it. Otherwise, the files in the directory are used as they are.
+    /// If this is `None`, the player's code is downloaded from the AAO repository on Bitbucket.
+    pub player_source: Option<PathBuf>,
+
+    /// The URL under which the raw files of the AAO repository can be found, for use with
+    /// self-hosted AAO instances whose code differs from the official repository.
+    ///
+    /// Any `{version}` in this URL will be replaced by the player version.
+    /// If this is `None`, the official AAO repository on Bitbucket is used.
+    pub player_code_url: Option<String>,
+
+    /// Whether to retrieve the player's scripts from the AAO instance at [`Args::base_url`]
+    /// instead of from the AAO repository.
+    ///
+    /// PHP templates (such as the player itself) are still retrieved from the repository, since
+    /// the instance only serves their rendered output.
+    pub player_from_instance: bool,
+
     /// The language to download the player in.
     pub language: String,
 
@@ -36,13 +123,60 @@ pub struct Args {
     /// Whether to replace any existing output files.
     pub replace_existing: bool,
 
+    /// Whether to start a library at the root of the output if there is none yet.
+    ///
+    /// A library keeps track of the cases downloaded to it in a registry file (and lists them in
+    /// an index page), so that later downloads can link to the other cases of their sequence.
+    /// Libraries that already exist are always updated.
+    pub create_library: bool,
+
+    /// Whether to skip cases that were downloaded to the library before and haven't been edited
+    /// since, instead of downloading them again.
+    ///
+    /// Skipped cases are still linked to by the other cases of their sequence.
+    pub skip_unchanged: bool,
+
+    /// Whether to ignore the cases that were downloaded next to the output before, i.e., to
+    /// neither register the cases in a library nor link to the cases of their sequence that were
+    /// downloaded by previous runs.
+    pub isolated: bool,
+
     /// Whether to download all trials contained in a sequence (if the given case is part of a
     /// sequence).
     pub sequence: DownloadSequence,
 
+    /// Which cases of a sequence to download (if the given case is part of one).
+    ///
+    /// If set, this takes precedence over [`Args::sequence`].
+    pub sequence_selection: Option<SequenceSelection>,
+
     /// Whether to output only a single HTML file, with the assets embedded as data URLs.
     pub one_html_file: bool,
 
+    /// Whether to compress the assets embedded into the single HTML file.
+    ///
+    /// Instead of data URLs, the assets are then packed into a single compressed blob, which is
+    /// decompressed into blob URLs by a small script when the player needs them. This makes the
+    /// file considerably smaller and faster to load. Only has an effect together with
+    /// [`one_html_file`](Self::one_html_file).
+    pub compress_assets: bool,
+
+    /// The size (in bytes) above which assets are not embedded into the single HTML file, but
+    /// written into a directory next to it (named like the file, with a `_files` suffix).
+    ///
+    /// This keeps the HTML file small enough for browsers when a case uses large media (such as
+    /// music), while the case stays nearly a single file. Only has an effect together with
+    /// [`one_html_file`](Self::one_html_file).
+    pub sidecar_threshold: Option<u64>,
+
+    /// Whether to put all cases (or, when downloading the cases of an author, all cases of each
+    /// sequence) into a single HTML file, with a list to choose a case from.
+    ///
+    /// Assets used by several of the cases are only embedded once, and redirections to the next
+    /// case of a sequence open it from the same file. Only has an effect together with
+    /// [`one_html_file`](Self::one_html_file).
+    pub bundle_cases: bool,
+
     /// Whether to apply any userscripts to the downloaded case. Can be passed multiple times.
     ///
     /// Scripts were created by Time Axis, with only the expanded keyboard controls written by me,
@@ -50,6 +184,12 @@ pub struct Args {
     /// (These options may change in the future when some scripts are consolidated).
     pub with_userscripts: Vec<Userscripts>,
 
+    /// The URL under which the userscripts can be found, if they shall not be retrieved from
+    /// their usual locations.
+    ///
+    /// Userscripts are looked up by their filename (e.g., `aaobacklog.user.js`) within this URL.
+    pub userscripts_url: Option<Url>,
+
     /// How many concurrent downloads to use.
     pub concurrent_downloads: usize,
 
@@ -84,6 +224,39 @@ pub struct Args {
     /// option if you plan to use Firefox.)
     pub disable_html5_audio: bool,
 
+    /// Whether to embed voice blips and short sound effects into the case data as data URLs,
+    /// even when not outputting a single HTML file.
+    ///
+    /// Howler.js can decode embedded sounds into Web Audio buffers without having to access
+    /// other files, which works in every browser when opening the player locally. Music (and any
+    /// other large audio) is still written to separate files.
+    pub embed_short_audio: bool,
+
+    /// The format in which the voice blips shall be downloaded.
+    ///
+    /// The player offers each voice blip in several formats, of which the browser plays the
+    /// first one it supports. Choosing a single format makes the player use it everywhere.
+    pub voice_format: VoiceFormat,
+
+    /// Whether to make the downloaded cases installable as progressive web apps.
+    ///
+    /// This adds a web app manifest, icons (based on the first profile or evidence of the case),
+    /// and a service worker caching all files of the case, so that a case served from a web
+    /// server once can then be played offline (e.g., on phones). Has no effect together with
+    /// [`one_html_file`](Self::one_html_file).
+    pub web_app: bool,
+
+    /// The categories of assets that shall not be downloaded, e.g., to get a lighter copy of a
+    /// case without its music.
+    ///
+    /// How these assets are referenced instead is determined by
+    /// [`omitted_asset_handling`](Self::omitted_asset_handling). The player is marked with the
+    /// omitted categories.
+    pub omit_assets: BTreeSet<AssetCategory>,
+
+    /// What to put in place of the assets omitted via [`omit_assets`](Self::omit_assets).
+    pub omitted_asset_handling: OmittedAssetHandling,
+
     /// Whether to disable the automatic fixing of photobucket watermarks.
     pub disable_photobucket_fix: bool,
 
@@ -97,8 +270,25 @@ pub struct Args {
     /// The base URL to use for Ace Attorney Online.
     ///
     /// This can be useful for testing with a local instance of AAO, for example.
+    /// Cases in [`Args::case_hosts`] are downloaded from their own host instead.
     pub base_url: Url,
 
+    /// The base URLs of the cases that shall be downloaded from another host than
+    /// [`Args::base_url`], keyed by case ID.
+    ///
+    /// Cases in the sequence of such a case are downloaded from the same host.
+    pub case_hosts: HashMap<u32, Url>,
+
+    /// Hosts that are equivalent to [`Args::base_url`] (i.e., mirrors of it), in the order in
+    /// which they shall be tried if a request to the base URL fails.
+    pub mirrors: Vec<Url>,
+
+    /// How to authenticate with Ace Attorney Online, e.g., to download privately shared cases.
+    ///
+    /// The resulting session is only used for requests to the hosts of Ace Attorney Online.
+    /// If this is `None`, all requests are made anonymously.
+    pub authentication: Option<Authentication>,
+
     /// The minimum level messages have to have to be logged.
     pub log_level: LevelFilter,
 
@@ -122,6 +312,164 @@ pub enum HttpHandling {
     RedirectToHttps,
 }
 
+/// A category of case assets that can be omitted from a download.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum AssetCategory {
+    /// Icons and sprites of the profiles, including the default sprites.
+    Profiles,
+    /// Icons of the evidence and the images shown when checking it.
+    Evidence,
+    /// Backgrounds and foreground objects of places.
+    Places,
+    /// Popups, such as "Objection!".
+    Popups,
+    /// Background music.
+    Music,
+    /// Sound effects.
+    Sounds,
+    /// Voice blips.
+    Voices,
+}
+
+impl AssetCategory {
+    /// All asset categories.
+    pub const ALL: [AssetCategory; 7] = [
+        Self::Profiles,
+        Self::Evidence,
+        Self::Places,
+        Self::Popups,
+        Self::Music,
+        Self::Sounds,
+        Self::Voices,
+    ];
+
+    /// Whether the assets in this category are audio files.
+    pub(crate) fn is_audio(self) -> bool {
+        matches!(self, Self::Music | Self::Sounds | Self::Voices)
+    }
+}
+
+impl Display for AssetCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Profiles => "profiles",
+            Self::Evidence => "evidence",
+            Self::Places => "places",
+            Self::Popups => "popups",
+            Self::Music => "music",
+            Self::Sounds => "sounds",
+            Self::Voices => "voices",
+        })
+    }
+}
+
+/// The format in which the voice blips are downloaded.
+#[derive(Debug, Clone, Copy, Serialize, Default, PartialEq, Eq)]
+pub enum VoiceFormat {
+    /// Download every format, so that each browser can pick the one it supports best.
+    #[default]
+    All,
+    /// Opus, which is the smallest, but not supported by some older browsers.
+    Opus,
+    /// WAV, which is supported by every browser, but the largest.
+    Wav,
+    /// MP3, which is supported by every browser.
+    Mp3,
+}
+
+impl VoiceFormat {
+    /// Returns the file extensions of the voice blips in this format, in the order in which the
+    /// player prefers them.
+    pub(crate) fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Self::All => &["opus", "wav", "mp3"],
+            Self::Opus => &["opus"],
+            Self::Wav => &["wav"],
+            Self::Mp3 => &["mp3"],
+        }
+    }
+}
+
+/// What to put in place of omitted assets.
+#[derive(Debug, Clone, Copy, Serialize, Default, PartialEq, Eq)]
+pub enum OmittedAssetHandling {
+    /// Keep referencing the assets at their online location, so that they're still available
+    /// while there is an internet connection.
+    #[default]
+    Online,
+    /// Replace the assets with empty images and silent sounds, so that the case never accesses
+    /// the internet.
+    Placeholder,
+}
+
+/// Settings that apply to a single case only, overriding the ones in [`Args`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CaseOverrides {
+    /// The name of the file or directory the case shall be written to, instead of the title and
+    /// ID of the case.
+    pub output: Option<String>,
+
+    /// The branch or commit name of Ace Attorney Online that shall be used for the player of
+    /// this case, instead of [`Args::player_version`].
+    pub player_version: Option<String>,
+}
+
+/// A case as given in a list of cases, along with its [`CaseOverrides`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseEntry {
+    /// The ID of the case.
+    pub id: u32,
+
+    /// The host of the case, if it was given as a URL (see [`Args::accept_case`]).
+    pub host: Option<String>,
+
+    /// The settings that apply to this case only.
+    pub overrides: CaseOverrides,
+}
+
+/// Filters for the cases of an author.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuthorFilter {
+    /// The language that the cases need to be in (e.g., `en`), if any.
+    pub language: Option<String>,
+
+    /// The day on or after which the cases need to have been last edited, if any.
+    pub edited_since: Option<NaiveDate>,
+}
+
+/// How to authenticate with Ace Attorney Online.
+#[derive(Clone, PartialEq, Eq)]
+pub enum Authentication {
+    /// Use the session cookies from the given cookie file.
+    ///
+    /// The file is either in the Netscape format (as exported by most browser extensions), or
+    /// simply contains the value of a `Cookie` header.
+    CookieFile(PathBuf),
+
+    /// Log in to the forum of Ace Attorney Online with the given credentials.
+    Login {
+        /// The name of the forum user.
+        username: String,
+        /// The password of the forum user.
+        password: String,
+    },
+}
+
+impl std::fmt::Debug for Authentication {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Credentials must never end up in any logs.
+        match self {
+            Self::CookieFile(path) => f.debug_tuple("CookieFile").field(path).finish(),
+            Self::Login { username, .. } => f
+                .debug_struct("Login")
+                .field("username", username)
+                .field("password", &"<redacted>")
+                .finish(),
+        }
+    }
+}
+
 /// Whether to download every case in a sequence if the given case is part of one.
 #[derive(Debug, Clone, Copy, Serialize, Default, PartialEq, Eq)]
 pub enum DownloadSequence {
@@ -134,6 +482,37 @@ pub enum DownloadSequence {
     Ask,
 }
 
+/// A command that manages the library of cases downloaded to a directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LibraryCommand {
+    /// List the cases in the library.
+    List,
+    /// Remove the case with the given ID from the library.
+    Remove(u32),
+    /// Check the cases in the library against their current version on Ace Attorney Online.
+    Status,
+}
+
+/// A selection of the cases in a sequence that shall be downloaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SequenceSelection {
+    /// The cases at the given positions in the sequence (starting at 1).
+    Range(RangeInclusive<usize>),
+    /// The cases with the given IDs.
+    Entries(Vec<u32>),
+}
+
+impl SequenceSelection {
+    /// Returns whether the case with the given [id] at the given [position] (starting at 1) in
+    /// its sequence is selected.
+    pub(crate) fn contains(&self, position: usize, id: u32) -> bool {
+        match self {
+            SequenceSelection::Range(range) => range.contains(&position),
+            SequenceSelection::Entries(ids) => ids.contains(&id),
+        }
+    }
+}
+
 /// Whether to abort the download when a case in a sequence is not found.
 #[derive(Debug, Clone, Copy, Serialize, Default, PartialEq, Eq)]
 pub enum SequenceErrorHandling {
@@ -196,8 +575,27 @@ impl Userscripts {
     }
 
     /// Returns all URLs belonging to the given collection of [scripts].
-    pub(crate) fn all_urls(scripts: &[Self]) -> Vec<&str> {
-        scripts.iter().flat_map(|x| x.urls()).unique().collect()
+    ///
+    /// If a [base] URL is given, the scripts will be retrieved from there instead.
+    pub(crate) fn all_urls(scripts: &[Self], base: Option<&Url>) -> Result<Vec<String>> {
+        scripts
+            .iter()
+            .flat_map(|x| x.urls())
+            .unique()
+            .map(|url| {
+                if let Some(base) = base {
+                    let filename = url.rsplit('/').next().expect("split is never empty");
+                    // Without a trailing slash, the last segment of the base would be replaced.
+                    let mut base = base.clone();
+                    if !base.path().ends_with('/') {
+                        base.set_path(&format!("{}/", base.path()));
+                    }
+                    Ok(base.join(filename)?.to_string())
+                } else {
+                    Ok(url.to_string())
+                }
+            })
+            .collect()
     }
 
     /// Ensures that the given [scripts] are a valid combination.
@@ -246,36 +644,203 @@ impl Args {
         }
     }
 
+    /// Parses the given [line] of a list of cases.
+    ///
+    /// The line starts with the URL or ID of the case (see [`Args::accept_case`]), optionally
+    /// followed by overrides in the form `key=value`, where values containing spaces can be put
+    /// in double quotes. Supported keys are `output` and `version` (see [`CaseOverrides`]).
+    /// Anything after a `#` is a comment. Returns `None` if the line contains no case.
+    pub fn accept_case_line(line: &str) -> Result<Option<CaseEntry>, String> {
+        let tokens = Self::split_case_line(line)?;
+        let Some((case, overrides)) = tokens.split_first() else {
+            return Ok(None);
+        };
+        let (id, host) = Self::accept_case(case)?;
+        let mut result = CaseOverrides::default();
+        for token in overrides {
+            let (key, value) = token
+                .split_once('=')
+                .ok_or_else(|| format!("Expected \"key=value\", but found \"{token}\""))?;
+            if value.is_empty() {
+                return Err(format!("No value given for \"{key}\""));
+            }
+            match key {
+                "output" | "o" => result.output = Some(value.to_string()),
+                "version" | "player-version" | "p" => {
+                    result.player_version = Some(value.to_string());
+                }
+                _ => {
+                    return Err(format!(
+                        "Unknown setting \"{key}\" (expected \"output\" or \"version\")"
+                    ));
+                }
+            }
+        }
+        Ok(Some(CaseEntry {
+            id,
+            host,
+            overrides: result,
+        }))
+    }
+
+    /// Parses the given [content] of a list of cases, with one case per line.
+    ///
+    /// See [`Args::accept_case_line`] for the format of each line.
+    pub fn accept_case_list(content: &str) -> Result<Vec<CaseEntry>, String> {
+        content
+            .lines()
+            .enumerate()
+            .filter_map(|(i, line)| {
+                Self::accept_case_line(line)
+                    .map_err(|e| format!("Line {}: {e}", i + 1))
+                    .transpose()
+            })
+            .collect()
+    }
+
+    /// Splits the given [line] of a list of cases into its whitespace-separated tokens, taking
+    /// double quotes and comments into account.
+    fn split_case_line(line: &str) -> Result<Vec<String>, String> {
+        let mut tokens = vec![];
+        let mut current: Option<String> = None;
+        let mut quoted = false;
+        for c in line.chars() {
+            match c {
+                '"' => {
+                    quoted = !quoted;
+                    current.get_or_insert_default();
+                }
+                '#' if !quoted && current.is_none() => break,
+                c if c.is_whitespace() && !quoted => tokens.extend(current.take()),
+                c => current.get_or_insert_default().push(c),
+            }
+        }
+        if quoted {
+            return Err("Unterminated quote".to_string());
+        }
+        tokens.extend(current);
+        Ok(tokens)
+    }
+
+    /// Parses the given [author] into the ID of the author.
+    ///
+    /// The author is either given by their ID or by the URL to their profile.
+    pub fn accept_author(author: &str) -> Result<u32, String> {
+        if let Ok(id) = author.parse::<u32>() {
+            Ok(id)
+        } else if let Some(captures) = re::AUTHOR_REGEX.captures(author) {
+            captures[1]
+                .parse()
+                .map_err(|_| "Author ID in given URL is not a valid number!".to_string())
+        } else {
+            Err(format!(
+                "Could not parse author ID from input \"{author}\". Please provide a valid profile URL or author ID."
+            ))
+        }
+    }
+
+    /// Parses the given [range] of positions in a sequence, in the form `START-END` (where either
+    /// may be left out) or as a single position.
+    pub fn accept_sequence_range(range: &str) -> Result<RangeInclusive<usize>, String> {
+        let parse = |x: &str, default: usize| {
+            let x = x.trim();
+            if x.is_empty() {
+                Ok(default)
+            } else {
+                x.parse::<usize>()
+                    .ok()
+                    .filter(|x| *x > 0)
+                    .ok_or_else(|| format!("Invalid position \"{x}\" (must be at least 1)"))
+            }
+        };
+        let (start, end) = if let Some((start, end)) = range.split_once('-') {
+            (parse(start, 1)?, parse(end, usize::MAX)?)
+        } else {
+            let position = parse(range, 0)?;
+            (position, position)
+        };
+        if end == 0 {
+            Err("Range must not be empty".to_string())
+        } else if start > end {
+            Err(format!("Invalid range \"{range}\" (start is after end)"))
+        } else {
+            Ok(start..=end)
+        }
+    }
+
+    /// Parses the given [date] in the form `YYYY-MM-DD`.
+    pub fn accept_date(date: &str) -> Result<NaiveDate, String> {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|e| format!("Invalid date \"{date}\" (expected YYYY-MM-DD): {e}"))
+    }
+
+    /// Parses the given [interval], which is a number followed by a unit (`s`, `m`, `h`, or `d`).
+    /// A number without a unit is in minutes.
+    pub fn accept_interval(interval: &str) -> Result<Duration, String> {
+        let interval = interval.trim();
+        let (number, seconds) = match interval.char_indices().last() {
+            Some((i, 's')) => (&interval[..i], 1),
+            Some((i, 'm')) => (&interval[..i], 60),
+            Some((i, 'h')) => (&interval[..i], 60 * 60),
+            Some((i, 'd')) => (&interval[..i], 24 * 60 * 60),
+            _ => (interval, 60),
+        };
+        number
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .filter(|x| *x > 0)
+            .and_then(|x| x.checked_mul(seconds))
+            .map(Duration::from_secs)
+            .ok_or_else(|| {
+                format!("Invalid interval \"{interval}\" (expected, e.g., 30m, 6h, or 1d)")
+            })
+    }
+
+    /// Parses the given [size], which is a number of bytes, optionally followed by a unit (`K`,
+    /// `M`, or `G` for kibibytes, mebibytes, or gibibytes, respectively).
+    pub fn accept_size(size: &str) -> Result<u64, String> {
+        let lower = size.trim().to_ascii_lowercase();
+        // Units may be written as, e.g., `M`, `MB`, or `MiB`.
+        let unit = lower
+            .strip_suffix("ib")
+            .or_else(|| lower.strip_suffix('b'))
+            .unwrap_or(&lower);
+        let (number, factor) = match unit.char_indices().last() {
+            Some((i, 'k')) => (&unit[..i], 1 << 10),
+            Some((i, 'm')) => (&unit[..i], 1 << 20),
+            Some((i, 'g')) => (&unit[..i], 1 << 30),
+            _ => (unit, 1),
+        };
+        number
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .and_then(|x| x.checked_mul(factor))
+            .ok_or_else(|| format!("Invalid size \"{size}\" (expected, e.g., 500K or 2M)"))
+    }
+
     /// Resolves the base URL from the given [explicit_base_url] override and the [cases].
     ///
     /// If an explicit base URL is given, it takes priority but a warning is returned if it
-    /// differs from the URL inferred from the case arguments. If no explicit URL is given,
-    /// the URL is inferred from the cases (or defaults to aaonline.fr).
+    /// differs from the default URL while none of the cases specify a host (cases that do are
+    /// warned about by [`Args::resolve_case_hosts`]). If no explicit URL is given, the URL is
+    /// inferred from the first case with a host (or defaults to aaonline.fr).
     ///
     /// Returns the resolved URL and an optional warning message for the caller to display.
     pub fn resolve_base_url(
         explicit_base_url: Option<&str>,
         cases: &[(u32, Option<String>)],
     ) -> Result<(Url, Option<String>), String> {
-        let inferred_base = if let Some(first_host) = cases.iter().find_map(|x| x.1.as_ref()) {
-            if let Some(other_host) = cases
-                .iter()
-                .find_map(|(_, host)| host.as_ref().filter(|x| *x != first_host))
-            {
-                return Err(format!(
-                    "All cases must be from the same host, but both \"{other_host}\" and \"{first_host}\" are present"
-                ));
-            }
-            first_host.as_str()
-        } else {
-            AAONLINE_MAIN_HOST
-        };
-        let inferred = Url::parse(inferred_base)
-            .map_err(|e| format!("Invalid base URL \"{inferred_base}\": {e}"))?;
+        let inferred_base = cases
+            .iter()
+            .find_map(|x| x.1.as_deref())
+            .unwrap_or(AAONLINE_MAIN_HOST);
+        let inferred = Self::parse_host(inferred_base)?;
 
         if let Some(url) = explicit_base_url {
             let parsed = Url::parse(url).map_err(|e| format!("Invalid base URL \"{url}\": {e}"))?;
-            let warning = (parsed != inferred).then(|| {
+            let warning = (parsed != inferred && cases.iter().all(|x| x.1.is_none())).then(|| {
                 format!(
                     "Specified base URL \"{parsed}\" does not match the URL inferred from case arguments (\"{inferred}\"). Using the specified base URL."
                 )
@@ -285,4 +850,252 @@ impl Args {
             Ok((inferred, None))
         }
     }
+
+    /// Resolves the hosts of the given [cases] that differ from the given [base_url].
+    ///
+    /// If an explicit base URL was given, it applies to all cases, so no case will have its own
+    /// host, and a warning is returned for the cases that were given for other hosts. Otherwise,
+    /// each case that was given as a URL is downloaded from the host in its URL.
+    ///
+    /// Returns a mapping from case IDs to their hosts, for all cases whose host differs from
+    /// the [base_url], and an optional warning message for the caller to display.
+    pub fn resolve_case_hosts(
+        explicit_base_url: Option<&str>,
+        cases: &[(u32, Option<String>)],
+        base_url: &Url,
+    ) -> Result<(HashMap<u32, Url>, Option<String>), String> {
+        if explicit_base_url.is_some() {
+            let mut ignored = vec![];
+            for (id, host) in cases {
+                if let Some(host) = host.as_deref()
+                    && Self::parse_host(host)? != *base_url
+                {
+                    ignored.push(format!("{id} ({host})"));
+                }
+            }
+            let warning = (!ignored.is_empty()).then(|| {
+                format!(
+                    "Specified base URL \"{base_url}\" does not match the hosts of cases {}. Using the specified base URL for all cases.",
+                    ignored.join(", ")
+                )
+            });
+            return Ok((HashMap::new(), warning));
+        }
+        let mut hosts: HashMap<u32, Url> = HashMap::new();
+        for (id, host) in cases {
+            let host = host
+                .as_deref()
+                .map_or_else(|| Ok(base_url.clone()), Self::parse_host)?;
+            if hosts.get(id).is_some_and(|x| *x != host) {
+                return Err(format!(
+                    "Case {id} was given for multiple hosts, but can only be downloaded once"
+                ));
+            }
+            hosts.insert(*id, host);
+        }
+        hosts.retain(|_, host| host != base_url);
+        Ok((hosts, None))
+    }
+
+    /// Parses the given [host] (as returned by [`Args::accept_case`]) into a URL.
+    fn parse_host(host: &str) -> Result<Url, String> {
+        Url::parse(host).map_err(|e| format!("Invalid base URL \"{host}\": {e}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn entry(
+        id: u32,
+        host: Option<&str>,
+        output: Option<&str>,
+        version: Option<&str>,
+    ) -> CaseEntry {
+        CaseEntry {
+            id,
+            host: host.map(String::from),
+            overrides: CaseOverrides {
+                output: output.map(String::from),
+                player_version: version.map(String::from),
+            },
+        }
+    }
+
+    #[rstest]
+    #[case("89247", Some(entry(89247, None, None, None)))]
+    #[case("  89247  ", Some(entry(89247, None, None, None)))]
+    #[case(
+        "https://aaonline.fr/player.php?trial_id=99015",
+        Some(entry(99015, Some("https://aaonline.fr/"), None, None))
+    )]
+    #[case("89247 output=case", Some(entry(89247, None, Some("case"), None)))]
+    #[case(
+        r#"89247 o="My case" version=abc123"#,
+        Some(entry(89247, None, Some("My case"), Some("abc123")))
+    )]
+    #[case(
+        r#"89247 output="a # b""#,
+        Some(entry(89247, None, Some("a # b"), None))
+    )]
+    #[case(
+        "89247 p=master # comment",
+        Some(entry(89247, None, None, Some("master")))
+    )]
+    #[case("", None)]
+    #[case("   ", None)]
+    #[case("# 89247", None)]
+    fn accept_case_line(#[case] line: &str, #[case] expected: Option<CaseEntry>) {
+        assert_eq!(Args::accept_case_line(line), Ok(expected));
+    }
+
+    #[rstest]
+    #[case("89247 output")]
+    #[case("89247 output=")]
+    #[case("89247 size=5")]
+    #[case(r#"89247 output="unterminated"#)]
+    #[case("not-a-case")]
+    // A `#` within a token doesn't start a comment.
+    #[case("89247#comment")]
+    fn accept_case_line_invalid(#[case] line: &str) {
+        assert!(Args::accept_case_line(line).is_err());
+    }
+
+    #[test]
+    fn accept_case_list() {
+        let list = "# My cases\n89247 output=first\n\n  99015\n";
+        assert_eq!(
+            Args::accept_case_list(list),
+            Ok(vec![
+                entry(89247, None, Some("first"), None),
+                entry(99015, None, None, None),
+            ])
+        );
+        let error = Args::accept_case_list("89247\n\n89247 size=5").unwrap_err();
+        assert!(error.starts_with("Line 3:"), "unexpected error: {error}");
+    }
+
+    #[rstest]
+    #[case("9-12", Some(9..=12))]
+    #[case(" 9 - 12 ", Some(9..=12))]
+    #[case("9-", Some(9..=usize::MAX))]
+    #[case("-3", Some(1..=3))]
+    #[case("4", Some(4..=4))]
+    #[case("4-4", Some(4..=4))]
+    #[case("0", None)]
+    #[case("0-3", None)]
+    #[case("5-3", None)]
+    #[case("a-b", None)]
+    #[case("", None)]
+    fn accept_sequence_range(#[case] range: &str, #[case] expected: Option<RangeInclusive<usize>>) {
+        assert_eq!(Args::accept_sequence_range(range).ok(), expected);
+    }
+
+    #[rstest]
+    #[case("30s", Some(30))]
+    #[case("30m", Some(30 * 60))]
+    #[case("30", Some(30 * 60))]
+    #[case(" 6h ", Some(6 * 60 * 60))]
+    #[case("1d", Some(24 * 60 * 60))]
+    #[case("0m", None)]
+    #[case("1w", None)]
+    #[case("h", None)]
+    #[case("-1h", None)]
+    #[case("99999999999999999d", None)]
+    fn accept_interval(#[case] interval: &str, #[case] expected: Option<u64>) {
+        assert_eq!(
+            Args::accept_interval(interval).ok(),
+            expected.map(Duration::from_secs)
+        );
+    }
+
+    #[rstest]
+    #[case("500", Some(500))]
+    #[case("500K", Some(500 * 1024))]
+    #[case("2M", Some(2 * 1024 * 1024))]
+    #[case("2mb", Some(2 * 1024 * 1024))]
+    #[case("2 MiB", Some(2 * 1024 * 1024))]
+    #[case("1G", Some(1024 * 1024 * 1024))]
+    #[case("10B", Some(10))]
+    #[case("0", Some(0))]
+    #[case("M", None)]
+    #[case("1.5M", None)]
+    #[case("2T", None)]
+    #[case("99999999999999999999G", None)]
+    fn accept_size(#[case] size: &str, #[case] expected: Option<u64>) {
+        assert_eq!(Args::accept_size(size).ok(), expected);
+    }
+
+    fn cases(cases: &[(u32, Option<&str>)]) -> Vec<(u32, Option<String>)> {
+        cases
+            .iter()
+            .map(|(id, host)| (*id, host.map(String::from)))
+            .collect()
+    }
+
+    #[rstest]
+    #[case(None, &[(1, None)], "https://aaonline.fr/", false)]
+    #[case(None, &[(1, None), (2, Some("https://mirror.example/"))], "https://mirror.example/", false)]
+    #[case(Some("https://aaonline.fr/"), &[(1, None)], "https://aaonline.fr/", false)]
+    #[case(Some("https://mirror.example/"), &[(1, None)], "https://mirror.example/", true)]
+    // Cases with hosts are warned about when resolving their hosts instead.
+    #[case(Some("https://mirror.example/"), &[(1, Some("https://aaonline.fr/"))], "https://mirror.example/", false)]
+    fn resolve_base_url(
+        #[case] explicit: Option<&str>,
+        #[case] given: &[(u32, Option<&str>)],
+        #[case] expected: &str,
+        #[case] warns: bool,
+    ) {
+        let (url, warning) = Args::resolve_base_url(explicit, &cases(given)).unwrap();
+        assert_eq!(url.as_str(), expected);
+        assert_eq!(warning.is_some(), warns, "unexpected warning: {warning:?}");
+    }
+
+    #[test]
+    fn resolve_base_url_invalid() {
+        assert!(Args::resolve_base_url(Some("not a url"), &cases(&[(1, None)])).is_err());
+    }
+
+    #[test]
+    fn resolve_case_hosts() {
+        let given = cases(&[
+            (1, None),
+            (2, Some("https://aaonline.fr/")),
+            (3, Some("https://mirror.example/")),
+        ]);
+        let base_url = Url::parse("https://aaonline.fr/").unwrap();
+        let (hosts, warning) = Args::resolve_case_hosts(None, &given, &base_url).unwrap();
+        assert_eq!(
+            hosts,
+            HashMap::from([(3, Url::parse("https://mirror.example/").unwrap())])
+        );
+        assert_eq!(warning, None);
+    }
+
+    #[test]
+    fn resolve_case_hosts_with_explicit_base_url() {
+        let given = cases(&[(1, None), (2, Some("https://mirror.example/"))]);
+        let base_url = Url::parse("https://aaonline.fr/").unwrap();
+        let (hosts, warning) =
+            Args::resolve_case_hosts(Some(base_url.as_str()), &given, &base_url).unwrap();
+        assert!(hosts.is_empty());
+        let warning = warning.expect("ignored host must be warned about");
+        assert!(
+            warning.contains("2 (https://mirror.example/)"),
+            "unexpected warning: {warning}"
+        );
+    }
+
+    #[test]
+    fn resolve_case_hosts_conflicting() {
+        let given = cases(&[
+            (1, Some("https://aaonline.fr/")),
+            (1, Some("https://mirror.example/")),
+        ]);
+        let base_url = Url::parse("https://aaonline.fr/").unwrap();
+        assert!(Args::resolve_case_hosts(None, &given, &base_url).is_err());
+    }
 }
@@ -0,0 +1,295 @@
+//! Contains the bundles used to put several cases into a single HTML file.
+//!
+//! A bundle starts out as a page listing its cases. Each case is stored as the complete HTML of
+//! its player, with the long base64 payloads of its data URLs replaced by references into a table
+//! shared by all cases, so that assets used by several cases are only embedded once. When a case
+//! is opened (via the `aaoffline_case` query parameter), the bundle restores its player and
+//! replaces itself with it.
+
+use std::collections::{BTreeSet, HashMap};
+
+use anyhow::Result;
+use itertools::Itertools;
+use regex::Captures;
+
+use crate::constants::re;
+use crate::data::case::Case;
+use crate::data::html_escape;
+use crate::pack::AssetPack;
+
+/// The query parameter containing the ID of the case to open from a bundle.
+pub(crate) const CASE_PARAMETER: &str = "aaoffline_case";
+
+/// Opens the case requested in the query parameters (or the only case of the bundle), if there
+/// is one.
+///
+/// This needs to run after the tables of the bundle, and replaces the page only after it has
+/// been parsed completely, since `document.open` does nothing while the page is being parsed.
+const LOADER_JS: &str = r"
+(function () {
+    var cases = document.querySelectorAll('script[data-case]');
+    var match = /[?&]aaoffline_case=(\d+)/.exec(location.search);
+    var id = match ? match[1] : cases.length === 1 ? cases[0].getAttribute('data-case') : null;
+    if (id === null) {
+        return;
+    }
+    var element = document.querySelector('script[data-case=\'' + id + '\']');
+    if (!element) {
+        document.getElementById('aaoffline-missing').hidden = false;
+        return;
+    }
+    var payloads = JSON.parse(document.getElementById('aaoffline-payloads').textContent);
+    var player = JSON.parse(element.textContent).replace(/@aaoffline-payload-(\d+)@/g,
+        function (_, index) { return payloads[index]; });
+    document.addEventListener('DOMContentLoaded', function () {
+        document.open();
+        document.write(player);
+        document.close();
+    }, { once: true });
+})();
+";
+
+/// Returns the branch of the redirection code that opens the case with the given [id] from the
+/// same bundle, passing along the given [save] (see [`crate::data::player::redirection_case`]).
+pub(crate) fn redirection_case(id: u32, save: &str) -> String {
+    format!("case {id}: window.location.href = '?{CASE_PARAMETER}={id}&{save};\nbreak;\n")
+}
+
+/// A case stored in a [`Bundle`].
+#[derive(Debug)]
+struct BundledCase {
+    /// The ID of the case.
+    id: u32,
+    /// The title of the case.
+    title: String,
+    /// The author of the case, if known.
+    author: Option<String>,
+    /// The sequence of the case and its position in it (starting at 1), if it's in one.
+    sequence: Option<(String, usize)>,
+    /// The player of the case, with its payloads replaced by references.
+    player: String,
+}
+
+/// Several cases that are written into a single HTML file.
+#[derive(Debug, Default)]
+pub(crate) struct Bundle {
+    /// The cases in this bundle, in the order they were added.
+    cases: Vec<BundledCase>,
+    /// The payloads shared by the cases, mapped to their index in the payload table.
+    payloads: HashMap<String, usize>,
+    /// The keys of the packed assets used by the cases (see [`AssetPack`]).
+    assets: BTreeSet<String>,
+}
+
+impl Bundle {
+    /// Adds the given [case] with the given finished [player] to this bundle.
+    ///
+    /// Packed assets are referenced by the player, but only embedded once the bundle is
+    /// rendered.
+    pub(crate) fn add(&mut self, case: &Case, mut player: String, compressed: bool) {
+        if compressed {
+            self.assets.extend(AssetPack::link(&mut player));
+        }
+        let player = re::BUNDLE_PAYLOAD_REGEX
+            .replace_all(&player, |caps: &Captures| {
+                let next = self.payloads.len();
+                let index = match self.payloads.get(&caps[1]) {
+                    Some(index) => *index,
+                    None => *self.payloads.entry(caps[1].to_string()).or_insert(next),
+                };
+                format!(";base64,@aaoffline-payload-{index}@")
+            })
+            .into_owned();
+        let info = &case.case_information;
+        self.cases.push(BundledCase {
+            id: case.id(),
+            title: info.title.clone(),
+            author: info.author.clone(),
+            sequence: info.sequence.as_ref().and_then(|x| {
+                let position = x.entry_ids().into_iter().position(|x| x == case.id())?;
+                Some((x.title.clone(), position + 1))
+            }),
+            player,
+        });
+    }
+
+    /// Returns the title of this bundle, which is the title of the sequence if all of its cases
+    /// belong to the same one.
+    fn title(&self) -> String {
+        match self
+            .cases
+            .iter()
+            .map(|x| x.sequence.as_ref().map(|x| &x.0))
+            .all_equal_value()
+        {
+            Ok(Some(sequence)) if self.cases.len() > 1 => sequence.trim().to_string(),
+            _ if self.cases.len() == 1 => self.cases[0].title.trim().to_string(),
+            _ => format!("{} cases", self.cases.len()),
+        }
+    }
+
+    /// Renders this bundle as a single HTML file, embedding the packed assets from the given
+    /// [pack] that its cases use.
+    pub(crate) fn render(&self, pack: &AssetPack) -> Result<String> {
+        let mut list = String::new();
+        let mut tables = String::new();
+        for case in self
+            .cases
+            .iter()
+            .sorted_by_key(|x| (x.sequence.as_ref().map(|x| x.1), x.title.to_lowercase()))
+        {
+            list.push_str(&format!(
+                "<li><a href=\"?{CASE_PARAMETER}={}\">{}</a>{}</li>\n",
+                case.id,
+                html_escape(&case.title),
+                case.author.as_ref().map_or_else(String::new, |x| format!(
+                    " <span class=\"author\">by {}</span>",
+                    html_escape(x)
+                )),
+            ));
+            // Script elements end at the first `</script>`, and JSON can only contain `<` within
+            // strings, where it can be escaped.
+            tables.push_str(&format!(
+                "<script type=\"application/json\" data-case=\"{}\">{}</script>\n",
+                case.id,
+                serde_json::to_string(&case.player)?.replace('<', "\\u003c"),
+            ));
+        }
+        let mut payloads = vec![""; self.payloads.len()];
+        for (payload, index) in &self.payloads {
+            payloads[*index] = payload;
+        }
+        let title = html_escape(&self.title());
+        let mut bundle = format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title}</title>
+<style>
+body {{ font-family: sans-serif; margin: 2em auto; max-width: 60em; padding: 0 1em; }}
+li {{ margin: 0.3em 0; }}
+.author {{ color: #666; font-size: 0.9em; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<p id="aaoffline-missing" hidden>The requested case is not part of this file. Please download all cases of the sequence together to play it.</p>
+<ol>
+{list}</ol>
+<script type="application/json" id="aaoffline-payloads">{payloads}</script>
+{tables}<script>{LOADER_JS}</script>
+</body>
+</html>
+"#,
+            payloads = serde_json::to_string(&payloads)?,
+        );
+        if !self.assets.is_empty() {
+            pack.insert(&mut bundle, &self.assets.iter().cloned().collect_vec())?;
+        }
+        Ok(bundle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Url;
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    fn case(id: u32, title: &str, sequence: Option<&[(u32, &str)]>) -> Case {
+        let mut info = json!({ "id": id, "title": title, "author": "Someone" });
+        if let Some(entries) = sequence {
+            info["sequence"] = json!({
+                "title": "The Sequence",
+                "list": entries
+                    .iter()
+                    .map(|(id, title)| json!({ "id": id, "title": title }))
+                    .collect::<Vec<_>>(),
+            });
+        }
+        Case {
+            case_information: serde_json::from_value(info).unwrap(),
+            case_data: Value::Null,
+            base_url: Url::parse("https://aaonline.fr/").unwrap(),
+            revision: None,
+        }
+    }
+
+    /// Returns the IDs of the cases in the order they're listed in the given [bundle].
+    fn listed_ids(bundle: &str) -> Vec<u32> {
+        bundle
+            .match_indices("<li><a href=\"?aaoffline_case=")
+            .map(|(i, x)| {
+                let id = &bundle[i + x.len()..];
+                id[..id.find('"').unwrap()].parse().unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn cases_are_listed_in_sequence_order() {
+        let entries = [
+            (3, "Turnabout Three"),
+            (1, "Turnabout One"),
+            (2, "Turnabout Two"),
+        ];
+        let mut bundle = Bundle::default();
+        for id in [1, 2, 3] {
+            bundle.add(
+                &case(id, &format!("Case {id}"), Some(&entries)),
+                String::new(),
+                false,
+            );
+        }
+        assert_eq!(bundle.title(), "The Sequence");
+        let rendered = bundle.render(&AssetPack::default()).unwrap();
+        assert_eq!(listed_ids(&rendered), [3, 1, 2]);
+        assert!(rendered.contains("<title>The Sequence</title>"));
+    }
+
+    #[test]
+    fn cases_without_sequence_are_listed_by_title() {
+        let mut bundle = Bundle::default();
+        bundle.add(&case(1, "beta", None), String::new(), false);
+        bundle.add(&case(2, "Alpha", None), String::new(), false);
+        bundle.add(
+            &case(3, "Gamma", Some(&[(3, "Gamma")])),
+            String::new(),
+            false,
+        );
+        assert_eq!(bundle.title(), "3 cases");
+        let rendered = bundle.render(&AssetPack::default()).unwrap();
+        assert_eq!(listed_ids(&rendered), [2, 1, 3]);
+
+        let mut single = Bundle::default();
+        single.add(&case(4, " Lonely ", None), String::new(), false);
+        assert_eq!(single.title(), "Lonely");
+    }
+
+    #[test]
+    fn payloads_are_shared_between_cases() {
+        let payload = "A".repeat(300);
+        let player = format!("<img src=\"data:image/png;base64,{payload}\"></script>");
+        let mut bundle = Bundle::default();
+        bundle.add(&case(1, "One", None), player.clone(), false);
+        bundle.add(&case(2, "Two", None), player, false);
+        let rendered = bundle.render(&AssetPack::default()).unwrap();
+        assert_eq!(rendered.matches(&payload).count(), 1);
+        assert_eq!(rendered.matches("base64,@aaoffline-payload-0@").count(), 2);
+        // The players can't end their script elements early.
+        assert!(rendered.contains("\\u003c/script>"));
+    }
+
+    #[test]
+    fn redirections_open_cases_from_the_bundle() {
+        let scripts = "window.location.href = '?trial_id=' + next_id + '&save_data=' + encodeURIComponent(save);";
+        let redirection = re::REDIRECTION_REGEX.captures(scripts).unwrap();
+        assert_eq!(
+            redirection_case(5, &redirection[2]),
+            "case 5: window.location.href = '?aaoffline_case=5&save_data=' + encodeURIComponent(save);\nbreak;\n"
+        );
+    }
+}
@@ -26,6 +26,14 @@ pub(crate) mod re {
         Regex::new(r"https?://(?:(?:www\.)?aaonline\.fr|aceattorney\.sparklin\.org)/").unwrap()
     });
 
+    pub(crate) static AUTHOR_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+        // Profile URLs of the forum use `u`, while the trial search uses `author_id`.
+        Regex::new(r"^https?://.*[?&](?:u|author_id)=(\d+)").unwrap()
+    });
+
+    pub(crate) static TRIAL_LINK_REGEX: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"(?:trial_id|id_proces)=(\d+)").unwrap());
+
     pub(crate) static AAONLINE_MAIN_HOST: &str = "https://aaonline.fr/";
 
     pub(crate) static TRIAL_INFORMATION_REGEX: LazyLock<Regex> = LazyLock::new(|| {
@@ -89,6 +97,22 @@ pub(crate) mod re {
         .unwrap()
     });
 
+    pub(crate) static ASSET_PLACEHOLDER_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+        // Placeholders for packed assets are either the whole string or follow `val=`.
+        Regex::new(r#"(["'])((?:val=)?)aaoffline-asset:([0-9a-f]{16})(["'])"#).unwrap()
+    });
+
+    pub(crate) static ASSET_REFERENCE_REGEX: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"aaofflineAsset\('([0-9a-f]{16})'").unwrap());
+
+    pub(crate) static BUNDLE_PAYLOAD_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+        // Only long payloads are worth sharing, short ones would barely get any shorter.
+        Regex::new(r";base64,([A-Za-z0-9+/]{256,}={0,2})").unwrap()
+    });
+
+    pub(crate) static HEAD_START_REGEX: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"(?i)<head[^>]*>").unwrap());
+
     pub(crate) static VOICE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
         Regex::new(r"(?s)function getVoiceUrl\(voice_id,\s*ext\)\s*\{(.*?)\}").unwrap()
     });
@@ -113,12 +137,37 @@ pub(crate) mod re {
         Regex::new(r"window\.location\.href\s*=\s*'\?trial_id='\s*\+\s*([^+\s]+)\s*\+\s*'&(save_data=.*?);").unwrap()
     });
 
+    /// Matches the redirections to other cases that we've written into a case before, up until
+    /// the default branch (which is where new redirections are inserted).
+    pub(crate) static REDIRECTION_SWITCH_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"switch \(Number\.parseInt\([^)]*\)\) \{\n(?P<cases>(?:case \d+: [^\n]*\nbreak;\n)*)default: window\.alert\(").unwrap()
+    });
+
+    pub(crate) static REDIRECTION_CASE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(
+            r"case (?P<id>\d+): window\.location\.href = [^\n]*? \+ '\?(?P<save>[^\n]*);\nbreak;\n",
+        )
+        .unwrap()
+    });
+
     pub(crate) static REMOVE_QUERY_PARAMETERS_REGEX: LazyLock<Regex> =
         LazyLock::new(|| Regex::new(r"\?.*").unwrap());
 
     pub(crate) static CONTENT_DISPOSITION_FILENAME_REGEX: LazyLock<Regex> =
         LazyLock::new(|| Regex::new(r#"filename="([^"]*?)""#).unwrap());
 
+    pub(crate) static REVISION_LINK_REGEX: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r#"href="([^"?#/:][^"?#:]*)""#).unwrap());
+
+    pub(crate) static REVISION_DATE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"(?:^|\D)(?P<timestamp>\d{10})(?:\D|$)|(?P<date>\d{4}-\d{2}-\d{2})").unwrap()
+    });
+
+    pub(crate) static HIDDEN_INPUT_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r#"<input type="hidden" name="(?P<name>[^"]*)" value="(?P<value>[^"]*)""#)
+            .unwrap()
+    });
+
     pub(crate) static GRAPHIC_ELEMENT_REGEX: LazyLock<Regex> = LazyLock::new(|| {
         Regex::new(r#"\s+element\.style\.height\s*=\s*img\.height\s*\+\s*['"]px['"];"#).unwrap()
     });
@@ -129,5 +178,12 @@ pub(crate) const UPDATE_MESSAGE: &str =
 
 pub(crate) const BRIDGE_URL: &str = "bridge.js.php";
 
+/// Lists the cases of an author. Only the first page of results is read, as the search isn't
+/// known to be paginated in a way we could follow.
+pub(crate) const AUTHOR_CASES_URL: &str = "search.php?search&criteria[author_id]={author}";
+
 pub(crate) const BITBUCKET_URL: &str =
     "https://bitbucket.org/AceAttorneyOnline/aao-game-creation-engine/raw/";
+
+pub(crate) const BITBUCKET_API_URL: &str =
+    "https://api.bitbucket.org/2.0/repositories/AceAttorneyOnline/aao-game-creation-engine/commit/";
@@ -0,0 +1,304 @@
+//! Contains the daemon mode, which keeps a collection of cases up to date by periodically
+//! downloading the ones that are new or have been edited since they were downloaded.
+//!
+//! While running, the daemon holds a lock file in its directory, so that two daemons don't write
+//! to the same collection at once, and keeps a status file there that other tools can read.
+
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::pin::pin;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use serde::Serialize;
+use serde_with::TimestampSeconds;
+
+use crate::MainContext;
+use crate::args::{Args, DownloadSequence, SequenceErrorHandling};
+
+/// The name of the lock file in the directory of a daemon.
+pub const LOCK_FILE: &str = "aaoffline.lock";
+
+/// The name of the status file in the directory of a daemon (unless another one was chosen).
+pub const STATUS_FILE: &str = "aaoffline-status.json";
+
+/// How long to wait before retrying after the first failed run.
+///
+/// This is doubled after each consecutive failure, up to the interval of the daemon.
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Periodically downloads the cases configured in some [Args], skipping the ones that haven't
+/// changed since the last download.
+#[derive(Debug)]
+pub struct Daemon {
+    /// The arguments to use for each run.
+    args: Args,
+    /// How long to wait between the start of two runs.
+    interval: Duration,
+    /// The directory containing the lock file.
+    dir: PathBuf,
+    /// The path to the status file.
+    status_file: PathBuf,
+    /// The current status of the daemon.
+    status: DaemonStatus,
+}
+
+/// The status of a daemon, as written to its status file.
+#[serde_with::serde_as]
+#[derive(Debug, Serialize)]
+struct DaemonStatus {
+    /// The ID of the process running the daemon.
+    pid: u32,
+    /// What the daemon is currently doing.
+    state: DaemonState,
+    /// When the daemon was started.
+    #[serde_as(as = "TimestampSeconds<i64>")]
+    started: DateTime<Utc>,
+    /// When the next run will start, if one is scheduled.
+    #[serde_as(as = "Option<TimestampSeconds<i64>>")]
+    next_run: Option<DateTime<Utc>>,
+    /// When the last successful run finished.
+    #[serde_as(as = "Option<TimestampSeconds<i64>>")]
+    last_success: Option<DateTime<Utc>>,
+    /// How many runs have failed since the last successful one.
+    consecutive_failures: u32,
+    /// The report of the last finished run.
+    last_run: Option<RunReport>,
+}
+
+/// What a daemon is currently doing.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum DaemonState {
+    /// Checking for changed cases and downloading them.
+    Running,
+    /// Waiting for the next run after a successful one.
+    Idle,
+    /// Waiting for the next run after a failed one.
+    BackingOff,
+    /// The daemon has been stopped.
+    Stopped,
+}
+
+/// The result of a single run of a daemon.
+#[serde_with::serde_as]
+#[derive(Debug, Serialize)]
+struct RunReport {
+    /// When the run started.
+    #[serde_as(as = "TimestampSeconds<i64>")]
+    started: DateTime<Utc>,
+    /// When the run finished.
+    #[serde_as(as = "TimestampSeconds<i64>")]
+    finished: DateTime<Utc>,
+    /// The error the run failed with, if any.
+    error: Option<String>,
+    /// The IDs of the cases that were downloaded (because they were new or changed).
+    updated_cases: Vec<u32>,
+}
+
+impl Daemon {
+    /// Creates a new daemon that downloads the cases configured in the given [args] every
+    /// [interval], writing its status to the given [status_file] (or to [`STATUS_FILE`] in the
+    /// output directory, if it's `None`).
+    ///
+    /// Since nobody is around to answer questions, every case of a sequence is downloaded (unless
+    /// only some were selected), inaccessible cases in a sequence are skipped, changed cases
+    /// replace their previous download, and a library is started in the output if needed.
+    #[must_use]
+    pub fn new(mut args: Args, interval: Duration, status_file: Option<PathBuf>) -> Daemon {
+        if args.sequence == DownloadSequence::Ask {
+            args.sequence = DownloadSequence::Every;
+        }
+        if args.sequence_error_handling == SequenceErrorHandling::Ask {
+            args.sequence_error_handling = SequenceErrorHandling::Continue;
+        }
+        args.replace_existing = true;
+        // Unchanged cases are recognized via the library.
+        args.create_library = true;
+        args.skip_unchanged = true;
+        let dir = args.output.clone().unwrap_or_else(|| PathBuf::from("."));
+        Daemon {
+            status_file: status_file.unwrap_or_else(|| dir.join(STATUS_FILE)),
+            dir,
+            args,
+            interval,
+            status: DaemonStatus {
+                pid: std::process::id(),
+                state: DaemonState::Running,
+                started: Utc::now(),
+                next_run: None,
+                last_success: None,
+                consecutive_failures: 0,
+                last_run: None,
+            },
+        }
+    }
+
+    /// Runs the daemon until it is interrupted (e.g., using Ctrl+C), using [new_context] to
+    /// create the context for each run from the arguments.
+    ///
+    /// # Errors
+    /// Returns an error if another daemon is already running in the same directory, or if the
+    /// lock file can't be created. Failed runs are retried instead.
+    pub async fn run(mut self, mut new_context: impl FnMut(Args) -> MainContext) -> Result<()> {
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .with_context(|| format!("Could not create directory {}", self.dir.display()))?;
+        let _lock = Self::lock(&self.dir, self.status.pid)?;
+        let mut shutdown = pin!(tokio::signal::ctrl_c());
+        loop {
+            let started = Utc::now();
+            self.status.state = DaemonState::Running;
+            self.status.next_run = None;
+            self.write_status().await;
+
+            let mut ctx = new_context(self.args.clone());
+            let result = tokio::select! {
+                result = ctx.run_all_steps() => result,
+                _ = &mut shutdown => break,
+            };
+            let finished = Utc::now();
+            let delay = if let Err(e) = &result {
+                error!("{e}");
+                self.status.consecutive_failures += 1;
+                self.status.state = DaemonState::BackingOff;
+                Self::retry_delay(self.status.consecutive_failures, self.interval)
+            } else {
+                self.status.consecutive_failures = 0;
+                self.status.last_success = Some(finished);
+                self.status.state = DaemonState::Idle;
+                // The interval is counted from the start of the run.
+                (started + self.interval - finished)
+                    .to_std()
+                    .unwrap_or_default()
+            };
+            self.status.last_run = Some(RunReport {
+                started,
+                finished,
+                error: result.err().map(|e| format!("{e:#}")),
+                updated_cases: ctx.written_cases().to_vec(),
+            });
+            let next_run = finished + delay;
+            self.status.next_run = Some(next_run);
+            self.write_status().await;
+            info!(
+                "Checking for changes again at {}.",
+                next_run.format("%Y-%m-%d %H:%M:%S UTC")
+            );
+
+            tokio::select! {
+                () = tokio::time::sleep(delay) => {},
+                _ = &mut shutdown => break,
+            }
+        }
+        info!("Stopping.");
+        self.status.state = DaemonState::Stopped;
+        self.status.next_run = None;
+        self.write_status().await;
+        Ok(())
+    }
+
+    /// Returns how long to wait before retrying after the given number of consecutive
+    /// [failures], for a daemon running every [interval].
+    fn retry_delay(failures: u32, interval: Duration) -> Duration {
+        RETRY_DELAY
+            .saturating_mul(2_u32.saturating_pow(failures.saturating_sub(1)))
+            .min(interval)
+    }
+
+    /// Locks the lock file in the given daemon [dir] on behalf of the process with the given
+    /// [pid], which stays locked as long as the returned file is open.
+    fn lock(dir: &Path, pid: u32) -> Result<File> {
+        let path = dir.join(LOCK_FILE);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("Could not open lock file {}", path.display()))?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => bail!(
+                "Another instance of aaoffline is already keeping {} up to date (see {}).",
+                dir.display(),
+                path.display()
+            ),
+            Err(TryLockError::Error(e)) => {
+                return Err(e)
+                    .with_context(|| format!("Could not lock lock file {}", path.display()));
+            }
+        }
+        // The process ID is only informational, the lock itself is what counts.
+        file.set_len(0)
+            .and_then(|()| writeln!(file, "{pid}"))
+            .with_context(|| format!("Could not write lock file {}", path.display()))?;
+        Ok(file)
+    }
+
+    /// Writes the current status to the status file, warning if that fails.
+    async fn write_status(&self) {
+        if let Err(e) = Self::write_atomically(&self.status_file, &self.status).await {
+            warn!(
+                "Could not write status file {}: {e:#}",
+                self.status_file.display()
+            );
+        }
+    }
+
+    /// Writes the given [status] to the given [path], so that readers never see a partially
+    /// written file.
+    async fn write_atomically(path: &Path, status: &DaemonStatus) -> Result<()> {
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        tokio::fs::write(&temp, serde_json::to_vec_pretty(status)?).await?;
+        tokio::fs::rename(&temp, path).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    #[rstest]
+    #[case(1, HOUR, RETRY_DELAY)]
+    #[case(2, HOUR, RETRY_DELAY * 2)]
+    #[case(4, HOUR, RETRY_DELAY * 8)]
+    // The delay never exceeds the interval, even after many failures.
+    #[case(7, HOUR, HOUR)]
+    #[case(u32::MAX, HOUR, HOUR)]
+    #[case(1, Duration::from_secs(10), Duration::from_secs(10))]
+    fn retry_delay(#[case] failures: u32, #[case] interval: Duration, #[case] expected: Duration) {
+        assert_eq!(Daemon::retry_delay(failures, interval), expected);
+    }
+
+    #[test]
+    fn lock_is_exclusive() {
+        let dir = tempfile::tempdir().unwrap();
+        let lock = Daemon::lock(dir.path(), 42).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join(LOCK_FILE)).unwrap(),
+            "42\n"
+        );
+        let error = Daemon::lock(dir.path(), 43).unwrap_err();
+        assert!(
+            error.to_string().starts_with("Another instance"),
+            "unexpected error: {error:#}"
+        );
+        // The process ID of the running daemon is kept.
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join(LOCK_FILE)).unwrap(),
+            "42\n"
+        );
+
+        drop(lock);
+        Daemon::lock(dir.path(), 43).unwrap();
+    }
+}
@@ -36,8 +36,18 @@ fn retrieve_escaped_json<T: DeserializeOwned>(regex: &Regex, text: &str) -> Resu
     serde_json::from_str(&extracted).context("Could not parse trial data. The script needs to be updated to be able to handle this trial.")
 }
 
+/// Escapes the given [text] for use within HTML (including attribute values).
+pub(crate) fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
 pub(crate) mod site;
 
 pub(crate) mod case;
 
 pub(crate) mod player;
+
+pub(crate) mod revision;
@@ -9,6 +9,7 @@ use colored::Colorize;
 use log::{debug, trace};
 
 use anyhow::anyhow;
+use reqwest::{StatusCode, Url};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use serde_with::TimestampSeconds;
@@ -16,39 +17,54 @@ use serde_with::formats::Flexible;
 
 use std::collections::HashSet;
 use std::fmt::Display;
+use std::path::Path;
 
-use crate::constants::re;
+use crate::args::AuthorFilter;
+use crate::constants::{AUTHOR_CASES_URL, re};
 use crate::data::RegexNotMatched;
+use crate::data::revision::Revision;
 
 /// Represents the information of a case.
+///
+/// Trial files exported by authors may lack most of these fields, in which case defaults are
+/// used.
 #[serde_with::serde_as]
 #[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Hash)]
 pub(crate) struct CaseInformation {
     /// The name of the author of the case.
-    author: Option<String>,
+    pub(crate) author: Option<String>,
     /// The ID of the author of the case.
-    author_id: u32,
+    #[serde(default)]
+    pub(crate) author_id: u32,
     /// Whether the case can be read by the current user.
-    can_read: bool,
+    #[serde(default)]
+    pub(crate) can_read: bool,
     /// Whether the case can be written to by the current user.
+    #[serde(default)]
     can_write: bool,
     /// The format of the case.
+    #[serde(default)]
     format: String,
     /// The ID of the case.
     id: u32,
     /// The language of the case.
-    language: String,
-    /// The date the case was last edited.
-    #[serde_as(as = "TimestampSeconds<i64, Flexible>")]
-    last_edit_date: DateTime<Utc>,
+    #[serde(default)]
+    pub(crate) language: String,
+    /// The date the case was last edited, if known.
+    ///
+    /// This is only unknown for cases imported from trial files that lack it.
+    #[serde_as(as = "Option<TimestampSeconds<i64, Flexible>>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) last_edit_date: Option<DateTime<Utc>>,
     /// The sequence the case is contained in, if any.
+    #[serde(default)]
     pub(crate) sequence: Option<Sequence>,
     /// The title of the case.
     pub(crate) title: String,
 }
 
 /// A sequence of connected cases.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct Sequence {
     /// The title of the sequence.
     pub(crate) title: String,
@@ -62,6 +78,11 @@ impl Sequence {
         self.list.iter().map(|x| x.id).collect()
     }
 
+    /// Returns the entries in this sequence, in order.
+    pub(crate) fn entries(&self) -> &[SequenceEntry] {
+        &self.list
+    }
+
     /// Returns the number of cases contained in this sequence.
     pub(crate) fn len(&self) -> usize {
         self.list.len()
@@ -84,10 +105,10 @@ impl Display for Sequence {
 }
 
 /// An entry (case) in a sequence.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct SequenceEntry {
     /// The ID of the case.
-    id: u32,
+    pub(crate) id: u32,
     /// The title of the case.
     title: String,
 }
@@ -114,6 +135,7 @@ impl Display for CaseInformation {
                 .unwrap_or(&"UNKNOWN AUTHOR".into())
                 .italic(),
             self.last_edit_date
+                .map_or_else(|| String::from("an unknown date"), |x| x.to_string())
         )
     }
 }
@@ -125,6 +147,10 @@ pub(crate) struct Case {
     pub(crate) case_information: CaseInformation,
     /// The data (i.e., contents) of this case.
     pub(crate) case_data: Value,
+    /// The base URL of the Ace Attorney Online instance this case was retrieved from.
+    pub(crate) base_url: Url,
+    /// The backed-up revision of this case, if it isn't the current version.
+    pub(crate) revision: Option<Revision>,
 }
 
 impl Case {
@@ -153,30 +179,199 @@ impl Case {
             .text()
             .await?;
 
-        let mut case_information: CaseInformation =
-            super::retrieve_escaped_json(&re::TRIAL_INFORMATION_REGEX, &case_script).map_err(
-                |x| {
-                    if x.root_cause().is::<RegexNotMatched>() {
-                        anyhow!("The case with given ID {case_id} could not be found!")
-                    } else {
-                        x
-                    }
-                },
-            )?;
+        Self::from_trial_script(&case_script, client.base_url.clone()).map_err(|x| {
+            if x.root_cause().is::<RegexNotMatched>() {
+                anyhow!("The case with given ID {case_id} could not be found!")
+            } else {
+                x
+            }
+        })
+    }
+
+    /// Retrieves the IDs of the cases by the author with the given [author_id] from Ace Attorney
+    /// Online.
+    ///
+    /// The cases are listed at the given URL [template], in which `{author}` is replaced by the
+    /// author ID. If no template is given, the trial search of the instance is used.
+    ///
+    /// If the template contains `{page}`, it is replaced by the page number, and the pages are
+    /// followed until one doesn't list any new cases.
+    pub(crate) async fn retrieve_author_case_ids(
+        author_id: u32,
+        client: &crate::AaofflineClient,
+        template: Option<&str>,
+    ) -> Result<Vec<u32>> {
+        let template = template
+            .unwrap_or(AUTHOR_CASES_URL)
+            .replace("{author}", &author_id.to_string());
+        let paginated = template.contains("{page}");
+        let mut ids = Vec::new();
+        let mut seen = HashSet::<u32>::new();
+        for page in 1.. {
+            let url = template.replace("{page}", &page.to_string());
+            debug!("Listing cases of author {author_id} at {url}...");
+            let listing = client
+                .get(&url)?
+                .send()
+                .await
+                .context("Could not list cases of author. Please check your internet connection.")?
+                .error_for_status()
+                .context("Cases of author seem to be inaccessible.")?
+                .text()
+                .await?;
+            trace!("Author case listing: {listing}");
+            let found = ids.len();
+            ids.extend(
+                re::TRIAL_LINK_REGEX
+                    .captures_iter(&listing)
+                    .filter_map(|x| x[1].parse().ok())
+                    .filter(|x| seen.insert(*x)),
+            );
+            // Past the last page, instances either list nothing or repeat the last page.
+            if !paginated || ids.len() == found {
+                break;
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Returns whether this case was written by the author with the given [author_id] and
+    /// matches the given [filter].
+    pub(crate) fn matches_author(&self, author_id: u32, filter: &AuthorFilter) -> bool {
+        let info = &self.case_information;
+        info.author_id == author_id
+            && filter
+                .language
+                .as_ref()
+                .is_none_or(|x| info.language.eq_ignore_ascii_case(x))
+            && filter
+                .edited_since
+                .is_none_or(|x| info.last_edit_date.is_some_and(|y| y.date_naive() >= x))
+    }
+
+    /// Retrieves only the information of the case with the given [case_id] from Ace Attorney
+    /// Online, returning `None` if the case doesn't exist (anymore).
+    ///
+    /// A case only counts as missing if AAO says so, i.e., if it declares no trial information
+    /// or responds with `404 Not Found` or `410 Gone`. Any other unexpected response is an error.
+    pub(crate) async fn retrieve_information(
+        case_id: u32,
+        client: &crate::AaofflineClient,
+    ) -> Result<Option<CaseInformation>> {
+        let response = client
+            .get(&format!("trial.js.php?trial_id={case_id}",))?
+            .send()
+            .await
+            .context("Could not download case data. Please check your internet connection.")?;
+        if matches!(response.status(), StatusCode::NOT_FOUND | StatusCode::GONE) {
+            return Ok(None);
+        }
+        let case_script = response
+            .error_for_status()
+            .context("Case data seems to be inaccessible.")?
+            .text()
+            .await?;
+        let declared = re::TRIAL_INFORMATION_REGEX
+            .captures(&case_script)
+            .context("Trial script seemingly changed format, this means the script needs to be updated to work with the newest AAO version.")?;
+        if declared.get(1).is_none() {
+            // AAO declares the variable without a value for cases that don't exist.
+            return Ok(None);
+        }
+        super::retrieve_escaped_json(&re::TRIAL_INFORMATION_REGEX, &case_script).map(Some)
+    }
+
+    /// Parses a case from the given [script], as generated by AAO's `trial.js.php`.
+    ///
+    /// The [base_url] is the AAO instance that any further data for the case is retrieved from.
+    pub(crate) fn from_trial_script(script: &str, base_url: Url) -> Result<Case> {
+        let case_information: CaseInformation =
+            super::retrieve_escaped_json(&re::TRIAL_INFORMATION_REGEX, script)?;
+        let case_data = super::retrieve_escaped_json(&re::TRIAL_DATA_REGEX, script).map_err(|e| {
+            if case_information.can_read {
+                e
+            } else {
+                anyhow!(
+                    "You don't have access to case \"{}\". If it was shared with you, please log in or pass your session cookies.",
+                    case_information.title
+                )
+            }
+        })?;
+        Ok(Self::new(case_information, case_data, base_url))
+    }
+
+    /// Parses a case from the given [json], which is an object containing the `trial_information`
+    /// and `initial_trial_data` of the case (i.e., the variables that `trial.js.php` defines).
+    ///
+    /// Both values may also be given as JSON-encoded strings.
+    pub(crate) fn from_trial_json(json: &str, base_url: Url) -> Result<Case> {
+        /// Parses the given [value], decoding it first if it is a JSON-encoded string.
+        fn parse<T: serde::de::DeserializeOwned>(value: Value) -> Result<T> {
+            if let Value::String(x) = value {
+                Ok(serde_json::from_str(&x)?)
+            } else {
+                Ok(serde_json::from_value(value)?)
+            }
+        }
+
+        let mut json: Value = serde_json::from_str(json).context("Trial file is not valid JSON")?;
+        let mut take = |key: &str| {
+            json.get_mut(key)
+                .map(Value::take)
+                .with_context(|| format!("Trial file does not contain {key}"))
+        };
+        let case_information = parse(take("trial_information")?)
+            .context("Could not parse trial information from trial file")?;
+        let case_data = parse(take("initial_trial_data")?)
+            .context("Could not parse trial data from trial file")?;
+        Ok(Self::new(case_information, case_data, base_url))
+    }
+
+    /// Reads a case from the local trial file at the given [path].
+    ///
+    /// The file may either be the output of AAO's `trial.js.php` or a JSON object (see
+    /// [`Case::from_trial_json`]).
+    #[cfg(feature = "fs")]
+    pub(crate) async fn from_file(path: &Path, base_url: Url) -> Result<Case> {
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("Could not read trial file {}", path.display()))?;
+        if content.trim_start().starts_with('{') {
+            Self::from_trial_json(&content, base_url)
+        } else {
+            Self::from_trial_script(&content, base_url)
+        }
+        .with_context(|| format!("Could not import trial file {}", path.display()))
+    }
 
+    /// Reads a case from the local trial file at the given [path].
+    ///
+    /// Without filesystem access, this always fails.
+    #[cfg(not(feature = "fs"))]
+    #[allow(clippy::unused_async)]
+    pub(crate) async fn from_file(path: &Path, _: Url) -> Result<Case> {
+        Err(anyhow!(
+            "Cannot import trial file {} without filesystem access.",
+            path.display()
+        ))
+    }
+
+    /// Creates a new case from the given [case_information] and [case_data].
+    fn new(mut case_information: CaseInformation, case_data: Value, base_url: Url) -> Case {
         // The trial's author can actually become null: https://aaonline.fr/forum/viewtopic.php?t=13898
         case_information
             .author
             .get_or_insert(String::from("[UNKNOWN]"));
 
-        let case_data = super::retrieve_escaped_json(&re::TRIAL_DATA_REGEX, &case_script)?;
         debug!("Case information: {:?}", case_information);
         trace!("Case data: {:?}", case_data);
 
-        Ok(Case {
+        Case {
             case_information,
             case_data,
-        })
+            base_url,
+            revision: None,
+        }
     }
 
     /// Returns a list of character and sprite IDs for sprites used in this case.
@@ -246,6 +441,10 @@ impl Case {
 
 impl Display for Case {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.case_information)
+        write!(f, "{}", self.case_information)?;
+        if let Some(revision) = &self.revision {
+            write!(f, " (revision {revision})")?;
+        }
+        Ok(())
     }
 }
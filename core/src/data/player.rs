@@ -1,8 +1,11 @@
 //! Contains data model related to the case player and its scripts.
 
-use crate::args::Userscripts;
-use crate::constants::{BITBUCKET_URL, re};
-use crate::download::Download;
+use crate::args::{AssetCategory, OmittedAssetHandling, Userscripts, VoiceFormat};
+use crate::bundle;
+use crate::constants::re;
+use crate::library::relative_path;
+use crate::pack::AssetPack;
+use crate::source::PlayerSource;
 use crate::transform::php;
 use crate::{GlobalContext, ProgressReporter};
 use anyhow::{Context, Result};
@@ -11,17 +14,34 @@ use futures_util::{StreamExt, stream};
 use itertools::Itertools;
 use log::{debug, trace, warn};
 
-use crate::AaofflineClient;
 use regex::{Captures, Regex};
 use serde_json::Value;
 
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
 
 use std::ops::Range;
 
 use super::case::Case;
 use super::site::SiteData;
 
+/// JavaScript that makes Howler.js play sounds embedded as data URLs via Web Audio, while all
+/// other sounds keep using the configured HTML5 audio option.
+///
+/// Howler.js decodes data URLs itself, so they don't need to be requested (which is what causes
+/// CORS errors when the player is opened locally).
+const EMBEDDED_AUDIO_JS: &str = r"
+(function () {
+    var init = Howl.prototype.init;
+    Howl.prototype.init = function (o) {
+        var src = [].concat(o.src)[0];
+        if (typeof src === 'string' && /^data:/.test(src)) {
+            o.html5 = false;
+        }
+        return init.call(this, o);
+    };
+})();
+";
+
 /// Merges two JSON objects into one.
 ///
 /// Code adapted from <https://stackoverflow.com/a/54118457>.
@@ -51,6 +71,8 @@ pub(crate) struct PlayerScripts {
     pub(crate) scripts: Option<String>,
     /// The global context for this program.
     pub(crate) ctx: GlobalContext,
+    /// The source from which the player's code is retrieved.
+    pub(crate) source: PlayerSource,
 }
 
 /// The target of a transformation.
@@ -99,30 +121,37 @@ struct JsModule {
 impl PlayerScripts {
     /// Retrieves the JavaScript text for the module with the given [name].
     async fn retrieve_js_text(
-        client: &AaofflineClient,
+        ctx: &GlobalContext,
+        source: &PlayerSource,
         name: &str,
-        player_version: &str,
     ) -> Result<String> {
-        let url = if name == "default_data" {
+        if name == "default_data" {
             // This is a special case—we can unfortunately not use the source code of AAO here
             // and need to access the rendered version from aaonline.fr, since this is a PHP file.
-            "default_data.js.php".to_string()
-        } else if name == "trial" {
+            return ctx
+                .client
+                .get("default_data.js.php")?
+                .send()
+                .await
+                .context("Could not download default data. Please check your internet connection.")?
+                .error_for_status()
+                .context("AAO default data seems to be inaccessible.")?
+                .text()
+                .await
+                .context("Script could not be decoded as text");
+        }
+        let path = if name == "trial" {
             // This one is also a PHP file, but we don't need the PHP-generated data as we already
             // retrieved it previously.
-            format!("{BITBUCKET_URL}/{player_version}/trial.js.php")
+            "trial.js.php".to_string()
         } else {
-            format!("{BITBUCKET_URL}/{player_version}/Javascript/{name}.js")
+            format!("Javascript/{name}.js")
         };
-        client.get(&url)?.send()
-            .await
-            .with_context(|| {
-                "Could not download scripts from AAO repository. Please check your internet connection."
-            })?
-            .error_for_status()
-            .context("AAO script code seems to be inaccessible.")?
-            .text()
+        source
+            .retrieve_file(&path, ctx)
             .await
+            .context("Could not download scripts from AAO repository. Please check your internet connection.")?
+            .content_str()
             .context("Script could not be decoded as text")
     }
 
@@ -236,8 +265,7 @@ impl PlayerScripts {
     ) -> Result<JsModule> {
         debug!("Retrieving JS module {name}");
 
-        let mut text =
-            Self::retrieve_js_text(&self.ctx.client, &name, &self.ctx.args.player_version).await?;
+        let mut text = Self::retrieve_js_text(&self.ctx, &self.source, &name).await?;
         if let Some(x) = pb {
             x.inc(1);
         }
@@ -286,16 +314,10 @@ impl PlayerScripts {
     ) -> Result<()> {
         pb.inc_length(37);
         let config = serde_json::to_string(&site_data.site_paths)?;
-        let common_js = Download::retrieve_url(
-            format!(
-                "{BITBUCKET_URL}/{}/Javascript/common.js",
-                self.ctx.args.player_version
-            )
-            .as_str(),
-            &self.ctx.args.http_handling,
-            &self.ctx.client,
-        )
-        .await?;
+        let common_js = self
+            .source
+            .retrieve_file("Javascript/common.js", &self.ctx)
+            .await?;
         pb.inc(1);
         self.scripts = Some(format!(
             "var cfg = {config};
@@ -352,9 +374,8 @@ impl Player {
 
     /// Creates a new player with the given [args].
     pub(crate) async fn new(ctx: GlobalContext) -> Result<Self> {
-        let default_text =
-            PlayerScripts::retrieve_js_text(&ctx.client, "default_data", &ctx.args.player_version)
-                .await?;
+        let source = PlayerSource::new(&ctx).await?;
+        let default_text = PlayerScripts::retrieve_js_text(&ctx, &source, "default_data").await?;
         let site_data = SiteData::from_site_data(&default_text, &ctx.client).await?;
         Ok(Player {
             site_data,
@@ -362,6 +383,7 @@ impl Player {
             scripts: PlayerScripts {
                 scripts: Some(default_text),
                 ctx,
+                source,
             },
         })
     }
@@ -379,19 +401,13 @@ impl Player {
 
     /// Retrieves the player code from the AAO repository.
     pub(crate) async fn retrieve_player(&mut self) -> Result<()> {
-        let mut player = self.scripts.ctx.client.get(&format!(
-            "{BITBUCKET_URL}/{}/player.php",
-            self.scripts.ctx.args.player_version
-        ))?
-        .send()
-        .await
-        .with_context(|| {
-            "Could not download player from AAO repository. Please check your internet connection."
-        })?
-        .error_for_status()
-        .context("AAO player code seems to be inaccessible.")?
-        .text()
-        .await?;
+        let mut player = self
+            .scripts
+            .source
+            .retrieve_file("player.php", &self.scripts.ctx)
+            .await
+            .context("Could not download player from AAO repository. Please check your internet connection.")?
+            .content_str()?;
 
         player.insert(0, '\n');
         self.content = Some(player);
@@ -408,14 +424,15 @@ impl Player {
     /// Retrieves the userscripts and appends them to the player scripts.
     pub(crate) async fn retrieve_userscripts(&mut self, pb: &dyn ProgressReporter) -> Result<()> {
         const HTML_END: &str = "</html>";
-        let urls = Userscripts::all_urls(&self.scripts.ctx.args.with_userscripts);
+        let args = &self.scripts.ctx.args;
+        let urls = Userscripts::all_urls(&args.with_userscripts, args.userscripts_url.as_ref())?;
         let client = &self.scripts.ctx.client;
         let userscripts = stream::iter(urls)
             .map(|url| async move {
                 debug!("Downloading userscript {url}...");
                 pb.inc(1);
                 client
-                    .get(url)?
+                    .get(&url)?
                     .send()
                     .await
                     .context("Could not download userscript.")?
@@ -449,7 +466,41 @@ impl Player {
         // as part of the scripts into the player.
         php::transform_trial_blocks(&self.scripts, case, &mut scripts)?;
         self.scripts.scripts = Some(scripts);
-        php::transform_player_blocks(self.content.as_mut().unwrap(), &self.scripts, case)
+        let content = self.content.as_mut().unwrap();
+        php::transform_player_blocks(content, &self.scripts, case)?;
+        // Redirections to other cases in the sequence are relative to the output directory, so
+        // we need to define how to get there from this case.
+        let ctx = &self.scripts.ctx;
+        let depth = ctx
+            .case_output_mapping
+            .get(&case.id())
+            .and_then(|x| x.parent()?.strip_prefix(&ctx.output).ok())
+            .map_or(0, |x| x.components().count());
+        let head_position = content.find("</head>").expect("No closing head found!");
+        content.insert_str(
+            head_position,
+            &format!(
+                "<script>var aaofflineRoot = '{}';</script>\n",
+                "../".repeat(depth)
+            ),
+        );
+        if let Some(revision) = &case.revision {
+            // We record which revision of the case this is, since it's not the current one.
+            let head_position = content.find("</head>").expect("No closing head found!");
+            content.insert_str(head_position, &format!("{}\n", revision.meta_tag()));
+        }
+        if !ctx.args.omit_assets.is_empty() {
+            // We also record which assets were left out of this copy of the case.
+            let head_position = content.find("</head>").expect("No closing head found!");
+            content.insert_str(
+                head_position,
+                &format!(
+                    "{}\n",
+                    omitted_assets_tag(&ctx.args.omit_assets, ctx.args.omitted_asset_handling)
+                ),
+            );
+        }
+        Ok(())
     }
 
     /// Applies the given [regex] to both the [player] and the [scripts] and returns a list of the
@@ -504,12 +555,11 @@ impl Player {
         for (target, css) in css_caps {
             let whole = css.get(0).unwrap();
             let group = css.get(1).unwrap();
-            let result = Download::retrieve_url(
-                group.as_str(),
-                &self.scripts.ctx.args.http_handling,
-                &self.scripts.ctx.client,
-            )
-            .await;
+            let result = self
+                .scripts
+                .source
+                .retrieve_resource(group.as_str(), &self.scripts.ctx)
+                .await;
             pb.inc(1);
 
             if let Ok(download) = result {
@@ -527,12 +577,11 @@ impl Player {
         for (target, include) in style_caps {
             let whole = include.get(0).unwrap();
             let group = include.get(1).unwrap();
-            let result = Download::retrieve_url(
-                &format!("CSS/{}.css", group.as_str()),
-                &self.scripts.ctx.args.http_handling,
-                &self.scripts.ctx.client,
-            )
-            .await;
+            let result = self
+                .scripts
+                .source
+                .retrieve_resource(&format!("CSS/{}.css", group.as_str()), &self.scripts.ctx)
+                .await;
             pb.inc(1);
             if let Ok(download) = result {
                 replacements.push(PlayerTransformation::new(
@@ -572,7 +621,7 @@ impl Player {
         let mut lang_json = Value::Null;
         pb.inc_length(lang_files.len() as u64);
         for lang_file in lang_files {
-            let content = Download::retrieve_url(&format!("{lang_dir}/{config_lang}/{lang_file}.js"), &self.scripts.ctx.args.http_handling, &self.scripts.ctx.client).await
+            let content = self.scripts.source.retrieve_resource(&format!("{lang_dir}/{config_lang}/{lang_file}.js"), &self.scripts.ctx).await
                 .context(format!("Could not download language files for {lang_file}. Please make sure the given language {} exists.", self.scripts.ctx.args.language))?.content;
             pb.inc(1);
             let lang = serde_json::from_slice::<Value>(&content)?;
@@ -619,12 +668,11 @@ impl Player {
                 // No need to do anything about data URLs.
                 continue;
             }
-            let result = Download::retrieve_url(
-                group.as_str(),
-                &self.scripts.ctx.args.http_handling,
-                &self.scripts.ctx.client,
-            )
-            .await;
+            let result = self
+                .scripts
+                .source
+                .retrieve_resource(group.as_str(), &self.scripts.ctx)
+                .await;
             if let Ok(download) = result {
                 replacements.push(PlayerTransformation::new(
                     target,
@@ -640,19 +688,21 @@ impl Player {
         // We need howler.js for sound effects.
         if let Some(howler) = re::HOWLER_REGEX.captures(scripts) {
             let configuration = howler.get(1).unwrap().as_str();
-            let result = Download::retrieve_url(
-                &format!(
-                    "{BITBUCKET_URL}/{}/Javascript/howler.js/howler.min.js",
-                    self.scripts.ctx.args.player_version
-                ),
-                &self.scripts.ctx.args.http_handling,
-                &self.scripts.ctx.client,
-            )
-            .await;
+            let result = self
+                .scripts
+                .source
+                .retrieve_file("Javascript/howler.js/howler.min.js", &self.scripts.ctx)
+                .await;
             pb.inc(1);
             if let Ok(download) = result {
                 // We will include Howler directly, as well as its configuration below.
-                let output = format!("{}\n{}", download.content_str()?, configuration);
+                let mut output = format!("{}\n{}", download.content_str()?, configuration);
+                let args = &self.scripts.ctx.args;
+                if args.embed_short_audio && !args.one_html_file && !args.disable_html5_audio {
+                    // Embedded sounds can't cause any CORS errors, so we let Howler decode them
+                    // into Web Audio buffers instead of playing them via HTML5 audio.
+                    output.push_str(EMBEDDED_AUDIO_JS);
+                }
                 replacements.push(PlayerTransformation::new(
                     TransformationTarget::Scripts,
                     howler.get(0).unwrap().range(),
@@ -671,10 +721,17 @@ impl Player {
                         preload_pos + PRELOAD.len()..preload_pos + PRELOAD.len(),
                         format!(", html5: {}", !self.scripts.ctx.args.disable_html5_audio),
                     ));
-                } else if self.scripts.ctx.args.disable_html5_audio {
-                    warn!(
-                        "--disable-html5-audio has no effect when used together with --one-html-file (-1), as HTML5 audio is disabled anyway."
-                    );
+                } else {
+                    if self.scripts.ctx.args.disable_html5_audio {
+                        warn!(
+                            "--disable-html5-audio has no effect when used together with --one-html-file (-1), as HTML5 audio is disabled anyway."
+                        );
+                    }
+                    if self.scripts.ctx.args.embed_short_audio {
+                        warn!(
+                            "--embed-short-audio has no effect when used together with --one-html-file (-1), as all audio is embedded anyway."
+                        );
+                    }
                 }
             } else if let Err(e) = result {
                 warn!("Could not download Howler.js, skipping: {e}");
@@ -692,9 +749,17 @@ impl Player {
                 // Unfortunately, the voice blips are retrieved dynamically, so we need to write
                 // some JavaScript here to statically return one of our URLs:
                 let mut voice_js = String::new();
+                let single_format = self.scripts.ctx.args.voice_format != VoiceFormat::All;
                 for ((id, ext), url) in &self.site_data.default_data.default_voice_urls {
-                    voice_js +=
-                        &format!("if (-voice_id === {id} && ext === '{ext}') return '{url}';\n");
+                    if single_format {
+                        // There is only one format, which we return for every format the player
+                        // asks for, so that the browser can't pick an empty fallback instead.
+                        voice_js += &format!("if (-voice_id === {id}) return '{url}';\n");
+                    } else {
+                        voice_js += &format!(
+                            "if (-voice_id === {id} && ext === '{ext}') return '{url}';\n"
+                        );
+                    }
                 }
                 // Just return an empty audio URL otherwise.
                 voice_js += "return 'data:audio/wav;base64,'";
@@ -767,7 +832,12 @@ impl Player {
                     // "unique" "file" for every psyche lock.
                     if let Some(data_url) = &self.site_data.default_data.psyche_lock_urls.get(name)
                     {
-                        format!("'{}'", data_url.replace(';', &format!("'{lock_id} + ';")))
+                        if let Some(key) = AssetPack::key(data_url) {
+                            // Packed assets can simply be requested under a different URL.
+                            format!("aaofflineAsset('{key}', ''{lock_id})")
+                        } else {
+                            format!("'{}'", data_url.replace(';', &format!("'{lock_id} + ';")))
+                        }
                     } else {
                         // Case doesn't use any psyche-locks, this doesn't matter.
                         String::new()
@@ -828,21 +898,28 @@ impl Player {
             let target = redirection.get(1).unwrap().as_str();
             let save = redirection.get(2).unwrap().as_str();
             let mut new_redirection = format!("switch (Number.parseInt({target})) {{\n");
-            for (id, path) in &self.scripts.ctx.case_output_mapping {
-                // The path needs to be relative to each case (so that downloaded cases can be moved).
-                let mut target_path = path
-                    .strip_prefix(&self.scripts.ctx.output)?
-                    .components()
-                    .map(|x| x.as_os_str().to_str().expect("invalid path"))
-                    .join("/");
-                if !self.scripts.ctx.args.one_html_file {
-                    // We need to go up one directory first.
-                    target_path = format!("../{target_path}");
+            let ctx = &self.scripts.ctx;
+            if ctx.bundles_cases() {
+                // Cases in a bundle are opened by loading the bundle with their ID.
+                for id in ctx.case_output_mapping.keys() {
+                    new_redirection.push_str(&bundle::redirection_case(*id, save));
                 }
-                target_path = target_path.replace('\'', "\\'");
-
-                new_redirection.push_str(&format!(
-                    "case {id}: window.location.href = '{target_path}' + '?{save};\nbreak;\n"
+            }
+            for (id, path) in ctx
+                .case_output_mapping
+                .iter()
+                .chain(&ctx.linked_outputs)
+                .filter(|_| !ctx.bundles_cases())
+            {
+                // The path needs to be relative to each case (so that downloaded cases can be moved).
+                // Since cases may be nested differently, each case defines the way up to the
+                // output directory as `aaofflineRoot` (see `transform_player`).
+                let target_path = relative_path(&ctx.output, path)
+                    .with_context(|| format!("Could not link to case at {}", path.display()))?;
+                new_redirection.push_str(&redirection_case(
+                    *id,
+                    &format!("aaofflineRoot + '{}'", target_path.replace('\'', "\\'")),
+                    save,
                 ));
             }
             new_redirection.push_str("default: window.alert('Target case was not downloaded when this case was written. Please download a sequence of cases together (at once). You can, for example, use `-s every` with aaoffline to do this.');\n}");
@@ -881,12 +958,11 @@ impl Player {
                 pb.inc(1);
                 continue;
             }
-            let result = Download::retrieve_url(
-                &format!("CSS/{}", group.as_str()),
-                &self.scripts.ctx.args.http_handling,
-                &self.scripts.ctx.client,
-            )
-            .await;
+            let result = self
+                .scripts
+                .source
+                .retrieve_resource(&format!("CSS/{}", group.as_str()), &self.scripts.ctx)
+                .await;
             if let Ok(download) = result {
                 replacements.push((group.range(), download.make_data_url()));
             } else if let Err(e) = result {
@@ -902,3 +978,40 @@ impl Player {
         Ok(())
     }
 }
+
+/// Returns the branch of the redirection code that redirects to the case with the given [id] at
+/// the given [target] (a JavaScript expression), passing along the given [save].
+pub(crate) fn redirection_case(id: u32, target: &str, save: &str) -> String {
+    format!("case {id}: window.location.href = {target} + '?{save};\nbreak;\n")
+}
+
+/// Returns the HTML tag that records which [omitted] categories of assets were replaced
+/// according to the given [handling] in the output.
+fn omitted_assets_tag(omitted: &BTreeSet<AssetCategory>, handling: OmittedAssetHandling) -> String {
+    format!(
+        "<meta name=\"aaoffline-omitted-assets\" content=\"{}\" data-replacement=\"{}\">",
+        omitted.iter().join(", "),
+        match handling {
+            OmittedAssetHandling::Online => "online",
+            OmittedAssetHandling::Placeholder => "placeholder",
+        }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn omitted_assets_tag_lists_categories() {
+        let omitted = BTreeSet::from([AssetCategory::Sounds, AssetCategory::Music]);
+        assert_eq!(
+            omitted_assets_tag(&omitted, OmittedAssetHandling::Placeholder),
+            r#"<meta name="aaoffline-omitted-assets" content="music, sounds" data-replacement="placeholder">"#
+        );
+        assert_eq!(
+            omitted_assets_tag(&omitted, OmittedAssetHandling::Online),
+            r#"<meta name="aaoffline-omitted-assets" content="music, sounds" data-replacement="online">"#
+        );
+    }
+}
@@ -0,0 +1,198 @@
+//! Contains data models and helper methods related to backed-up revisions of a case.
+
+use std::fmt::Display;
+
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::{DateTime, NaiveDate, Utc};
+use colored::Colorize;
+use itertools::Itertools;
+use log::{debug, trace};
+use reqwest::Url;
+use reqwest::header::LAST_MODIFIED;
+use serde_json::Value;
+
+use crate::AaofflineClient;
+use crate::constants::re;
+use crate::data::case::Case;
+use crate::data::html_escape;
+use crate::data::site::SitePaths;
+
+/// A backed-up revision of a case, as stored by Ace Attorney Online.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct Revision {
+    /// The name of this revision (i.e., the name of its backup file).
+    pub(crate) name: String,
+    /// The date at which this revision was backed up, if known.
+    pub(crate) date: Option<DateTime<Utc>>,
+    /// The URL at which the backup file for this revision can be found.
+    url: Url,
+}
+
+impl Display for Revision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name.bold())?;
+        if let Some(date) = self.date {
+            write!(f, " [backed up on {date}]")?;
+        }
+        Ok(())
+    }
+}
+
+impl Revision {
+    /// Lists the revisions of the case with the given [case_id] that are available on the AAO
+    /// instance of the given [client], sorted from oldest to newest.
+    ///
+    /// The revisions are listed at the given URL [template], in which `{id}` is replaced by the
+    /// case ID. If no template is given, the backups directory configured on the instance is used.
+    pub(crate) async fn list(
+        case_id: u32,
+        client: &AaofflineClient,
+        template: Option<&str>,
+    ) -> Result<Vec<Revision>> {
+        let template = if let Some(template) = template {
+            template.to_string()
+        } else {
+            let site_paths = SitePaths::retrieve_from_bridge(client).await?;
+            format!("{}{{id}}/", site_paths.trialdata_backups_dir)
+        };
+        let listing_url = client
+            .base_url
+            .join(&template.replace("{id}", &case_id.to_string()))
+            .context("Invalid URL for case revisions")?;
+        debug!("Listing revisions of case {case_id} at {listing_url}...");
+        let listing = client
+            .get(listing_url.as_str())?
+            .send()
+            .await
+            .context("Could not list case revisions. Please check your internet connection.")?
+            .error_for_status()
+            .with_context(|| format!("No revisions of case {case_id} seem to be available."))?
+            .text()
+            .await?;
+        trace!("Revision listing: {listing}");
+        let names: Vec<String> = if let Ok(names) = serde_json::from_str(&listing) {
+            names
+        } else {
+            // Otherwise, this should be a directory index as generated by most web servers.
+            re::REVISION_LINK_REGEX
+                .captures_iter(&listing)
+                .map(|x| x[1].to_string())
+                .filter(|x| !x.ends_with('/'))
+                .unique()
+                .collect()
+        };
+        names
+            .into_iter()
+            .map(|name| {
+                Ok(Revision {
+                    date: Self::date_from_name(&name),
+                    url: listing_url
+                        .join(&name)
+                        .with_context(|| format!("Invalid revision name {name}"))?,
+                    name: urlencoding::decode(&name).map_or(name.clone(), |x| x.into_owned()),
+                })
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(|x| {
+                x.into_iter()
+                    .sorted_by(|a, b| a.date.cmp(&b.date).then_with(|| a.name.cmp(&b.name)))
+                    .collect()
+            })
+    }
+
+    /// Extracts the backup date from the given revision [name], if it contains one.
+    ///
+    /// Both UNIX timestamps and dates in the form `YYYY-MM-DD` are recognized.
+    fn date_from_name(name: &str) -> Option<DateTime<Utc>> {
+        let captures = re::REVISION_DATE_REGEX.captures(name)?;
+        if let Some(timestamp) = captures.name("timestamp") {
+            DateTime::from_timestamp(timestamp.as_str().parse().ok()?, 0)
+        } else {
+            NaiveDate::parse_from_str(&captures["date"], "%Y-%m-%d")
+                .ok()
+                .and_then(|x| x.and_hms_opt(0, 0, 0))
+                .map(|x| x.and_utc())
+        }
+    }
+
+    /// Selects the revision matching the given [spec] from the given [revisions].
+    ///
+    /// The [spec] is either the name of a revision or a date in the form `YYYY-MM-DD`, in which
+    /// case the latest revision backed up on or before that day is selected.
+    pub(crate) fn select(mut revisions: Vec<Revision>, spec: &str) -> Result<Revision> {
+        if let Some(position) = revisions.iter().position(|x| x.name == spec) {
+            return Ok(revisions.swap_remove(position));
+        }
+        let Ok(day) = NaiveDate::parse_from_str(spec, "%Y-%m-%d") else {
+            bail!(
+                "No revision named \"{spec}\" exists. Available revisions are: {}",
+                revisions.iter().map(|x| &x.name).join(", ")
+            );
+        };
+        let end_of_day = day
+            .succ_opt()
+            .and_then(|x| x.and_hms_opt(0, 0, 0))
+            .map(|x| x.and_utc())
+            .context("Invalid revision date")?;
+        revisions
+            .into_iter()
+            .rfind(|x| x.date.is_some_and(|date| date < end_of_day))
+            .ok_or_else(|| anyhow!("No revision from on or before {spec} exists."))
+    }
+
+    /// Retrieves the contents of this revision for the given [current] version of the case.
+    ///
+    /// Backups that only contain the trial data (and not the trial information) keep the
+    /// information of the current version.
+    pub(crate) async fn retrieve(
+        mut self,
+        current: Case,
+        client: &AaofflineClient,
+    ) -> Result<Case> {
+        let response = client
+            .get(self.url.as_str())?
+            .send()
+            .await
+            .context("Could not download case revision. Please check your internet connection.")?
+            .error_for_status()
+            .with_context(|| format!("Revision {} seems to be inaccessible.", self.name))?;
+        if self.date.is_none() {
+            self.date = response
+                .headers()
+                .get(LAST_MODIFIED)
+                .and_then(|x| x.to_str().ok())
+                .and_then(|x| DateTime::parse_from_rfc2822(x).ok())
+                .map(|x| x.to_utc());
+        }
+        let content = response.text().await?;
+        let context = || format!("Could not parse revision {}", self.name);
+        let mut case = if re::TRIAL_DATA_REGEX.is_match(&content) {
+            Case::from_trial_script(&content, current.base_url.clone()).with_context(context)?
+        } else {
+            // Backups may be prefixed by some kind of version marker before the actual JSON.
+            let json = &content[content.find('{').with_context(context)?..];
+            let value: Value = serde_json::from_str(json).with_context(context)?;
+            if value.get("trial_information").is_some() {
+                Case::from_trial_json(json, current.base_url.clone()).with_context(context)?
+            } else if value.get("frames").is_some() {
+                Case {
+                    case_data: value,
+                    ..current
+                }
+            } else {
+                bail!("Revision {} does not contain any trial data.", self.name);
+            }
+        };
+        case.revision = Some(self);
+        Ok(case)
+    }
+
+    /// Returns the HTML tag that records this revision in the output.
+    pub(crate) fn meta_tag(&self) -> String {
+        format!(
+            "<meta name=\"aaoffline-revision\" content=\"{}\" data-date=\"{}\">",
+            html_escape(&self.name),
+            self.date.map(|x| x.to_rfc3339()).unwrap_or_default()
+        )
+    }
+}
@@ -79,7 +79,7 @@ pub(crate) struct SitePaths {
     css_dir: String,
     defaultplaces_subdir: String,
     evidence_subdir: String,
-    forum_path: String,
+    pub(crate) forum_path: String,
     icon_subdir: String,
     js_dir: String,
     pub(crate) lang_dir: String,
@@ -92,7 +92,7 @@ pub(crate) struct SitePaths {
     startup_subdir: String,
     still_subdir: String,
     talking_subdir: String,
-    trialdata_backups_dir: String,
+    pub(crate) trialdata_backups_dir: String,
     trialdata_deleted_dir: String,
     trialdata_dir: String,
     voices_dir: String,
@@ -9,7 +9,7 @@ use futures_util::future::join_all;
 use futures_util::stream::{AbortHandle, Abortable};
 use futures_util::{FutureExt, StreamExt, stream};
 use itertools::Itertools;
-use log::{debug, error, trace, warn};
+use log::{debug, error, info, trace, warn};
 use mime2ext::mime2ext;
 use regex::Regex;
 use reqwest::Url;
@@ -18,18 +18,20 @@ use sanitize_filename::sanitize;
 use serde_json::Value;
 use std::borrow::Cow;
 use std::collections::hash_set::Drain;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::hash::{DefaultHasher, Hash, Hasher};
 use std::iter;
 use std::path::{Path, PathBuf};
 use std::string::FromUtf8Error;
+use std::sync::Mutex;
 use tokio::sync::OnceCell;
 
+use crate::GlobalContext;
 use crate::ProgressReporter;
+use crate::args::{AssetCategory, HttpHandling, OmittedAssetHandling, VoiceFormat};
 use crate::constants::re::{CONTENT_DISPOSITION_FILENAME_REGEX, REMOVE_QUERY_PARAMETERS_REGEX};
 use crate::data::case::Case;
 use crate::data::site::{SiteData, SitePaths};
-use crate::{GlobalContext, args::HttpHandling};
 
 /// Downloaded content.
 pub(crate) struct Download {
@@ -77,6 +79,24 @@ impl Download {
         })
     }
 
+    /// Creates a [Download] from [content] that was previously downloaded from the given [url]
+    /// (e.g., content that was read from a cache).
+    ///
+    /// The Content-Type header is guessed based on the path of the URL.
+    #[cfg(feature = "fs")]
+    pub(crate) fn from_content(url: &str, content: Vec<u8>) -> Result<Download> {
+        let target_url = Url::parse(url)?;
+        let mut headers = HeaderMap::new();
+        if let Some(mime) = mime_guess::from_path(target_url.path()).first_raw() {
+            headers.insert("Content-Type", HeaderValue::from_static(mime));
+        }
+        Ok(Self {
+            target_url,
+            content: content.into(),
+            headers,
+        })
+    }
+
     /// Returns the content of this [Download] as a UTF-8 encoded String.
     pub(crate) fn content_str(&self) -> Result<String, FromUtf8Error> {
         String::from_utf8(self.content.to_vec())
@@ -142,6 +162,16 @@ impl Download {
     }
 }
 
+/// Returns the directory next to the single HTML file at the given [player] that contains the
+/// assets too large to be embedded into it (see [`sidecar_threshold`]).
+///
+/// [`sidecar_threshold`]: crate::args::Args::sidecar_threshold
+pub fn sidecar_dir(player: &Path) -> PathBuf {
+    let mut name = player.file_stem().unwrap_or_default().to_os_string();
+    name.push("_files");
+    player.with_file_name(name)
+}
+
 /// Hashes the given [value] to a u64.
 fn hash(value: &str) -> u64 {
     let mut hasher = DefaultHasher::new();
@@ -170,6 +200,55 @@ pub(crate) struct AssetDownload {
     output_path: PathBuf,
 }
 
+impl AssetDownload {
+    /// The maximum size (in bytes) of sound effects that will be embedded as data URLs when
+    /// [`embed_short_audio`](crate::args::Args::embed_short_audio) is set.
+    const MAX_EMBEDDED_SOUND_SIZE: usize = 512 * 1024;
+
+    /// A transparent 1x1 GIF that replaces omitted images.
+    const EMPTY_IMAGE: &str =
+        "data:image/gif;base64,R0lGODlhAQABAIAAAAAAAP///yH5BAEAAAAALAAAAAABAAEAAAIBRAA7";
+
+    /// A silent WAV file that replaces omitted audio.
+    const SILENT_AUDIO: &str = "data:audio/wav;base64,UklGRiwAAABXQVZFZm10IBAAAAABAAEAQB8AAEAfAAABAAgAZGF0YQgAAACAgICAgICAgA==";
+
+    /// Whether this asset is a psyche-lock file.
+    fn is_psyche_lock(&self) -> bool {
+        self.json_refs
+            .iter()
+            .any(|x| matches!(x.source, JsonSource::PsycheLock(_)))
+    }
+
+    /// Returns the placeholder to use instead of this asset if it is omitted.
+    fn placeholder(&self) -> &'static str {
+        if self
+            .json_refs
+            .iter()
+            .filter_map(JsonReference::category)
+            .any(AssetCategory::is_audio)
+        {
+            Self::SILENT_AUDIO
+        } else {
+            Self::EMPTY_IMAGE
+        }
+    }
+
+    /// Whether this asset should be embedded as a data URL into the case data, given that its
+    /// downloaded content is of the given [size].
+    ///
+    /// This is only the case for voice blips and short sound effects, so that Howler.js can play
+    /// them via Web Audio even when the player is opened locally.
+    fn is_embedded_audio(&self, size: usize) -> bool {
+        self.json_refs.iter().all(|x| match &x.source {
+            JsonSource::DefaultVoices(..) => true,
+            JsonSource::CaseData(_) => {
+                x.pointer.starts_with("/sounds/") && size <= Self::MAX_EMBEDDED_SOUND_SIZE
+            }
+            _ => false,
+        })
+    }
+}
+
 impl PartialEq for AssetDownload {
     fn eq(&self, other: &Self) -> bool {
         self.url == other.url && self.output_path == other.output_path
@@ -225,6 +304,26 @@ impl JsonReference {
     fn concat_path(&self, path: &str) -> JsonReference {
         JsonReference::new(self.source.clone(), format!("{}/{path}", self.pointer))
     }
+
+    /// Returns the category of the referenced asset, or `None` if it can't be omitted.
+    fn category(&self) -> Option<AssetCategory> {
+        match &self.source {
+            // The first part of the pointer is the part of the case data the asset is in.
+            JsonSource::CaseData(_) => match self.pointer.split('/').nth(1)? {
+                "profiles" => Some(AssetCategory::Profiles),
+                "evidence" => Some(AssetCategory::Evidence),
+                "places" => Some(AssetCategory::Places),
+                "popups" => Some(AssetCategory::Popups),
+                "music" => Some(AssetCategory::Music),
+                "sounds" => Some(AssetCategory::Sounds),
+                _ => None,
+            },
+            JsonSource::DefaultPlaces | JsonSource::PointArea(_) => Some(AssetCategory::Places),
+            JsonSource::DefaultVoices(..) => Some(AssetCategory::Voices),
+            JsonSource::DefaultSprites(..) => Some(AssetCategory::Profiles),
+            JsonSource::PsycheLock(_) => None,
+        }
+    }
 }
 
 /// A downloader for case assets.
@@ -234,6 +333,8 @@ pub(crate) struct AssetDownloader<'a> {
     collector: AssetCollector,
     /// A reference to the global context for the program.
     ctx: &'a GlobalContext,
+    /// The asset files that were written (or linked) so far.
+    written: Mutex<Vec<PathBuf>>,
 }
 
 /// Collects asset downloads and assigns unique filenames to them.
@@ -241,6 +342,11 @@ pub(crate) struct AssetDownloader<'a> {
 struct AssetCollector {
     /// The collected asset downloads.
     collected: HashSet<AssetDownload>,
+    /// The categories of assets that shall not be downloaded.
+    omit: BTreeSet<AssetCategory>,
+    /// The collected assets of the [omit] categories, which are pointed elsewhere instead of
+    /// being downloaded.
+    omitted: HashSet<AssetDownload>,
     /// The default icon URL.
     default_icon_url: String,
     /// The output directory for the assets.
@@ -250,10 +356,16 @@ struct AssetCollector {
 }
 
 impl AssetCollector {
-    /// Creates a new asset collector.
-    fn new(default_icon_url: String, output: PathBuf) -> AssetCollector {
+    /// Creates a new asset collector that leaves out the assets of the given [omit] categories.
+    fn new(
+        default_icon_url: String,
+        output: PathBuf,
+        omit: BTreeSet<AssetCategory>,
+    ) -> AssetCollector {
         AssetCollector {
             collected: HashSet::new(),
+            omit,
+            omitted: HashSet::new(),
             default_icon_url,
             output,
             target_case_title: None,
@@ -273,6 +385,19 @@ impl AssetCollector {
     /// The [url] will be used for hashing only, to ensure a unique output name,
     /// while the [path]'s filename will be used for the output path's filename.
     fn new_path(output: &Path, url: &str, path: &Path) -> PathBuf {
+        let path = output.join("assets").join(Self::new_filename(url, path));
+        assert!(
+            path.parent()
+                .expect("parent dir must exist")
+                .ends_with("assets"),
+            "must end with assets/ but doesn't: {}",
+            path.display()
+        );
+        path
+    }
+
+    /// Creates a new unique filename for the given [url] and [path] (see [`Self::new_path`]).
+    fn new_filename(url: &str, path: &Path) -> PathBuf {
         let ext = if let Some(ext) = path.extension().and_then(|x| x.to_str()) {
             ext
         } else {
@@ -290,18 +415,7 @@ impl AssetCollector {
             .map(Cow::into_owned)
             .map(|x| x.replace('%', "-"))
             .unwrap_or(name);
-        let path = output
-            .join("assets")
-            .join(sanitize(format!("{name}-{}", hash(url))).to_lowercase())
-            .with_extension(ext);
-        assert!(
-            path.parent()
-                .expect("parent dir must exist")
-                .ends_with("assets"),
-            "must end with assets/ but doesn't: {}",
-            path.display()
-        );
-        path
+        PathBuf::from(sanitize(format!("{name}-{}", hash(url))).to_lowercase()).with_extension(ext)
     }
 
     /// Creates a new asset download request.
@@ -313,6 +427,9 @@ impl AssetCollector {
     /// server. If a [`filename`] is given, it will be used (or try to be used, as long as it
     /// hasn't been used yet) as the filename for the asset.
     ///
+    /// References in one of the categories that are omitted aren't collected for download, but
+    /// set aside to be pointed to the asset's online location or a placeholder instead.
+    ///
     /// [^1]: Note that this will be the "case-local" path to the asset, which is distinct from the
     /// "case-global" path to the asset where it will be saved relative to the current directory.
     /// The "case-local" path should, for example, always start with "assets/".
@@ -358,6 +475,7 @@ impl AssetCollector {
             .to_string();
 
         trace!("Creating asset for {url}");
+        let omitted = json_ref.category().is_some_and(|x| self.omit.contains(&x));
         let asset = AssetDownload {
             url: url.clone(),
             path: OnceCell::new(),
@@ -369,6 +487,17 @@ impl AssetCollector {
                 .clone(),
             output_path: self.output.clone(),
         };
+        if omitted {
+            // The reference is pointed elsewhere once all assets were collected. Other references
+            // to the same asset (in categories that aren't omitted) still get it downloaded.
+            trace!("Omitting asset for {url}");
+            let mut target_asset = asset;
+            if let Some(existing) = self.omitted.take(&target_asset) {
+                target_asset.json_refs.extend(existing.json_refs);
+            }
+            self.omitted.insert(target_asset);
+            return None;
+        }
         let target_asset = if let Some(mut existing) = self.collected.take(&asset) {
             // If an asset with this URL exists already, we'll add our JsonRef to it.
             debug!("Duplicate asset for {url}");
@@ -417,11 +546,17 @@ impl<'a> AssetDownloader<'a> {
     ) -> AssetDownloader<'a> {
         let default_icon_path = site_data.site_paths.default_icon();
         AssetDownloader {
-            collector: AssetCollector::new(default_icon_path, output),
+            collector: AssetCollector::new(default_icon_path, output, ctx.args.omit_assets.clone()),
             ctx,
+            written: Mutex::default(),
         }
     }
 
+    /// Returns the asset files that were written (or linked) so far.
+    pub(crate) fn written_files(&self) -> Vec<PathBuf> {
+        self.written.lock().unwrap().clone()
+    }
+
     /// Sets the output directory for the collected assets, creating it if necessary.
     pub(crate) async fn set_output(&mut self, output: PathBuf) -> Result<(), std::io::Error> {
         // May need to create the directory first.
@@ -503,23 +638,72 @@ impl<'a> AssetDownloader<'a> {
         }
     }
 
+    /// Points the given omitted [asset] to its online location or a placeholder instead of
+    /// downloading it.
+    fn omit_asset(&self, asset: &AssetDownload) -> Result<()> {
+        let path = match self.ctx.args.omitted_asset_handling {
+            OmittedAssetHandling::Online => self.ctx.client.resolve(&asset.url)?,
+            OmittedAssetHandling::Placeholder => asset.placeholder().to_string(),
+        };
+        asset.path.set(path).expect("path must not be set already");
+        Ok(())
+    }
+
     /// Downloads the given [asset] and writes it to its set path.
     async fn download_asset(&self, asset: &mut AssetDownload) -> Result<()> {
         let download =
             Download::retrieve_url(&asset.url, &self.ctx.args.http_handling, &self.ctx.client)
                 .await?;
-        if self.ctx.args.one_html_file {
+        if self.ctx.args.one_html_file
+            && self
+                .ctx
+                .args
+                .sidecar_threshold
+                .is_some_and(|x| download.content.len() as u64 > x)
+            && !asset.is_psyche_lock()
+        {
+            // This asset is too large to be embedded, so it's put next to the HTML file.
+            let dir = sidecar_dir(&asset.output_path);
+            let name = AssetCollector::new_filename(
+                download.target_url.as_str(),
+                Path::new(&download.filename()),
+            );
+            self.ctx.writer.create_dir_all(&dir).await?;
+            self.ctx
+                .writer
+                .write(&dir.join(&name), &download.content)
+                .await?;
+            let url = [dir.as_path(), &name]
+                .iter()
+                .filter_map(|x| x.file_name()?.to_str())
+                .map(urlencoding::encode)
+                .join("/");
+            asset.path.set(url).expect("path must not be set already");
+        } else if self.ctx.args.one_html_file
+            || self.ctx.args.embed_short_audio
+                && asset.path.get().is_none()
+                && asset.is_embedded_audio(download.content.len())
+        {
             // No need to write data anywhere but in the data URL.
-            asset
-                .path
-                .set(download.make_data_url())
-                .expect("path must not be set already");
+            let url = if self.ctx.args.one_html_file && self.ctx.args.compress_assets {
+                self.ctx.asset_pack.add(
+                    hash(&asset.url),
+                    &download.filename(),
+                    download.mime_type().unwrap_or("application/octet-stream"),
+                    &download.content,
+                )?
+            } else {
+                download.make_data_url()
+            };
+            asset.path.set(url).expect("path must not be set already");
         } else if let Some(path) = asset.path.get() {
             // We need to reuse the existing path here.
+            let path = PathBuf::from(path);
             self.ctx
                 .writer
-                .write_asset(&PathBuf::from(path), &download.content)
+                .write_asset(&path, &download.content)
                 .await?;
+            self.written.lock().unwrap().push(path);
         } else {
             let path = AssetCollector::new_path(
                 &asset.output_path,
@@ -530,6 +714,7 @@ impl<'a> AssetDownloader<'a> {
                 .writer
                 .write_asset(&path, &download.content)
                 .await?;
+            self.written.lock().unwrap().push(path.clone());
             asset
                 .path
                 .set(
@@ -569,9 +754,13 @@ impl<'a> AssetDownloader<'a> {
             let original_path = PathBuf::from(path);
             let original_name = original_path.file_name().expect("must have file path");
             let writer = &self.ctx.writer;
+            let links: Vec<_> = (1..=max_locks)
+                .map(|i| original_path.with_file_name(format!("{name}_{i}.gif")))
+                .collect();
+            self.written.lock().unwrap().extend(links.iter().cloned());
             join_all(
-                (1..=max_locks)
-                    .map(|i| original_path.with_file_name(format!("{name}_{i}.gif")))
+                links
+                    .into_iter()
                     .map(|p| async move {
                         (
                             p.clone(),
@@ -595,6 +784,9 @@ impl<'a> AssetDownloader<'a> {
     /// Collects the case asset download requests for the given [case] and [`site_data`], returning
     /// the (possibly faulty) requests in a vector.
     ///
+    /// Assets of the categories in [`omit_assets`](crate::args::Args::omit_assets) are left out
+    /// here already, see [`AssetCollector::collect_download`].
+    ///
     /// *Note: This does not start the downloads yet!*
     pub(crate) async fn collect_case_data(
         &mut self,
@@ -629,7 +821,7 @@ impl<'a> AssetDownloader<'a> {
 
         self.collect_sounds(data, paths, case_id)?;
 
-        self.collect_voices(paths);
+        self.collect_voices(paths, self.ctx.args.voice_format);
 
         self.collect_psyche_locks(data, site_data).await;
 
@@ -949,12 +1141,11 @@ impl<'a> AssetDownloader<'a> {
         Ok(())
     }
 
-    /// Collects the voice assets used in the case (which are just all voice assets, since there
-    /// are no custom voices).
-    fn collect_voices(&mut self, paths: &SitePaths) {
-        const VOICE_EXT: [&str; 3] = ["opus", "wav", "mp3"];
+    /// Collects the voice assets used in the case (which are just all voice assets in the
+    /// configured [format], since there are no custom voices).
+    fn collect_voices(&mut self, paths: &SitePaths, format: VoiceFormat) {
         for i in 1..=3 {
-            for ext in VOICE_EXT {
+            for ext in format.extensions() {
                 self.collector.collect_download(
                     &Value::String(format!("voice_singleblip_{i}.{ext}")),
                     Some(paths.voice_path()),
@@ -1066,7 +1257,8 @@ impl<'a> AssetDownloader<'a> {
         Ok(())
     }
 
-    /// Downloads the given collected (possibly faulty) [downloads] in parallel.
+    /// Downloads the given collected (possibly faulty) [downloads] in parallel, and points the
+    /// references to omitted assets to their online location or a placeholder.
     pub(crate) async fn download_collected(
         &mut self,
         pb: &dyn ProgressReporter,
@@ -1074,13 +1266,25 @@ impl<'a> AssetDownloader<'a> {
         cases: &mut [Case],
         site_data: &mut SiteData,
     ) -> Result<()> {
+        let omitted: Vec<_> = self.collector.omitted.drain().collect();
+        if !omitted.is_empty() {
+            info!(
+                "Omitting {} asset{} ({}).",
+                omitted.len(),
+                if omitted.len() == 1 { "" } else { "s" },
+                self.ctx.args.omit_assets.iter().join(", ")
+            );
+        }
+        for asset in &omitted {
+            self.omit_asset(asset)?;
+        }
         pb.inc_length(downloads.len() as u64);
         let downloaded = self.download_assets(downloads, pb).await?;
 
         // We now need to write back the data URLs into the JSON.
         let mut case_map: HashMap<u32, &mut Case> = cases.iter_mut().map(|x| (x.id(), x)).collect();
-        for asset in downloaded {
-            Self::rewrite_data(&asset, &mut case_map, site_data);
+        for asset in downloaded.iter().chain(&omitted) {
+            Self::rewrite_data(asset, &mut case_map, site_data);
         }
         Ok(())
     }
@@ -1198,3 +1402,94 @@ impl<'a> AssetDownloader<'a> {
         used_default_sprites
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collector(omit: &[AssetCategory]) -> AssetCollector {
+        let mut collector = AssetCollector::new(
+            "https://aaonline.fr/icon.png".to_string(),
+            PathBuf::from("out"),
+            omit.iter().copied().collect(),
+        );
+        collector.target_case_title = Some("Case".to_string());
+        collector
+    }
+
+    fn collect(collector: &mut AssetCollector, url: &str, pointer: &str) {
+        collector.collect_download(
+            &Value::String(url.to_string()),
+            None,
+            None,
+            None,
+            JsonReference::for_case(1, pointer.to_string()),
+            false,
+        );
+    }
+
+    #[test]
+    fn omitted_categories_are_not_collected() {
+        let mut collector = collector(&[AssetCategory::Music]);
+        collect(&mut collector, "https://example.com/a.mp3", "/music/0/path");
+        collect(
+            &mut collector,
+            "https://example.com/b.png",
+            "/profiles/0/icon",
+        );
+        // The same asset is still downloaded for the references that aren't omitted.
+        collect(&mut collector, "https://example.com/c.mp3", "/music/1/path");
+        collect(
+            &mut collector,
+            "https://example.com/c.mp3",
+            "/sounds/0/path",
+        );
+
+        let urls =
+            |x: &HashSet<AssetDownload>| x.iter().map(|x| x.url.clone()).sorted().collect_vec();
+        assert_eq!(
+            urls(&collector.collected),
+            ["https://example.com/b.png", "https://example.com/c.mp3"]
+        );
+        assert_eq!(
+            urls(&collector.omitted),
+            ["https://example.com/a.mp3", "https://example.com/c.mp3"]
+        );
+        let omitted = collector
+            .omitted
+            .iter()
+            .find(|x| x.url.ends_with("c.mp3"))
+            .unwrap();
+        assert_eq!(
+            omitted.json_refs,
+            HashSet::from([JsonReference::for_case(1, "/music/1/path".to_string())])
+        );
+    }
+
+    #[test]
+    fn placeholders_match_categories() {
+        let mut collector = collector(&AssetCategory::ALL);
+        collect(&mut collector, "https://example.com/a.mp3", "/music/0/path");
+        collect(
+            &mut collector,
+            "https://example.com/b.png",
+            "/evidence/0/icon",
+        );
+        let placeholder = |url: &str| {
+            collector
+                .omitted
+                .iter()
+                .find(|x| x.url == url)
+                .unwrap()
+                .placeholder()
+        };
+        assert_eq!(
+            placeholder("https://example.com/a.mp3"),
+            AssetDownload::SILENT_AUDIO
+        );
+        assert_eq!(
+            placeholder("https://example.com/b.png"),
+            AssetDownload::EMPTY_IMAGE
+        );
+    }
+}
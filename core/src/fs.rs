@@ -1,14 +1,18 @@
 //! Structs and methods related to interaction with the filesystem.
 
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use async_trait::async_trait;
 use futures_util::TryFutureExt;
 use log::warn;
+use sanitize_filename::sanitize;
 use tokio::io;
 
 use crate::FileWriter;
+use crate::download::sidecar_dir;
+use crate::webapp::{MANIFEST_FILE, SERVICE_WORKER_FILE};
 
 /// A writer that uses the utilities provided by [`tokio::fs`] to asynchronously
 /// interact with the local filesystem.
@@ -73,6 +77,16 @@ impl FileWriter for TokioFsWriter {
                     );
                 }
             });
+            // Large assets may have been put next to the file.
+            let sidecar = sidecar_dir(output);
+            if let Err(e) = tokio::fs::remove_dir_all(&sidecar).await
+                && e.kind() != io::ErrorKind::NotFound
+            {
+                warn!(
+                    "Could not remove {}: {e}. Please remove it manually.",
+                    sidecar.display()
+                );
+            }
         } else if Path::new(output).is_dir() {
             // We need to remove the assets folder and the index.html file.
             tokio::fs::remove_dir_all(output.join("assets"))
@@ -88,6 +102,17 @@ impl FileWriter for TokioFsWriter {
                         );
                     }
                 });
+            // The case may also have been written as a web app.
+            for file in [MANIFEST_FILE, SERVICE_WORKER_FILE] {
+                if let Err(e) = tokio::fs::remove_file(output.join(file)).await
+                    && e.kind() != io::ErrorKind::NotFound
+                {
+                    warn!(
+                        "Could not remove {file} in {}: {e}. Please remove it manually.",
+                        output.display()
+                    );
+                }
+            }
         }
     }
 
@@ -99,3 +124,280 @@ impl FileWriter for TokioFsWriter {
         self
     }
 }
+
+/// Returns the default directory in which aaoffline caches data between runs.
+///
+/// This is an `aaoffline` directory within the user's cache directory, if there is one.
+#[must_use]
+pub fn default_cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|x| x.join("aaoffline"))
+}
+
+/// Splits the given relative [path] into its components, resolving any `.` and `..` components.
+///
+/// Returns `None` if the path is empty or would point outside of its root.
+fn relative_components(path: &str) -> Option<Vec<String>> {
+    let mut components: Vec<String> = Vec::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(x) => components.push(x.to_string_lossy().into_owned()),
+            Component::ParentDir => {
+                components.pop()?;
+            }
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+        }
+    }
+    Some(components).filter(|x| !x.is_empty())
+}
+
+/// An on-disk cache for the files of the AAO player, keyed by the commit they belong to.
+///
+/// Since a commit never changes, cached files never need to be invalidated.
+#[derive(Debug, Clone)]
+pub(crate) struct PlayerCache {
+    /// The directory containing the cached files for the commit.
+    dir: PathBuf,
+}
+
+impl PlayerCache {
+    /// The name of the file (within the cache root) that remembers which commit a branch or tag
+    /// was last resolved to.
+    const REFS_FILE: &str = "refs.json";
+
+    /// Returns the cache for the given [commit] within the given cache [root].
+    pub(crate) fn new(root: &Path, commit: &str) -> PlayerCache {
+        PlayerCache {
+            dir: root.join("player").join(sanitize(commit)),
+        }
+    }
+
+    /// Returns the cache for the files that the AAO instance at the given [host] serves for the
+    /// same commit.
+    ///
+    /// These are kept apart from the files of the repository itself (and from those of other
+    /// instances), since an instance may serve different files than the repository contains.
+    pub(crate) fn for_instance(&self, host: &str) -> PlayerCache {
+        let commit = self.dir.file_name().expect("cache must have a name");
+        PlayerCache {
+            dir: self.dir.with_file_name(format!(
+                "{}@{}",
+                commit.to_string_lossy(),
+                sanitize(host)
+            )),
+        }
+    }
+
+    /// Returns the location of the file at the given (relative) [path] within the cache.
+    ///
+    /// Returns `None` if the path would point outside of the cache.
+    fn file_path(&self, path: &str) -> Option<PathBuf> {
+        Some(
+            relative_components(path)?
+                .iter()
+                .fold(self.dir.clone(), |x, y| x.join(sanitize(y))),
+        )
+    }
+
+    /// Reads the file at the given [path] from the cache, if it is present.
+    pub(crate) async fn get(&self, path: &str) -> Option<Vec<u8>> {
+        tokio::fs::read(self.file_path(path)?).await.ok()
+    }
+
+    /// Stores the given [content] of the file at the given [path] in the cache.
+    pub(crate) async fn put(&self, path: &str, content: &[u8]) -> Result<()> {
+        let target = self
+            .file_path(path)
+            .with_context(|| format!("Path {path} cannot be cached."))?;
+        write_atomically(target, content.to_vec()).await
+    }
+
+    /// Reads the mapping from branch or tag names to commits from the given cache [root].
+    async fn read_refs(root: &Path) -> HashMap<String, String> {
+        tokio::fs::read(root.join("player").join(Self::REFS_FILE))
+            .await
+            .ok()
+            .and_then(|x| serde_json::from_slice(&x).ok())
+            .unwrap_or_default()
+    }
+
+    /// Returns the commit that the given [reference] was last resolved to, if known.
+    pub(crate) async fn cached_ref(root: &Path, reference: &str) -> Option<String> {
+        Self::read_refs(root).await.remove(reference)
+    }
+
+    /// Remembers that the given [reference] was resolved to the given [commit].
+    pub(crate) async fn store_ref(root: &Path, reference: &str, commit: &str) -> Result<()> {
+        let mut refs = Self::read_refs(root).await;
+        if refs.get(reference).is_some_and(|x| x == commit) {
+            return Ok(());
+        }
+        refs.insert(reference.to_string(), commit.to_string());
+        let path = root.join("player").join(Self::REFS_FILE);
+        write_atomically(path, serde_json::to_vec_pretty(&refs)?).await
+    }
+}
+
+/// Writes the given [content] to the file at the given [path], creating its parent directories.
+///
+/// The content is written to a uniquely named temporary file next to the target first, so that
+/// an interrupted run can't leave behind a truncated file that we'd then happily use in
+/// subsequent runs, and concurrent runs writing the same file don't interfere with each other.
+async fn write_atomically(path: PathBuf, content: Vec<u8>) -> Result<()> {
+    tokio::task::spawn_blocking(move || {
+        let dir = path.parent().expect("written file must have parent");
+        std::fs::create_dir_all(dir)?;
+        let mut temporary = tempfile::NamedTempFile::new_in(dir)?;
+        std::io::Write::write_all(&mut temporary, &content)?;
+        temporary.persist(&path)?;
+        Ok(())
+    })
+    .await?
+}
+
+/// A local copy of the AAO repository from which the files of the player are read.
+///
+/// This is either a git checkout, in which case files are read from a specific commit, or a
+/// plain directory, in which case files are read as they are.
+#[derive(Debug, Clone)]
+pub(crate) struct LocalCheckout {
+    /// The root directory of the local copy.
+    root: PathBuf,
+    /// The commit to read files from, if this is a git checkout.
+    commit: Option<String>,
+}
+
+impl LocalCheckout {
+    /// Opens the local copy of the AAO repository at the given [root].
+    ///
+    /// If it is a git checkout, the given [version] is resolved to a commit within it.
+    pub(crate) async fn open(root: &Path, version: &str) -> Result<LocalCheckout> {
+        if !tokio::fs::metadata(root)
+            .await
+            .with_context(|| format!("Could not access player source {}", root.display()))?
+            .is_dir()
+        {
+            anyhow::bail!("Player source {} is not a directory.", root.display());
+        }
+        let is_git = Self::git(root, &["rev-parse", "--git-dir"]).await.is_ok();
+        let commit = if is_git {
+            let output = Self::git(
+                root,
+                &[
+                    "rev-parse",
+                    "--verify",
+                    "--quiet",
+                    // The version may come from untrusted input (e.g., an API job), so it must
+                    // never be taken as an option.
+                    "--end-of-options",
+                    &format!("{version}^{{commit}}"),
+                ],
+            )
+            .await
+            .with_context(|| {
+                format!(
+                    "Player version {version} does not exist in the git repository at {}",
+                    root.display()
+                )
+            })?;
+            Some(String::from_utf8(output)?.trim().to_string())
+        } else {
+            warn!(
+                "Player source {} is not a git repository, so its files will be used as they are (ignoring the player version).",
+                root.display()
+            );
+            None
+        };
+        Ok(LocalCheckout {
+            root: root.to_path_buf(),
+            commit,
+        })
+    }
+
+    /// Returns the commit that files are read from, if this is a git checkout.
+    pub(crate) fn commit(&self) -> Option<&str> {
+        self.commit.as_deref()
+    }
+
+    /// Runs git with the given [args] within the given [root] and returns its standard output.
+    async fn git(root: &Path, args: &[&str]) -> Result<Vec<u8>> {
+        let output = tokio::process::Command::new("git")
+            .arg("-C")
+            .arg(root)
+            .args(args)
+            .output()
+            .await
+            .context("Could not run git")?;
+        if output.status.success() {
+            Ok(output.stdout)
+        } else {
+            Err(anyhow::anyhow!(
+                "git exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+        }
+    }
+
+    /// Reads the file at the given [path] (relative to the root of the repository).
+    pub(crate) async fn read(&self, path: &str) -> Result<Vec<u8>> {
+        let components = relative_components(path)
+            .with_context(|| format!("Invalid player file path {path}"))?;
+        if let Some(commit) = &self.commit {
+            // The `./` prefix makes the path relative to the given directory rather than to the
+            // root of the repository, in case the two differ.
+            Self::git(
+                &self.root,
+                &["show", &format!("{commit}:./{}", components.join("/"))],
+            )
+            .await
+            .with_context(|| format!("Could not read {path} from commit {commit}"))
+        } else {
+            let target = components.iter().fold(self.root.clone(), |x, y| x.join(y));
+            tokio::fs::read(&target)
+                .await
+                .with_context(|| format!("Could not read {}", target.display()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn concurrent_cache_writes_do_not_interfere() {
+        let root = tempfile::tempdir().unwrap();
+        let cache = PlayerCache::new(root.path(), "commit");
+        let contents: Vec<_> = (0..16u8).map(|x| vec![x; 64 * 1024]).collect();
+        futures_util::future::try_join_all(contents.iter().map(|x| cache.put("js/player.js", x)))
+            .await
+            .unwrap();
+        let cached = cache.get("js/player.js").await.unwrap();
+        assert!(contents.contains(&cached));
+        let files = std::fs::read_dir(root.path().join("player/commit/js")).unwrap();
+        assert_eq!(files.count(), 1, "temporary files must not be left behind");
+    }
+
+    #[tokio::test]
+    async fn instances_are_cached_separately() {
+        let root = tempfile::tempdir().unwrap();
+        let cache = PlayerCache::new(root.path(), "commit");
+        let mirror = cache.for_instance("mirror.example");
+        cache.put("CSS/player.css", b"repository").await.unwrap();
+        cache
+            .for_instance("aaonline.fr")
+            .put("CSS/player.css", b"instance")
+            .await
+            .unwrap();
+        assert_eq!(cache.get("CSS/player.css").await.unwrap(), b"repository");
+        assert_eq!(
+            cache
+                .for_instance("aaonline.fr")
+                .get("CSS/player.css")
+                .await
+                .unwrap(),
+            b"instance"
+        );
+        assert_eq!(mirror.get("CSS/player.css").await, None);
+    }
+}
@@ -3,47 +3,66 @@
 //! A downloader for Ace Attorney Online cases that allows them to be played offline.
 
 pub mod args;
+mod bundle;
 pub(crate) mod constants;
 pub(crate) mod data;
 pub(crate) mod download;
+mod library;
 mod middleware;
+mod pack;
+mod session;
+mod source;
 pub(crate) mod transform;
+mod webapp;
 
+#[cfg(feature = "fs")]
+pub mod daemon;
 #[cfg(feature = "fs")]
 pub mod fs;
 
 use anyhow::{Context, Result, anyhow};
 use args::Userscripts;
 use async_trait::async_trait;
+use bundle::Bundle;
 use colored::Colorize;
 use data::case::{Case, Sequence};
 use data::player::Player;
+use data::revision::Revision;
 use download::AssetDownloader;
 use futures_util::{StreamExt, TryFutureExt};
 use itertools::Itertools;
+use library::{CaseStatus, Library};
 use log::{Level, debug, info, warn};
 use middleware::AaofflineMiddleware;
+use pack::AssetPack;
 use reqwest::{Client, Url};
 use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
+use session::Session;
 use std::any::Any;
-use std::collections::{HashMap, HashSet};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::Debug;
 use std::io::{IsTerminal, stdin};
 use std::path::{Path, PathBuf};
-use std::sync::RwLock;
+use std::sync::{Arc, RwLock};
 use std::time::Duration;
 
+#[cfg(not(target_arch = "wasm32"))]
+use middleware::MirrorMiddleware;
 #[cfg(not(target_arch = "wasm32"))]
 use reqwest_retry::RetryTransientMiddleware;
 #[cfg(not(target_arch = "wasm32"))]
 use reqwest_retry::policies::ExponentialBackoff;
 
-use crate::args::SequenceErrorHandling;
+use crate::args::{LibraryCommand, SequenceErrorHandling};
+
+pub use download::sidecar_dir;
+pub use library::LIBRARY_FILE;
 
 /// The total number of steps that aaoffline needs to go through.
 pub const MAX_STEPS: u8 = 8;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct AaofflineClient {
     /// The base URL for Ace Attorney Online, which is used for constructing the URLs for requests.
     base_url: Url,
@@ -53,7 +72,12 @@ pub(crate) struct AaofflineClient {
 
 impl AaofflineClient {
     pub(crate) fn get(&self, url: &str) -> Result<reqwest_middleware::RequestBuilder> {
-        let url = if constants::re::AAONLINE_HOST_REGEX.is_match(url) {
+        Ok(self.inner.get(self.resolve(url)?))
+    }
+
+    /// Returns the full URL that a request to the given [url] would go to.
+    pub(crate) fn resolve(&self, url: &str) -> Result<String> {
+        Ok(if constants::re::AAONLINE_HOST_REGEX.is_match(url) {
             // If the URL is already a full URL to aaonline, we can just use it as is.
             url.to_string()
         } else {
@@ -62,8 +86,16 @@ impl AaofflineClient {
                 .join(url)
                 .with_context(|| format!("URL {url} is not a valid relative URL and could not be joined with base URL {}", self.base_url))?
                 .to_string()
-        };
-        Ok(self.inner.get(url))
+        })
+    }
+
+    /// Returns a client for the Ace Attorney Online instance at the given [base_url], sharing
+    /// the underlying HTTP client with this one.
+    pub(crate) fn for_host(&self, base_url: Url) -> AaofflineClient {
+        AaofflineClient {
+            base_url,
+            inner: self.inner.clone(),
+        }
     }
 }
 
@@ -78,8 +110,51 @@ pub(crate) struct GlobalContext {
     client: AaofflineClient,
     /// The [FileWriter] to use for handling files and directories.
     writer: Box<dyn FileWriter + Sync>,
+    /// The session on Ace Attorney Online used for requests made by the [client].
+    session: Arc<Session>,
     /// Mapping from case ID to output file.
     case_output_mapping: HashMap<u32, PathBuf>,
+    /// Mapping from case ID to output file for cases in a sequence that aren't being downloaded,
+    /// but were already downloaded before.
+    linked_outputs: HashMap<u32, PathBuf>,
+    /// The library that the cases are downloaded to, if it can be used.
+    library: Option<Library>,
+    /// The compressed assets to embed into single HTML files.
+    asset_pack: AssetPack,
+}
+
+impl GlobalContext {
+    /// Returns the name of the file or directory that the given [case] shall be written to.
+    fn case_name(&self, case: &Case) -> String {
+        self.args
+            .case_overrides
+            .get(&case.id())
+            .and_then(|x| x.output.as_deref())
+            .map_or_else(|| case.filename(), sanitize_filename::sanitize)
+    }
+
+    /// Returns the player version that shall be used for the given [case].
+    fn player_version(&self, case: &Case) -> &str {
+        self.args
+            .case_overrides
+            .get(&case.id())
+            .and_then(|x| x.player_version.as_deref())
+            .unwrap_or(&self.args.player_version)
+    }
+
+    /// Whether the cases shall be bundled into single HTML files.
+    fn bundles_cases(&self) -> bool {
+        self.args.one_html_file && self.args.bundle_cases
+    }
+
+    /// Returns the client for the Ace Attorney Online instance that the case with the given [id]
+    /// shall be retrieved from.
+    fn client_for_case(&self, id: u32) -> AaofflineClient {
+        self.args
+            .case_hosts
+            .get(&id)
+            .map_or_else(|| self.client.clone(), |x| self.client.for_host(x.clone()))
+    }
 }
 
 /// The main context for the program.
@@ -94,6 +169,12 @@ pub struct MainContext {
     dialog: RwLock<Box<dyn InteractiveDialog>>,
     /// The global context.
     global_ctx: Option<GlobalContext>,
+    /// The IDs of the cases that were written by the last run.
+    written_cases: Vec<u32>,
+    /// The asset files that were written for the cases that are currently being downloaded.
+    written_assets: Vec<PathBuf>,
+    /// The bundles that the cases are collected in before being written, keyed by their path.
+    bundles: BTreeMap<PathBuf, Bundle>,
 }
 
 /// An abstraction over writing to the file system.
@@ -161,6 +242,19 @@ pub trait ProgressReporter: Debug + Send + Sync {
 pub trait InteractiveDialog: Debug + Send + Sync {
     /// Ask the user to confirm the given yes/no [prompt], using [default_value] as the default choice.
     fn confirm(&mut self, prompt: &str, default_value: bool) -> Option<bool>;
+
+    /// Ask the user to select any number of the given [items] for the given [prompt], with the
+    /// items in [defaults] being selected initially.
+    ///
+    /// Returns the indices of the selected items. By default, the items in [defaults] are
+    /// selected without asking.
+    fn select(&mut self, _prompt: &str, items: &[String], defaults: &[bool]) -> Option<Vec<usize>> {
+        Some(
+            (0..items.len())
+                .filter(|&i| defaults.get(i).copied().unwrap_or_default())
+                .collect(),
+        )
+    }
 }
 
 impl MainContext {
@@ -193,6 +287,13 @@ impl MainContext {
         self.ctx().writer.as_ref()
     }
 
+    /// Returns the IDs of the cases that were written by the last call to
+    /// [`Self::run_all_steps`].
+    #[must_use]
+    pub fn written_cases(&self) -> &[u32] {
+        &self.written_cases
+    }
+
     fn pb(&self) -> &dyn ProgressReporter {
         self.pb.as_ref()
     }
@@ -227,6 +328,7 @@ impl MainContext {
         });
 
         let http_handling = args.http_handling;
+        let session = Arc::new(Session::new(&args));
         let mut builder = Client::builder().user_agent("aaoffline");
 
         #[cfg(not(target_arch = "wasm32"))]
@@ -242,9 +344,13 @@ impl MainContext {
         }
         let mut client_builder =
             ClientBuilder::new(builder.build().expect("client cannot be built"))
-                .with_init(AaofflineMiddleware::from(&args));
+                .with_init(AaofflineMiddleware::new(&args, Arc::clone(&session)));
         #[cfg(not(target_arch = "wasm32"))]
         {
+            // Mirrors are only failed over to once the retries of the current one were used up.
+            if let Some(mirrors) = MirrorMiddleware::new(&args, Arc::clone(&session)) {
+                client_builder = client_builder.with(mirrors);
+            }
             let retry_policy = ExponentialBackoff::builder().build_with_max_retries(args.retries);
             client_builder =
                 client_builder.with(RetryTransientMiddleware::new_with_policy(retry_policy));
@@ -263,9 +369,16 @@ impl MainContext {
                 writer,
                 output,
                 client,
+                session,
                 case_output_mapping: HashMap::new(),
+                linked_outputs: HashMap::new(),
+                library: None,
+                asset_pack: AssetPack::default(),
             }),
             dialog: RwLock::new(dialog),
+            written_cases: vec![],
+            written_assets: vec![],
+            bundles: BTreeMap::new(),
         }
     }
 
@@ -307,8 +420,8 @@ impl MainContext {
         if self.case_ids.len() == 1 {
             self.ctx().writer.delete_case_at(output).await;
         } else {
-            // Otherwise, we will remove the cases individually.
-            for filepath in self.ctx().case_output_mapping.values() {
+            // Otherwise, we will remove the cases individually (bundled cases share their file).
+            for filepath in self.ctx().case_output_mapping.values().unique() {
                 if self.ctx().args.one_html_file {
                     // Only need to delete the single file.
                     self.ctx().writer.delete_case_at(filepath).await;
@@ -331,26 +444,53 @@ impl MainContext {
 
     /// Retrieves the case information for all cases and possibly their sequences.
     async fn retrieve_case_infos(&mut self) -> Result<Vec<Case>> {
-        self.add_progress(self.case_ids.len() as u64);
+        self.add_progress((self.case_ids.len() + self.ctx().args.case_files.len()) as u64);
         // We temporarily move the context out of here to use its client freely.
         let ctx = self.global_ctx.take().expect("context must exist here");
-        let client = &ctx.client;
+        let requested: Vec<_> = self
+            .case_ids
+            .iter()
+            .map(|id| (*id, ctx.client_for_case(*id)))
+            .collect();
         let concurrent = ctx.args.concurrent_downloads;
         let mut abort_behavior = ctx.args.sequence_error_handling;
         let pb = self.pb();
         pb.inc(0);
 
-        let mut cases: HashSet<_> =
-            Self::download_case_infos_no_sequence(&self.case_ids, client, concurrent, pb)
-                .await
-                .into_iter()
-                .collect::<Result<HashSet<_>>>()?;
-
+        let mut requested_cases = Self::download_case_infos_no_sequence(&requested, concurrent, pb)
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>>>()?;
+        if let Some(spec) = &ctx.args.revision {
+            Self::revision_case_id(&ctx)?;
+            let case = requested_cases
+                .pop()
+                .expect("exactly one case must be requested");
+            requested_cases.push(Self::retrieve_revision(case, spec, &ctx).await?);
+        }
+        let mut cases: HashSet<_> = requested_cases.into_iter().collect();
+        cases.extend(Self::import_case_files(&ctx, pb).await?);
+        cases.extend(Self::retrieve_author_cases(&ctx, pb).await?);
+
+        // Cases in a sequence are retrieved from the same host as the case that referenced them.
+        let known_ids: HashSet<_> = cases.iter().map(Case::id).collect();
+        // Each sequence only needs to be handled once, even if several of its cases are known.
+        let mut known_sequences = HashSet::new();
         let additional = &cases
             .iter()
-            .map(|case| self.additional_cases(case, &ctx))
+            .filter(|case| {
+                case.case_information
+                    .sequence
+                    .as_ref()
+                    .is_none_or(|x| known_sequences.insert(x.entry_ids()))
+            })
+            .map(|case| {
+                let client = ctx.client.for_host(case.base_url.clone());
+                self.additional_cases(case, &known_ids, &ctx)
+                    .map(|ids| ids.into_iter().map(move |id| (id, client.clone())))
+            })
             .flatten_ok()
-            .collect::<Result<Vec<u32>>>()?;
+            .collect::<Result<Vec<_>>>()?;
         let pb = self.pb();
         pb.inc_length(additional.len() as u64);
         self.show_step_ctx(
@@ -359,7 +499,7 @@ impl MainContext {
             &ctx,
         );
         let additional_cases =
-            Self::download_case_infos_no_sequence(additional, client, concurrent, self.pb()).await;
+            Self::download_case_infos_no_sequence(additional, concurrent, self.pb()).await;
         for case in additional_cases {
             match (case, &abort_behavior) {
                 (Ok(new_case), _) => {
@@ -390,47 +530,226 @@ impl MainContext {
 
         // We also need to update our output filename(s).
         self.update_output_paths(&cases);
+        let root = self.library_root();
+        if !self.ctx().args.isolated
+            && (self.ctx().args.create_library || root.join(LIBRARY_FILE).is_file())
+        {
+            self.ctx_mut().library = Library::open(&root).await;
+        }
+        let cases = self.skip_unchanged_cases(cases);
+        self.link_existing_sequence_cases(&cases);
 
         self.finish_progress("All case information retrieved.".into());
         Ok(cases)
     }
 
-    /// Downloads the case information for the given [ids], without downloading the sequences.
+    /// Removes the cases from the given [cases] that are already in the library and haven't
+    /// changed since, if the arguments ask for this.
+    ///
+    /// The removed cases are linked to like other cases that were downloaded before.
+    fn skip_unchanged_cases(&mut self, cases: Vec<Case>) -> Vec<Case> {
+        let ctx = self.ctx_mut();
+        let Some(library) = ctx.library.as_ref().filter(|_| ctx.args.skip_unchanged) else {
+            return cases;
+        };
+        let (mut unchanged, mut changed): (Vec<_>, Vec<_>) = cases.into_iter().partition(|case| {
+            ctx.case_output_mapping
+                .get(&case.id())
+                .is_some_and(|output| library.is_unchanged(case, output, &ctx.args.omit_assets))
+        });
+        if ctx.bundles_cases() {
+            // Bundles are always written as a whole, so they need to contain their unchanged
+            // cases as well.
+            let rewritten: HashSet<_> = changed
+                .iter()
+                .filter_map(|x| ctx.case_output_mapping.get(&x.id()))
+                .collect();
+            let kept;
+            (kept, unchanged) = unchanged
+                .into_iter()
+                .partition(|x| rewritten.contains(&ctx.case_output_mapping[&x.id()]));
+            changed.extend(kept);
+        }
+        for case in unchanged {
+            debug!("Skipping case {case}, since it hasn't changed since it was downloaded.");
+            ctx.case_output_mapping.remove(&case.id());
+        }
+        changed
+    }
+
+    /// Returns the ID of the case whose revisions shall be used, making sure that exactly one
+    /// case was requested.
+    fn revision_case_id(ctx: &GlobalContext) -> Result<u32> {
+        match (ctx.args.cases.as_slice(), ctx.args.case_files.is_empty()) {
+            ([id], true) => Ok(*id),
+            _ => Err(anyhow!(
+                "Revisions can only be used when downloading exactly one case from Ace Attorney Online."
+            )),
+        }
+    }
+
+    /// Retrieves the revision matching the given [spec] of the given [case].
+    async fn retrieve_revision(case: Case, spec: &str, ctx: &GlobalContext) -> Result<Case> {
+        let client = ctx.client.for_host(case.base_url.clone());
+        let revisions =
+            Revision::list(case.id(), &client, ctx.args.revisions_url.as_deref()).await?;
+        let revision = Revision::select(revisions, spec)?;
+        debug!("Using revision {revision} of case {}.", case.id());
+        revision.retrieve(case, &client).await
+    }
+
+    /// Lists the available revisions of the requested case.
+    async fn list_revisions(&self) -> Result<()> {
+        let ctx = self.ctx();
+        let id = Self::revision_case_id(ctx)?;
+        let revisions = Revision::list(
+            id,
+            &ctx.client_for_case(id),
+            ctx.args.revisions_url.as_deref(),
+        )
+        .await?;
+        self.pb().finish_and_clear();
+        if revisions.is_empty() {
+            info!("No revisions of case {id} are available.");
+        } else {
+            info!(
+                "Available revisions of case {id}:\n{}",
+                revisions.iter().map(|x| format!("• {x}")).join("\n")
+            );
+        }
+        Ok(())
+    }
+
+    /// Imports the cases from the local trial files given in the arguments.
+    async fn import_case_files(
+        ctx: &GlobalContext,
+        pb: &dyn ProgressReporter,
+    ) -> Result<Vec<Case>> {
+        let mut cases = Vec::with_capacity(ctx.args.case_files.len());
+        for path in &ctx.args.case_files {
+            cases.push(Case::from_file(path, ctx.client.base_url.clone()).await?);
+            pb.inc(1);
+        }
+        Ok(cases)
+    }
+
+    /// Retrieves the cases of the author given in the arguments, skipping those that don't match
+    /// the author filter.
+    async fn retrieve_author_cases(
+        ctx: &GlobalContext,
+        pb: &dyn ProgressReporter,
+    ) -> Result<Vec<Case>> {
+        let Some(author) = ctx.args.author else {
+            return Ok(vec![]);
+        };
+        let ids: Vec<_> = Case::retrieve_author_case_ids(
+            author,
+            &ctx.client,
+            ctx.args.author_cases_url.as_deref(),
+        )
+        .await?
+        .into_iter()
+        .filter(|id| !ctx.args.cases.contains(id))
+        .map(|id| (id, ctx.client.clone()))
+        .collect();
+        pb.inc_length(ids.len() as u64);
+        let mut cases = Vec::with_capacity(ids.len());
+        for case in
+            Self::download_case_infos_no_sequence(&ids, ctx.args.concurrent_downloads, pb).await
+        {
+            match case {
+                Ok(case) if case.matches_author(author, &ctx.args.author_filter) => {
+                    cases.push(case);
+                }
+                Ok(case) => debug!(
+                    "Skipping case {} since it doesn't match the author filter.",
+                    case.id()
+                ),
+                Err(e) => warn!("Could not retrieve case of author: {e}\nContinuing anyway."),
+            }
+        }
+        if cases.is_empty() && ctx.args.cases.is_empty() {
+            return Err(anyhow!(
+                "No cases by author {author} matching the given filters could be found."
+            ));
+        }
+        Ok(cases)
+    }
+
+    /// Downloads the case information for the given [ids] using their respective clients,
+    /// without downloading the sequences.
     async fn download_case_infos_no_sequence(
-        ids: &[u32],
-        client: &AaofflineClient,
+        ids: &[(u32, AaofflineClient)],
         concurrent_conns: usize,
         pb: &dyn ProgressReporter,
     ) -> Vec<Result<Case>> {
-        futures_util::stream::iter(ids.iter().map(|x| Case::retrieve_from_id(*x, client)))
-            .buffer_unordered(concurrent_conns)
-            .inspect(|_| pb.inc(1))
-            .collect::<Vec<_>>()
-            .await
+        futures_util::stream::iter(
+            ids.iter()
+                .map(|(id, client)| Case::retrieve_from_id(*id, client)),
+        )
+        .buffer_unordered(concurrent_conns)
+        .inspect(|_| pb.inc(1))
+        .collect::<Vec<_>>()
+        .await
     }
 
     /// Retrieves additional cases that should be downloaded if the given [case] is part of a sequence.
     ///
     /// This is dependent on the value of the `sequence` field in the arguments.
-    fn additional_cases(&mut self, case: &Case, ctx: &GlobalContext) -> Result<Vec<u32>> {
+    /// Cases whose IDs are in [known_ids] are already being downloaded, so they aren't returned.
+    fn additional_cases(
+        &mut self,
+        case: &Case,
+        known_ids: &HashSet<u32>,
+        ctx: &GlobalContext,
+    ) -> Result<Vec<u32>> {
         // Check if the user wants to download the whole sequence this case is contained in.
         if let Some(sequence) = case.case_information.sequence.as_ref() {
             debug!("Sequence detected: {sequence}");
-            if match ctx.args.sequence {
-                args::DownloadSequence::Every => true,
-                args::DownloadSequence::Single => false,
-                args::DownloadSequence::Ask => self.ask_sequence(case, sequence)?,
-            } {
-                return Ok(sequence.entry_ids());
+            let missing: Vec<_> = sequence
+                .entry_ids()
+                .into_iter()
+                .filter(|x| !known_ids.contains(x))
+                .collect();
+            if missing.is_empty() {
+                debug!("All cases in the sequence are already being downloaded.");
+                return Ok(vec![]);
+            }
+            let selected = if let Some(selection) = &ctx.args.sequence_selection {
+                sequence
+                    .entries()
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, x)| selection.contains(i + 1, x.id))
+                    .map(|(_, x)| x.id)
+                    .filter(|x| missing.contains(x))
+                    .collect()
+            } else {
+                match ctx.args.sequence {
+                    args::DownloadSequence::Every => missing,
+                    args::DownloadSequence::Single => vec![],
+                    args::DownloadSequence::Ask => self.ask_sequence(case, sequence, &missing)?,
+                }
+            };
+            if !selected.is_empty() {
+                debug!("Downloading cases {selected:?} of the sequence.");
+                return Ok(selected);
             }
         }
         debug!("Not downloading sequence.");
         Ok(vec![])
     }
 
-    /// Asks the user whether they want to download the whole sequence.
-    fn ask_sequence(&self, case: &Case, sequence: &Sequence) -> Result<bool> {
+    /// Asks the user which of the [missing] cases in the given [sequence] they want to download.
+    fn ask_sequence(&self, case: &Case, sequence: &Sequence, missing: &[u32]) -> Result<Vec<u32>> {
         if stdin().is_terminal() {
+            let entries: Vec<_> = sequence
+                .entries()
+                .iter()
+                .enumerate()
+                .filter(|(_, x)| missing.contains(&x.id))
+                .collect();
+            let selected = RefCell::new(vec![]);
             let result = self.pb().suspend(&|| {
                 info!(
                     "The case \"{}\" is part of a sequence: {sequence}.",
@@ -440,18 +759,23 @@ impl MainContext {
                     info!("However, as there is only entry in this sequence, we will continue normally.");
                     return Some(false);
                 }
-                let result = self.dialog.write().unwrap().confirm("Do you want to download the other cases in this sequence too?", false);
+                let items: Vec<_> = entries.iter().map(|(i, x)| format!("{}. {x}", i + 1)).collect();
+                let result = self.dialog.write().unwrap().select("Which other cases in this sequence do you want to download too? (Press space to select, enter to confirm)", &items, &vec![false; items.len()]);
                 println!();
-                result
+                result.map(|x| selected.replace(x)).map(|_| true)
             });
-            if let Some(choice) = result {
-                Ok(choice)
+            if result.is_some() {
+                Ok(selected
+                    .into_inner()
+                    .into_iter()
+                    .filter_map(|x| entries.get(x).map(|(_, entry)| entry.id))
+                    .collect())
             } else {
                 Err(anyhow!("Download cancelled per user request."))
             }
         } else {
             debug!("stdin is not a terminal, not asking whether to download sequence.");
-            Ok(false)
+            Ok(vec![])
         }
     }
 
@@ -499,7 +823,8 @@ impl MainContext {
         let site_data = &mut player.site_data;
         let ctx = &player.scripts.ctx;
         let mut handler = AssetDownloader::new(ctx.output.clone(), site_data, ctx);
-        let multiple = cases.len() > 1;
+        // Cases from other hosts may be downloaded separately, so we need to check all of them.
+        let multiple = ctx.case_output_mapping.len() > 1;
         // We need to remember these because we overwrite them while collecting downloads,
         // and we may collect downloads more than once (for multiple cases), in which case we'd
         // try to download the modified paths, which we don't want.
@@ -512,7 +837,15 @@ impl MainContext {
                 .clone_from(&original_default_places);
             let output = if multiple {
                 // Case data needs to be put into the directory of that case.
-                ctx.output.join(PathBuf::from(case.filename().to_string()))
+                match ctx.case_output_mapping.get(&case.id()) {
+                    Some(path) if !ctx.args.one_html_file => path
+                        .parent()
+                        .expect("case output must have parent")
+                        .to_path_buf(),
+                    // Single files are identified by their own path.
+                    Some(path) => path.clone(),
+                    None => ctx.output.join(ctx.case_name(case)),
+                }
             } else {
                 ctx.output.clone()
             };
@@ -523,6 +856,8 @@ impl MainContext {
         let result = handler
             .download_collected(self.pb.as_ref(), downloads, cases, site_data)
             .await;
+        // Cases of earlier groups may share their output directory with these ones.
+        self.written_assets.extend(handler.written_files());
         if result.is_ok() {
             self.pb().finish_progress("Case data downloaded.".into());
         }
@@ -548,7 +883,8 @@ impl MainContext {
 
     /// Retrieves the userscripts and appends them to the player.
     async fn append_userscripts(&mut self) -> Result<()> {
-        let urls = Userscripts::all_urls(&self.ctx().args.with_userscripts);
+        let args = &self.ctx().args;
+        let urls = Userscripts::all_urls(&args.with_userscripts, args.userscripts_url.as_ref())?;
         if urls.is_empty() {
             return Ok(());
         }
@@ -616,15 +952,101 @@ impl MainContext {
         .await
     }
 
+    /// Writes the collected bundles to disk.
+    async fn write_bundles(&mut self) -> Result<()> {
+        for (path, bundle) in std::mem::take(&mut self.bundles) {
+            self.show_step(
+                8,
+                &format!("Writing bundle \"{}\" to disk...", path.display()),
+            );
+            let result = match bundle.render(&self.ctx().asset_pack) {
+                Ok(content) => self
+                    .ctx()
+                    .writer
+                    .create_dir_all(path.parent().unwrap())
+                    .and_then(|()| self.ctx().writer.write(&path, content.as_bytes()))
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "Could not write bundle to file {}. Please check your permissions.",
+                            path.display()
+                        )
+                    }),
+                Err(e) => Err(e),
+            };
+            self.clean_on_fail(result).await?;
+        }
+        Ok(())
+    }
+
+    /// Writes the web app files for the given [case] next to its player at [`output_path`], and
+    /// links them in the player.
+    async fn write_web_app(&mut self, case: &Case, output_path: &Path) -> Result<()> {
+        let tags = match webapp::write_web_app(
+            case,
+            output_path,
+            &self.written_assets,
+            &*self.ctx().writer,
+        )
+        .await
+        {
+            Ok(tags) => tags,
+            Err(e) => return self.clean_on_fail(Err(e)).await,
+        };
+        let content = self.player.as_mut().unwrap().content.as_mut().unwrap();
+        if let Some(head_end) = content.find("</head>") {
+            content.insert_str(head_end, &tags);
+        } else {
+            warn!("Could not find the end of the player's head, so the web app won't be linked.");
+        }
+        Ok(())
+    }
+
+    /// Returns the names of the files or directories that the given [cases] shall be written to,
+    /// keyed by case ID.
+    fn case_names(&self, cases: &[Case]) -> HashMap<u32, String> {
+        cases
+            .iter()
+            .map(|x| (x.id(), self.ctx().case_name(x)))
+            .collect()
+    }
+
     fn update_output_paths(&mut self, cases: &[Case]) {
-        let one_case = cases.len() == 1;
+        let Some(first) = cases.first() else {
+            // Nothing to put anywhere.
+            return;
+        };
+        if let Some(author) = self.ctx().args.author {
+            self.update_author_output_paths(author, cases);
+            return;
+        }
+        if self.ctx().bundles_cases() {
+            self.update_bundle_output_path(cases);
+            return;
+        }
+        let mut one_case = cases.len() == 1;
         let original_output = self.ctx().args.output.clone();
+        if one_case
+            && original_output.is_none()
+            && !self.ctx().args.isolated
+            && let Some(sequence) = &first.case_information.sequence
+            && sequence.entry_ids().into_iter().any(|id| {
+                id != first.id()
+                    && Self::find_existing_output(&Self::sequence_dir(sequence), id).is_some()
+            })
+        {
+            // Other cases of this sequence were downloaded before, so we put this one next to them.
+            debug!("Found existing cases of sequence \"{}\".", sequence.title);
+            one_case = false;
+        }
         let one_file = self.ctx().args.one_html_file;
+        let names = self.case_names(cases);
+        let first_name = names[&first.id()].clone();
         let output = &mut self.ctx_mut().output;
 
         if one_case && original_output.is_none() {
             // We need to update the output name, now that we know the title.
-            let mut name = cases.first().unwrap().filename();
+            let mut name = first_name;
             if one_file {
                 name += ".html";
             }
@@ -636,7 +1058,7 @@ impl MainContext {
                 .is_none_or(|x| !x.eq_ignore_ascii_case("html"))
         {
             if output.is_dir() {
-                *output = output.join(cases.first().unwrap().filename());
+                *output = output.join(&first_name);
             }
             output.set_extension("html");
         } else if !one_case
@@ -656,7 +1078,7 @@ impl MainContext {
                 .sequence
                 .as_ref()
                 .unwrap();
-            *output = PathBuf::from(&sequence.title.trim());
+            *output = Self::sequence_dir(sequence);
         } else if !one_case && original_output.is_none() {
             // Downloaded cases are not part of a single sequence.
             // We'll put them in the current directory.
@@ -672,13 +1094,370 @@ impl MainContext {
                 match (one_case, one_file) {
                     (true, true) => output.clone(),
                     (true, false) => output.join("index.html"),
-                    (false, true) => output.join(case.filename() + ".html"),
-                    (false, false) => output.join(case.filename()).join("index.html"),
+                    (false, true) => output.join(names[&case.id()].clone() + ".html"),
+                    (false, false) => output.join(&names[&case.id()]).join("index.html"),
                 },
             )
         }));
     }
 
+    /// Updates the output path for the given [cases] when they are bundled into a single file.
+    ///
+    /// Unless another output was given, the file is named after the sequence of the cases (or
+    /// the case, if there is only one).
+    fn update_bundle_output_path(&mut self, cases: &[Case]) {
+        let names = self.case_names(cases);
+        let name = match cases
+            .iter()
+            .map(|x| &x.case_information.sequence)
+            .all_equal_value()
+        {
+            Ok(Some(sequence)) if cases.len() > 1 => {
+                sanitize_filename::sanitize(sequence.title.trim())
+            }
+            _ if cases.len() == 1 => names[&cases[0].id()].clone(),
+            _ => String::from("cases"),
+        };
+        let ctx = self.ctx_mut();
+        let output = match ctx.args.output.clone() {
+            None => PathBuf::from(name + ".html"),
+            Some(mut output)
+                if output
+                    .extension()
+                    .is_none_or(|x| !x.eq_ignore_ascii_case("html")) =>
+            {
+                if output.is_dir() {
+                    output = output.join(name);
+                }
+                output.set_extension("html");
+                output
+            }
+            Some(output) => output,
+        };
+        ctx.output.clone_from(&output);
+        ctx.case_output_mapping
+            .extend(cases.iter().map(|x| (x.id(), output.clone())));
+    }
+
+    /// Finds the cases in the sequences of the given [cases] that aren't being downloaded, but
+    /// were downloaded to the same directory before, so that we can redirect to them.
+    ///
+    /// Bundles only redirect to the cases they contain, so nothing is linked for them (and neither
+    /// is anything for isolated downloads).
+    fn link_existing_sequence_cases(&mut self, cases: &[Case]) {
+        let ctx = self.ctx();
+        let mut linked = HashMap::new();
+        if ctx.bundles_cases() || ctx.args.isolated {
+            return;
+        }
+        for case in cases {
+            let (Some(sequence), Some(path)) = (
+                &case.case_information.sequence,
+                ctx.case_output_mapping.get(&case.id()),
+            ) else {
+                continue;
+            };
+            // Cases in a sequence are next to each other, whether they're directories or files.
+            let dir = if ctx.args.one_html_file {
+                path.parent()
+            } else {
+                path.parent().and_then(Path::parent)
+            }
+            .filter(|x| !x.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+            for id in sequence.entry_ids() {
+                if ctx.case_output_mapping.contains_key(&id) || linked.contains_key(&id) {
+                    continue;
+                }
+                if let Some(existing) = ctx
+                    .library
+                    .as_ref()
+                    .and_then(|x| x.output_of(id))
+                    .or_else(|| Self::find_existing_output(dir, id))
+                {
+                    debug!(
+                        "Linking case {id} of the sequence to {}.",
+                        existing.display()
+                    );
+                    linked.insert(id, existing);
+                }
+            }
+        }
+        self.ctx_mut().linked_outputs = linked;
+    }
+
+    /// Runs the given [command] on the library in the configured library directory.
+    async fn run_library_command(&self, command: LibraryCommand) -> Result<()> {
+        let root = self
+            .ctx()
+            .args
+            .library_dir
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("."));
+        let mut library = Library::load(&root).await?;
+        match command {
+            LibraryCommand::List => {
+                self.pb().finish_and_clear();
+                let sizes: Vec<_> = library
+                    .cases()
+                    .map(|x| library::case_size(&library.output(x)))
+                    .collect();
+                if sizes.is_empty() {
+                    info!("There are no cases in the library at {}.", root.display());
+                } else {
+                    info!(
+                        "Cases in the library at {} ({} in total):\n{}",
+                        root.display(),
+                        library::format_size(sizes.iter().sum()),
+                        library
+                            .cases()
+                            .zip(sizes)
+                            .map(|(x, size)| format!(
+                                "• {x}, {} at {}",
+                                library::format_size(size),
+                                library.output(x).display()
+                            ))
+                            .join("\n")
+                    );
+                }
+                Ok(())
+            }
+            LibraryCommand::Remove(id) => self.remove_from_library(&mut library, id).await,
+            LibraryCommand::Status => {
+                self.show_step(1, "Checking cases in the library...");
+                self.library_status(&library).await;
+                Ok(())
+            }
+        }
+    }
+
+    /// Removes the case with the given [id] from the given [library], along with its files.
+    async fn remove_from_library(&self, library: &mut Library, id: u32) -> Result<()> {
+        let ctx = self.ctx();
+        let case = library.unregister(id).with_context(|| {
+            format!(
+                "Case {id} is not in the library at {}.",
+                library.root().display()
+            )
+        })?;
+        let output = library.output(&case);
+        let bundled = library.cases().any(|x| library.output(x) == output);
+        if bundled {
+            // The file is still needed by the other cases in its bundle.
+            info!(
+                "Case {id} is bundled with other cases in {}, so the file is kept until all of them are removed.",
+                output.display()
+            );
+        } else {
+            // Like when cleaning up, cases are either a single file or a directory containing
+            // both an `index.html` and its assets.
+            let case_dir = output
+                .parent()
+                .filter(|_| output.file_name().is_some_and(|x| x == "index.html"));
+            ctx.writer.delete_case_at(case_dir.unwrap_or(&output)).await;
+            if let Some(dir) = case_dir.or_else(|| output.parent()) {
+                library::remove_empty_dirs(dir, library.root()).await;
+            }
+        }
+        // Other cases of the sequence shouldn't try to redirect to the removed case anymore.
+        for other in case
+            .sequence
+            .iter()
+            .flat_map(Sequence::entry_ids)
+            .filter_map(|x| library.output_of(x))
+            // Bundles can't be changed after they were written.
+            .filter(|x| *x != output)
+        {
+            if let Err(e) = library::update_redirections(&other, &[], &[id], &*ctx.writer).await {
+                warn!("Could not unlink removed case: {e:#}");
+            }
+        }
+        library.save(&*ctx.writer).await?;
+        self.pb().finish_and_clear();
+        info!("Removed case {case} from the library.");
+        Ok(())
+    }
+
+    /// Checks the cases in the given [library] against their current versions on Ace Attorney
+    /// Online and reports their status.
+    async fn library_status(&self, library: &Library) {
+        let ctx = self.ctx();
+        self.add_progress(library.cases().count() as u64);
+        let pb = self.pb();
+        let statuses: Vec<_> = futures_util::stream::iter(library.cases().map(|case| async move {
+            let client = Url::parse(&case.base_url)
+                .map_or_else(|_| ctx.client.clone(), |x| ctx.client.for_host(x));
+            (case, case.status(&client).await)
+        }))
+        .buffered(ctx.args.concurrent_downloads)
+        .inspect(|_| pb.inc(1))
+        .collect()
+        .await;
+        pb.finish_and_clear();
+        if statuses.is_empty() {
+            info!(
+                "There are no cases in the library at {}.",
+                library.root().display()
+            );
+            return;
+        }
+        let up_to_date = statuses
+            .iter()
+            .filter(|(_, status)| matches!(status, CaseStatus::UpToDate))
+            .count();
+        info!(
+            "Status of the cases in the library at {} ({up_to_date} of {} up to date):\n{}",
+            library.root().display(),
+            statuses.len(),
+            statuses
+                .iter()
+                .map(|(case, status)| format!("• {case}: {status}"))
+                .join("\n")
+        );
+    }
+
+    /// Returns the root directory of the library that the cases are downloaded to.
+    ///
+    /// This is the output directory, unless it was chosen automatically or only contains a
+    /// single case, in which case it's the directory containing it.
+    fn library_root(&self) -> PathBuf {
+        let ctx = self.ctx();
+        let single = ctx
+            .case_output_mapping
+            .values()
+            .any(|x| *x == ctx.output || *x == ctx.output.join("index.html"));
+        if single || ctx.args.output.is_none() {
+            ctx.output
+                .parent()
+                .filter(|x| !x.as_os_str().is_empty())
+                .map_or_else(|| PathBuf::from("."), Path::to_path_buf)
+        } else {
+            ctx.output.clone()
+        }
+    }
+
+    /// Returns the previously written cases that need to link to other cases of their sequence
+    /// once the given [cases] are written, along with the cases to link to.
+    fn sequence_links(&self, cases: &[Case]) -> Vec<(PathBuf, Vec<(u32, PathBuf)>)> {
+        let ctx = self.ctx();
+        let mut links = vec![];
+        for sequence in cases
+            .iter()
+            .filter_map(|x| x.case_information.sequence.as_ref())
+            .unique()
+        {
+            let members: Vec<(u32, PathBuf)> = sequence
+                .entry_ids()
+                .into_iter()
+                .filter_map(|id| {
+                    ctx.case_output_mapping
+                        .get(&id)
+                        .or_else(|| ctx.linked_outputs.get(&id))
+                        .map(|x| (id, x.clone()))
+                })
+                .collect();
+            links.extend(
+                members
+                    .iter()
+                    .filter(|(id, _)| ctx.linked_outputs.contains_key(id))
+                    .map(|(_, path)| (path.clone(), members.clone())),
+            );
+        }
+        links
+    }
+
+    /// Registers the given [cases] (along with their player files) in the library, and links
+    /// previously written cases to them using the given [links] (see [`Self::sequence_links`]).
+    async fn update_library(
+        &mut self,
+        cases: &[(Case, PathBuf)],
+        links: &[(PathBuf, Vec<(u32, PathBuf)>)],
+    ) {
+        let ctx = self.ctx_mut();
+        if let Some(library) = &mut ctx.library {
+            // Other runs may have registered cases in the meantime, which we must keep.
+            if let Some(current) = Library::open(library.root()).await {
+                *library = current;
+            }
+            for (case, output) in cases {
+                library.register(case, output, &ctx.args.omit_assets);
+            }
+            if let Err(e) = library.save(&*ctx.writer).await {
+                warn!("{e:#}");
+            }
+        }
+        for (output, targets) in links {
+            if let Err(e) = library::update_redirections(output, targets, &[], &*ctx.writer).await {
+                warn!("Could not link previously downloaded case to this sequence: {e:#}");
+            }
+        }
+    }
+
+    /// Returns the directory that the cases of the given [sequence] are put in by default (i.e.,
+    /// if no output was given), which is named after the sequence.
+    fn sequence_dir(sequence: &Sequence) -> PathBuf {
+        PathBuf::from(sanitize_filename::sanitize(sequence.title.trim()))
+    }
+
+    /// Returns the output of a previous download of the case with the given [id] in the given
+    /// [dir], if there is one.
+    ///
+    /// This relies on the default output names, which end with the ID of the case.
+    fn find_existing_output(dir: &Path, id: u32) -> Option<PathBuf> {
+        let suffix = format!("_{id}");
+        dir.read_dir()
+            .ok()?
+            .filter_map(Result::ok)
+            .map(|x| x.path())
+            .find_map(|path| {
+                let name = path.file_name()?.to_str()?;
+                if let Some(name) = name.strip_suffix(".html") {
+                    (name.ends_with(&suffix) && path.is_file()).then_some(path)
+                } else if name.ends_with(&suffix) {
+                    Some(path.join("index.html")).filter(|x| x.is_file())
+                } else {
+                    None
+                }
+            })
+    }
+
+    /// Updates the output paths for the given [cases] when downloading the cases of the given
+    /// [author].
+    ///
+    /// The cases are put into a directory for the author (unless another output was given), with
+    /// a subdirectory for each sequence (or a single file, if cases are bundled).
+    fn update_author_output_paths(&mut self, author: u32, cases: &[Case]) {
+        let one_file = self.ctx().args.one_html_file;
+        let bundle = self.ctx().bundles_cases();
+        let names = self.case_names(cases);
+        let output = self.ctx().args.output.clone().unwrap_or_else(|| {
+            let name = cases
+                .iter()
+                .filter(|x| x.case_information.author_id == author)
+                .find_map(|x| x.case_information.author.clone())
+                .unwrap_or_else(|| format!("author_{author}"));
+            PathBuf::from(sanitize_filename::sanitize(name.trim()))
+        });
+        self.ctx_mut().output.clone_from(&output);
+
+        let cases_output = &mut self.ctx_mut().case_output_mapping;
+        cases_output.extend(cases.iter().map(|case| {
+            let parent = case.case_information.sequence.as_ref().map_or_else(
+                || output.clone(),
+                |x| output.join(sanitize_filename::sanitize(x.title.trim())),
+            );
+            let path = if let (true, Some(sequence)) = (bundle, &case.case_information.sequence) {
+                // All cases of a sequence share one file.
+                output.join(sanitize_filename::sanitize(sequence.title.trim()) + ".html")
+            } else if one_file {
+                parent.join(names[&case.id()].clone() + ".html")
+            } else {
+                parent.join(&names[&case.id()]).join("index.html")
+            };
+            (case.id(), path)
+        }));
+    }
+
     /// Runs aaoffline to completion, downloading the cases configured in this instance.
     ///
     /// # Panics
@@ -688,8 +1467,29 @@ impl MainContext {
     /// Since this function runs all steps consecutively, each of the errors that can occur for the
     /// individual steps can also occur here.
     pub async fn run_all_steps(&mut self) -> Result<()> {
+        if let Some(authentication) = &self.ctx().args.authentication {
+            let ctx = self.ctx();
+            ctx.session
+                .authenticate(authentication, &ctx.args, &ctx.client)
+                .await?;
+        }
+        if self.ctx().args.list_revisions {
+            self.show_step(1, "Retrieving case revisions...");
+            return self.list_revisions().await;
+        }
+        if let Some(command) = self.ctx().args.library_command {
+            return self.run_library_command(command).await;
+        }
         self.show_step(1, "Retrieving case information...");
-        let mut cases: Vec<_> = self.retrieve_case_infos().await?;
+        let cases: Vec<_> = self.retrieve_case_infos().await?;
+        self.written_cases.clear();
+        self.bundles.clear();
+        self.ctx().asset_pack.clear();
+        if cases.is_empty() {
+            self.pb()
+                .finish_progress("All cases are up to date.".bold().green().to_string());
+            return Ok(());
+        }
         let num_cases = cases.len();
         let one_case = num_cases == 1;
 
@@ -723,37 +1523,115 @@ impl MainContext {
                 .join("\n")
         );
 
-        self.show_step(2, "Retrieving site configuration...");
+        // Other parts of the sequences that were downloaded before need to link to the new ones.
+        let links = self.sequence_links(&cases);
+
+        // The player needs to be set up separately for each Ace Attorney Online instance and
+        // each player version.
+        let mut groups: Vec<((Url, String), Vec<Case>)> = Vec::new();
+        for case in cases {
+            let key = (
+                case.base_url.clone(),
+                self.ctx().player_version(&case).to_string(),
+            );
+            if let Some(group) = groups.iter_mut().find(|x| x.0 == key) {
+                group.1.push(case);
+            } else {
+                groups.push((key, vec![case]));
+            }
+        }
+        let multiple_hosts = groups.iter().map(|x| &x.0.0).unique().count() > 1;
+        let multiple_versions = groups.iter().map(|x| &x.0.1).unique().count() > 1;
+        let original_client = self.ctx().client.clone();
+        let original_version = self.ctx().args.player_version.clone();
+        let mut output_path = PathBuf::new();
+        let mut written = Vec::with_capacity(num_cases);
+        self.written_assets.clear();
+        for ((host, version), mut cases) in groups {
+            let mut suffix = String::new();
+            if multiple_hosts {
+                suffix.push_str(&format!(" for {host}"));
+            }
+            if multiple_versions {
+                suffix.push_str(&format!(" (player version {version})"));
+            }
+            let ctx = self.ctx_mut();
+            ctx.client = ctx.client.for_host(host);
+            ctx.args.player_version = version;
+            output_path = self.run_player_steps(&mut cases, &suffix).await?;
+            // We need the context back for the next group.
+            self.global_ctx = Some(self.player.take().expect("player must exist").scripts.ctx);
+            let ctx = self.ctx_mut();
+            ctx.client = original_client.clone();
+            ctx.args.player_version.clone_from(&original_version);
+            let mapping = &self.ctx().case_output_mapping;
+            written.extend(cases.into_iter().map(|x| {
+                let path = mapping[&x.id()].clone();
+                (x, path)
+            }));
+        }
+        self.write_bundles().await?;
+        self.update_library(&written, &links).await;
+        self.written_cases = written.iter().map(|(x, _)| x.id()).collect();
+
+        let message = if one_case {
+            format!(
+                "Case successfully written to \"{}\"!",
+                &output_path.display()
+            )
+        } else {
+            let output = if self.ctx().output == Path::new(".") {
+                "current directory"
+            } else {
+                &format!("directory \"{}\"", &self.ctx().output.display().to_string())
+            };
+            format!("{num_cases} cases successfully written to {output}!",)
+        };
+        self.pb()
+            .finish_progress(message.bold().green().to_string());
+        Ok(())
+    }
+
+    /// Runs the steps that set up the player for the given [cases] (which must all be from the
+    /// same host) and writes them to disk, returning the output path of the last case.
+    ///
+    /// The [suffix] is appended to the text of each step.
+    async fn run_player_steps(&mut self, cases: &mut [Case], suffix: &str) -> Result<PathBuf> {
+        let num_cases = cases.len();
+        self.show_step(2, &format!("Retrieving site configuration{suffix}..."));
         self.retrieve_site_config().await?;
 
         self.show_step(
             3,
             &format!(
-                "Downloading case assets{}... (This may take a while)",
-                if one_case {
+                "Downloading case assets{}{suffix}... (This may take a while)",
+                if num_cases == 1 {
                     String::new()
                 } else {
                     format!(" for {num_cases} cases")
                 }
             ),
         );
-        self.download_case_data(&mut cases).await?;
+        self.download_case_data(cases).await?;
 
-        self.show_step(4, "Retrieving player...");
+        self.show_step(4, &format!("Retrieving player{suffix}..."));
         self.retrieve_player().await?;
 
-        self.show_step(5, "Retrieving player scripts...");
+        self.show_step(5, &format!("Retrieving player scripts{suffix}..."));
         self.retrieve_player_scripts().await?;
 
-        self.show_step(6, "Retrieving additional external player sources...");
+        self.show_step(
+            6,
+            &format!("Retrieving additional external player sources{suffix}..."),
+        );
         self.retrieve_player_sources().await?;
 
-        self.show_step(7, "Applying userscripts...");
+        self.show_step(7, &format!("Applying userscripts{suffix}..."));
         self.append_userscripts().await?;
 
         let original_state = self.player.as_ref().unwrap().save();
-        let mut output_path: &PathBuf = &PathBuf::new();
-        for case in cases {
+        let mut output_path = PathBuf::new();
+        for case in cases.iter() {
             // Need to reset transformed player.
             self.show_step(
                 8,
@@ -766,30 +1644,37 @@ impl MainContext {
                 .as_mut()
                 .unwrap()
                 .restore(original_state.clone());
-            self.transform_player_blocks(&case).await?;
-            output_path = self
-                .ctx()
-                .case_output_mapping
-                .get(&case.id())
-                .expect("Unhandled case encountered");
-            self.output_player(output_path).await?;
+            self.transform_player_blocks(case).await?;
+            output_path.clone_from(
+                self.ctx()
+                    .case_output_mapping
+                    .get(&case.id())
+                    .expect("Unhandled case encountered"),
+            );
+            if self.ctx().args.web_app && !self.ctx().args.one_html_file {
+                self.write_web_app(case, &output_path).await?;
+            }
+            let compressed = self.ctx().args.one_html_file && self.ctx().args.compress_assets;
+            if self.ctx().bundles_cases() {
+                // Bundles are written once all of their cases are finished.
+                let player = self.player.as_mut().unwrap().content.take().unwrap();
+                self.bundles
+                    .entry(output_path.clone())
+                    .or_default()
+                    .add(case, player, compressed);
+                continue;
+            }
+            if compressed {
+                let player = self.player.as_mut().unwrap();
+                let result = player
+                    .scripts
+                    .ctx
+                    .asset_pack
+                    .embed(player.content.as_mut().unwrap());
+                self.clean_on_fail(result).await?;
+            }
+            self.output_player(&output_path).await?;
         }
-
-        let message = if one_case {
-            format!(
-                "Case successfully written to \"{}\"!",
-                &output_path.display()
-            )
-        } else {
-            let output = if self.ctx().output == Path::new(".") {
-                "current directory"
-            } else {
-                &format!("directory \"{}\"", &self.ctx().output.display().to_string())
-            };
-            format!("{num_cases} cases successfully written to {output}!",)
-        };
-        self.pb()
-            .finish_progress(message.bold().green().to_string());
-        Ok(())
+        Ok(output_path)
     }
 }
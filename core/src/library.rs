@@ -0,0 +1,747 @@
+//! Contains methods related to the library of cases that were downloaded to a directory.
+//!
+//! Each library has a registry file at its root that keeps track of the downloaded cases, which
+//! allows later downloads to link to (and from) cases that were downloaded before, such as other
+//! parts of the same sequence.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+use std::iter;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use itertools::Itertools;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use serde_with::TimestampSeconds;
+
+use crate::args::AssetCategory;
+use crate::bundle::CASE_PARAMETER;
+use crate::constants::re;
+use crate::data::case::{Case, Sequence};
+use crate::data::html_escape;
+use crate::data::player::redirection_case;
+use crate::download::sidecar_dir;
+use crate::{AaofflineClient, FileWriter};
+
+/// The name of the registry file at the root of a library.
+pub const LIBRARY_FILE: &str = "aaoffline-library.json";
+
+/// The name of the index page at the root of a library.
+const INDEX_FILE: &str = "index.html";
+
+/// Marks index pages written by us, so that we never overwrite any other file.
+const INDEX_MARKER: &str = r#"<meta name="generator" content="aaoffline library">"#;
+
+/// The registry of the cases that were downloaded to a library directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Library {
+    /// The root directory of the library.
+    #[serde(skip)]
+    root: PathBuf,
+    /// The cases in the library, keyed by their ID.
+    cases: BTreeMap<u32, LibraryCase>,
+}
+
+/// A case that was downloaded to a library.
+#[serde_with::serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct LibraryCase {
+    /// The ID of the case.
+    pub(crate) id: u32,
+    /// The title of the case.
+    pub(crate) title: String,
+    /// The name of the author of the case.
+    pub(crate) author: Option<String>,
+    /// The ID of the author of the case.
+    pub(crate) author_id: u32,
+    /// The language of the case.
+    pub(crate) language: String,
+    /// The date the case was last edited when it was downloaded, if known.
+    #[serde_as(as = "Option<TimestampSeconds<i64>>")]
+    pub(crate) last_edit_date: Option<DateTime<Utc>>,
+    /// The sequence the case is contained in, if any.
+    pub(crate) sequence: Option<Sequence>,
+    /// The name of the backed-up revision of the case, if it isn't the current version.
+    pub(crate) revision: Option<String>,
+    /// The categories of assets that were omitted from the download of the case.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub(crate) omitted_assets: BTreeSet<AssetCategory>,
+    /// The base URL of the Ace Attorney Online instance the case was downloaded from.
+    pub(crate) base_url: String,
+    /// The path to the player file of the case, relative to the root of the library.
+    pub(crate) path: String,
+    /// The date the case was downloaded.
+    #[serde_as(as = "TimestampSeconds<i64>")]
+    pub(crate) downloaded: DateTime<Utc>,
+}
+
+impl Library {
+    /// Opens the library at the given [root], which is empty if it has no registry yet.
+    ///
+    /// Returns `None` if the registry exists, but can't be read.
+    #[cfg(feature = "fs")]
+    pub(crate) async fn open(root: &Path) -> Option<Library> {
+        let path = root.join(LIBRARY_FILE);
+        let mut library = match tokio::fs::read(&path).await {
+            Ok(content) => match serde_json::from_slice(&content) {
+                Ok(library) => library,
+                Err(e) => {
+                    // We don't want to overwrite the registry in this case, so we ignore it.
+                    warn!(
+                        "Could not read library registry {}, so it won't be updated: {e}",
+                        path.display()
+                    );
+                    return None;
+                }
+            },
+            Err(_) => Library::default(),
+        };
+        library.root = root.to_path_buf();
+        Some(library)
+    }
+
+    /// Opens the library at the given [root].
+    ///
+    /// Without filesystem access, there is no library.
+    #[cfg(not(feature = "fs"))]
+    #[allow(clippy::unused_async)]
+    pub(crate) async fn open(_: &Path) -> Option<Library> {
+        None
+    }
+
+    /// Opens the existing library at the given [root].
+    pub(crate) async fn load(root: &Path) -> Result<Library> {
+        if !root.join(LIBRARY_FILE).is_file() {
+            bail!(
+                "There is no library at {} (it contains no {LIBRARY_FILE}).",
+                root.display()
+            );
+        }
+        Self::open(root)
+            .await
+            .with_context(|| format!("Could not open library at {}", root.display()))
+    }
+
+    /// Returns the root directory of this library.
+    pub(crate) fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the cases in this library, ordered by ID.
+    pub(crate) fn cases(&self) -> impl Iterator<Item = &LibraryCase> {
+        self.cases.values()
+    }
+
+    /// Removes the case with the given [id] from the registry of this library, returning it.
+    ///
+    /// This does not remove the files of the case.
+    pub(crate) fn unregister(&mut self, id: u32) -> Option<LibraryCase> {
+        self.cases.remove(&id)
+    }
+
+    /// Returns the player file of the given [case] in this library.
+    pub(crate) fn output(&self, case: &LibraryCase) -> PathBuf {
+        case.path
+            .split('/')
+            .fold(self.root.clone(), |x, y| x.join(y))
+    }
+
+    /// Returns the player file of the case with the given [id], if it's in this library and
+    /// still exists.
+    pub(crate) fn output_of(&self, id: u32) -> Option<PathBuf> {
+        let path = self.output(self.cases.get(&id)?);
+        path.is_file().then_some(path)
+    }
+
+    /// Returns the path of the given (existing) [output] relative to the root of this library,
+    /// or `None` if it isn't within the library.
+    fn path_of(&self, output: &Path) -> Option<String> {
+        // Symbolic links and `..` components could otherwise make paths seem to be outside of (or
+        // within) the library when they aren't.
+        let root = std::fs::canonicalize(&self.root).ok()?;
+        let output = std::fs::canonicalize(output).ok()?;
+        relative_path(&root, &output).filter(|x| !x.split('/').any(|x| x == ".."))
+    }
+
+    /// Whether the given [case] was already downloaded to the given [output] in this library
+    /// (with the same [omitted] asset categories), and hasn't been edited since.
+    pub(crate) fn is_unchanged(
+        &self,
+        case: &Case,
+        output: &Path,
+        omitted: &BTreeSet<AssetCategory>,
+    ) -> bool {
+        self.cases.get(&case.id()).is_some_and(|x| {
+            // Without an edit date, we can't know whether the case was edited since.
+            x.last_edit_date.is_some()
+                && x.last_edit_date == case.case_information.last_edit_date
+                && x.revision.is_none()
+                && case.revision.is_none()
+                && x.omitted_assets == *omitted
+                && self.path_of(output).is_some_and(|path| path == x.path)
+                && self.output(x).is_file()
+        })
+    }
+
+    /// Adds the given [case], whose player file was written to the given [output] without the
+    /// [omitted] asset categories, to this library (replacing any previous download of it).
+    pub(crate) fn register(
+        &mut self,
+        case: &Case,
+        output: &Path,
+        omitted: &BTreeSet<AssetCategory>,
+    ) {
+        let Some(path) = self.path_of(output) else {
+            debug!(
+                "Case at {} is not within library {}, not registering it.",
+                output.display(),
+                self.root.display()
+            );
+            return;
+        };
+        let info = &case.case_information;
+        self.cases.insert(
+            case.id(),
+            LibraryCase {
+                id: case.id(),
+                title: info.title.clone(),
+                author: info.author.clone(),
+                author_id: info.author_id,
+                language: info.language.clone(),
+                last_edit_date: info.last_edit_date,
+                sequence: info.sequence.clone(),
+                revision: case.revision.as_ref().map(|x| x.name.clone()),
+                omitted_assets: omitted.clone(),
+                base_url: case.base_url.to_string(),
+                path,
+                downloaded: Utc::now(),
+            },
+        );
+    }
+
+    /// Writes the registry and the index page of this library using the given [writer].
+    ///
+    /// Nothing is written for a new library without any cases, so that no directory becomes a
+    /// library unless a case was actually downloaded into it. Once all of its cases were removed,
+    /// the registry and the index page are removed as well.
+    pub(crate) async fn save(&self, writer: &dyn FileWriter) -> Result<()> {
+        let path = self.root.join(LIBRARY_FILE);
+        if self.cases.is_empty() {
+            if path.is_file() {
+                // The last case was removed, so the library itself goes as well.
+                self.remove().await;
+            } else {
+                debug!(
+                    "Not creating library at {}, since it contains no cases.",
+                    self.root.display()
+                );
+            }
+            return Ok(());
+        }
+        writer
+            .write(&path, &serde_json::to_vec_pretty(self)?)
+            .await
+            .with_context(|| format!("Could not write library registry {}", path.display()))?;
+
+        let index = self.root.join(INDEX_FILE);
+        if self.cases.values().any(|x| x.path == INDEX_FILE) {
+            debug!("Not writing library index, since a case was written to its location.");
+            return Ok(());
+        } else if index.exists()
+            && !read_player(&index)
+                .await
+                .is_ok_and(|x| x.contains(INDEX_MARKER))
+        {
+            warn!(
+                "Not writing library index, since {} already exists.",
+                index.display()
+            );
+            return Ok(());
+        }
+        writer
+            .write(&index, self.index_html().as_bytes())
+            .await
+            .with_context(|| format!("Could not write library index {}", index.display()))
+    }
+
+    /// Removes the registry and the index page (if we wrote it) of this library.
+    #[cfg(feature = "fs")]
+    async fn remove(&self) {
+        let index = self.root.join(INDEX_FILE);
+        let ours = read_player(&index)
+            .await
+            .is_ok_and(|x| x.contains(INDEX_MARKER));
+        for file in iter::once(self.root.join(LIBRARY_FILE)).chain(ours.then_some(index)) {
+            if let Err(e) = tokio::fs::remove_file(&file).await {
+                warn!(
+                    "Could not remove {}: {e}. Please remove it manually.",
+                    file.display()
+                );
+            }
+        }
+    }
+
+    /// Removes the registry and the index page of this library.
+    ///
+    /// Without filesystem access, there is nothing to remove.
+    #[cfg(not(feature = "fs"))]
+    #[allow(clippy::unused_async)]
+    async fn remove(&self) {}
+
+    /// Returns the index page of this library, which lists its cases grouped by author and
+    /// sequence, and allows searching through them.
+    fn index_html(&self) -> String {
+        let mut body = String::new();
+        let paths = self.cases.values().map(|x| &x.path).counts();
+        let authors = self
+            .cases
+            .values()
+            .into_group_map_by(|x| x.author.clone().unwrap_or_else(|| "Unknown author".into()));
+        for (author, cases) in authors
+            .into_iter()
+            .sorted_by_key(|(author, _)| author.to_lowercase())
+        {
+            body.push_str(&format!(
+                "<section class=\"author\">\n<h2>{}</h2>\n",
+                html_escape(&author)
+            ));
+            let sequences = cases
+                .into_iter()
+                .into_group_map_by(|x| x.sequence.as_ref().map(|x| x.title.clone()));
+            // Sequences come first (sorted by title), then the cases that aren't in one.
+            for (sequence, cases) in sequences
+                .into_iter()
+                .sorted_by_key(|(x, _)| (x.is_none(), x.as_ref().map(|x| x.to_lowercase())))
+            {
+                let heading = sequence.as_deref().map_or_else(
+                    || "Standalone cases".to_string(),
+                    |x| format!("Sequence: {}", html_escape(x)),
+                );
+                body.push_str(&format!(
+                    "<div class=\"group\">\n<h3>{heading}</h3>\n<table>\n<thead><tr><th>#</th><th>Title</th><th>Language</th><th>Last edited</th><th>Downloaded</th></tr></thead>\n<tbody>\n"
+                ));
+                for (position, case) in cases
+                    .into_iter()
+                    .map(|x| (x.sequence_position(), x))
+                    .sorted_by_key(|(position, x)| (*position, x.title.to_lowercase()))
+                {
+                    body.push_str(&case.index_row(position, &author, paths[&case.path] > 1));
+                }
+                body.push_str("</tbody>\n</table>\n</div>\n");
+            }
+            body.push_str("</section>\n");
+        }
+        format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+{INDEX_MARKER}
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Ace Attorney Online library</title>
+<style>
+body {{ font-family: sans-serif; margin: 2em auto; max-width: 60em; padding: 0 1em; }}
+#search {{ box-sizing: border-box; font-size: 1.1em; padding: 0.4em; width: 100%; }}
+table {{ border-collapse: collapse; width: 100%; }}
+th, td {{ border-bottom: 1px solid #ccc; padding: 0.3em 0.5em; text-align: left; }}
+td:first-child, th:first-child {{ width: 2em; }}
+.revision {{ color: #666; font-size: 0.9em; }}
+</style>
+</head>
+<body>
+<h1>Ace Attorney Online library</h1>
+<p>{count} case{plural} downloaded with aaoffline.</p>
+<input type="search" id="search" placeholder="Search by title, author, sequence, language or ID..." autofocus>
+<p id="no-results" hidden>No cases match your search.</p>
+{body}<script>
+document.getElementById('search').addEventListener('input', function () {{
+    var words = this.value.toLowerCase().split(/\s+/).filter(Boolean);
+    document.querySelectorAll('tbody tr').forEach(function (row) {{
+        var text = row.getAttribute('data-search');
+        row.hidden = !words.every(function (word) {{ return text.indexOf(word) !== -1; }});
+    }});
+    document.querySelectorAll('.group, .author').forEach(function (group) {{
+        group.hidden = !group.querySelector('tbody tr:not([hidden])');
+    }});
+    document.getElementById('no-results').hidden = !!document.querySelector('tbody tr:not([hidden])');
+}});
+</script>
+</body>
+</html>
+"#,
+            count = self.cases.len(),
+            plural = if self.cases.len() == 1 { "" } else { "s" },
+        )
+    }
+}
+
+impl Display for LibraryCase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "\"{}\" ({}) by {}",
+            self.title.bold(),
+            self.id,
+            self.author.as_deref().unwrap_or("unknown author")
+        )?;
+        if let (Some(sequence), Some(position)) = (&self.sequence, self.sequence_position()) {
+            write!(f, " (part {position} of sequence \"{}\")", sequence.title)?;
+        }
+        if let Some(revision) = &self.revision {
+            write!(f, " (revision {revision})")?;
+        }
+        if !self.omitted_assets.is_empty() {
+            write!(f, " (without {})", self.omitted_assets.iter().join(", "))?;
+        }
+        write!(
+            f,
+            " [{}, last edited on {}, downloaded on {}]",
+            self.language,
+            format_date(self.last_edit_date),
+            self.downloaded.format("%Y-%m-%d")
+        )
+    }
+}
+
+impl LibraryCase {
+    /// Checks this case against its current version on Ace Attorney Online, using the given
+    /// [client].
+    pub(crate) async fn status(&self, client: &AaofflineClient) -> CaseStatus {
+        match Case::retrieve_information(self.id, client).await {
+            Ok(None) => CaseStatus::Deleted,
+            Ok(Some(information)) if !information.can_read => CaseStatus::Inaccessible,
+            Ok(Some(information)) => match (information.last_edit_date, self.last_edit_date) {
+                (Some(current), Some(downloaded)) if current > downloaded => {
+                    CaseStatus::Outdated(current)
+                }
+                (Some(_), Some(_)) => CaseStatus::UpToDate,
+                (_, None) => CaseStatus::Unknown(anyhow!(
+                    "The downloaded version of the case has no edit date to compare against."
+                )),
+                (None, Some(_)) => CaseStatus::Unknown(anyhow!(
+                    "Ace Attorney Online did not report when the case was last edited."
+                )),
+            },
+            Err(e) => CaseStatus::Unknown(e),
+        }
+    }
+
+    /// Returns the position of this case in its sequence (starting at 1), if it's in one.
+    fn sequence_position(&self) -> Option<usize> {
+        self.sequence
+            .as_ref()?
+            .entry_ids()
+            .into_iter()
+            .position(|x| x == self.id)
+            .map(|x| x + 1)
+    }
+
+    /// Returns the row for this case (at the given [position] of its sequence, if any) in the
+    /// index page of the library, where it's listed under the given [author].
+    ///
+    /// If the case is [bundled] with other cases, the row links to it within its bundle.
+    fn index_row(&self, position: Option<usize>, author: &str, bundled: bool) -> String {
+        let mut href = self
+            .path
+            .split('/')
+            .map(|x| urlencoding::encode(x))
+            .join("/");
+        if bundled {
+            href.push_str(&format!("?{CASE_PARAMETER}={}", self.id));
+        }
+        let search = [
+            self.title.as_str(),
+            author,
+            self.sequence.as_ref().map_or("", |x| x.title.as_str()),
+            &self.language,
+            &self.id.to_string(),
+        ]
+        .join(" ")
+        .to_lowercase();
+        format!(
+            "<tr data-search=\"{}\"><td>{}</td><td><a href=\"{}\">{}</a>{}{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            html_escape(&search),
+            position.map(|x| x.to_string()).unwrap_or_default(),
+            html_escape(&href),
+            html_escape(&self.title),
+            self.revision.as_ref().map_or_else(String::new, |x| format!(
+                " <span class=\"revision\">(revision {})</span>",
+                html_escape(x)
+            )),
+            if self.omitted_assets.is_empty() {
+                String::new()
+            } else {
+                format!(
+                    " <span class=\"revision\">(without {})</span>",
+                    self.omitted_assets.iter().join(", ")
+                )
+            },
+            html_escape(&self.language),
+            format_date(self.last_edit_date),
+            self.downloaded.format("%Y-%m-%d %H:%M UTC"),
+        )
+    }
+}
+
+/// The state of a case in a library, compared to its current version on Ace Attorney Online.
+#[derive(Debug)]
+pub(crate) enum CaseStatus {
+    /// The case has not been edited since it was downloaded.
+    UpToDate,
+    /// The case was edited (last on the given date) since it was downloaded.
+    Outdated(DateTime<Utc>),
+    /// The case does not exist on Ace Attorney Online anymore.
+    Deleted,
+    /// The case exists, but can't be accessed anymore (e.g., because it was made private).
+    Inaccessible,
+    /// The state of the case could not be determined due to the given error.
+    Unknown(anyhow::Error),
+}
+
+impl Display for CaseStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CaseStatus::UpToDate => write!(f, "{}", "up to date".green()),
+            CaseStatus::Outdated(date) => write!(
+                f,
+                "{} (last edited on {})",
+                "outdated".yellow(),
+                date.format("%Y-%m-%d")
+            ),
+            CaseStatus::Deleted => write!(f, "{}", "deleted".red()),
+            CaseStatus::Inaccessible => write!(f, "{}", "inaccessible".red()),
+            CaseStatus::Unknown(e) => write!(f, "{} ({e})", "unknown".red()),
+        }
+    }
+}
+
+/// Returns the size (in bytes) of the case at the given [output] (its player file), including
+/// its assets.
+pub(crate) fn case_size(output: &Path) -> u64 {
+    /// Returns the size of the file or directory at the given [path].
+    fn size(path: &Path) -> u64 {
+        // Symbolic links (e.g., for psyche locks) don't take up any meaningful space.
+        match std::fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.is_dir() => std::fs::read_dir(path)
+                .map(|x| x.filter_map(Result::ok).map(|x| size(&x.path())).sum())
+                .unwrap_or_default(),
+            Ok(metadata) if metadata.is_file() => metadata.len(),
+            _ => 0,
+        }
+    }
+    if output.file_name().is_some_and(|x| x == "index.html")
+        && let Some(dir) = output.parent()
+    {
+        size(output) + size(&dir.join("assets"))
+    } else {
+        size(output) + size(&sidecar_dir(output))
+    }
+}
+
+/// Formats the given [size] (in bytes) for humans.
+pub(crate) fn format_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if size < 1024 {
+        return format!("{size} B");
+    }
+    #[allow(clippy::cast_precision_loss)] // Doesn't matter for display purposes.
+    let mut size = size as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// Formats the given edit [date] (which may be unknown) as a day.
+fn format_date(date: Option<DateTime<Utc>>) -> String {
+    date.map_or_else(
+        || String::from("unknown"),
+        |x| x.format("%Y-%m-%d").to_string(),
+    )
+}
+
+/// Removes the given [dir] and its ancestors up to (but excluding) the given [root], as long as
+/// they are empty.
+#[cfg(feature = "fs")]
+pub(crate) async fn remove_empty_dirs(dir: &Path, root: &Path) {
+    let mut current = Some(dir);
+    while let Some(dir) = current.filter(|x| *x != root && x.starts_with(root)) {
+        if tokio::fs::remove_dir(dir).await.is_err() {
+            // Not empty (or inaccessible), so we're done.
+            break;
+        }
+        current = dir.parent();
+    }
+}
+
+/// Removes the given [dir] and its ancestors, as long as they are empty.
+///
+/// Without filesystem access, this does nothing.
+#[cfg(not(feature = "fs"))]
+#[allow(clippy::unused_async)]
+pub(crate) async fn remove_empty_dirs(_: &Path, _: &Path) {}
+
+/// Updates the redirections in the previously written case at the given [output], so that the
+/// cases in [link] (IDs of cases and their player files) can be reached from it, while the cases
+/// in [unlink] can't be reached anymore.
+pub(crate) async fn update_redirections(
+    output: &Path,
+    link: &[(u32, PathBuf)],
+    unlink: &[u32],
+    writer: &dyn FileWriter,
+) -> Result<()> {
+    let content = read_player(output).await?;
+    let switch = re::REDIRECTION_SWITCH_REGEX
+        .captures(&content)
+        .with_context(|| format!("Could not find redirections in {}", output.display()))?;
+    let cases = switch.name("cases").expect("cases group must exist");
+    let existing: Vec<_> = re::REDIRECTION_CASE_REGEX
+        .captures_iter(cases.as_str())
+        .collect();
+    // The save passed along is the same for all redirections.
+    let save = existing
+        .first()
+        .map(|x| x["save"].to_string())
+        .with_context(|| format!("Could not find redirections in {}", output.display()))?;
+    let dir = output
+        .parent()
+        .filter(|x| !x.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+
+    let mut branches: Vec<String> = existing
+        .iter()
+        .filter(|x| {
+            !link
+                .iter()
+                .map(|(id, _)| id)
+                .chain(unlink)
+                .any(|id| x["id"] == id.to_string())
+        })
+        .map(|x| x[0].to_string())
+        .collect();
+    for (id, path) in link {
+        let target = relative_path(dir, path)
+            .with_context(|| format!("Could not link to case at {}", path.display()))?;
+        branches.push(redirection_case(
+            *id,
+            &format!("'{}'", target.replace('\'', "\\'")),
+            &save,
+        ));
+    }
+    let mut linked = content.clone();
+    linked.replace_range(cases.range(), &branches.concat());
+    if linked != content {
+        debug!("Updating redirections in {}...", output.display());
+        writer
+            .write(output, linked.as_bytes())
+            .await
+            .with_context(|| format!("Could not update {}", output.display()))?;
+    }
+    Ok(())
+}
+
+/// Reads the previously written player file at the given [path].
+#[cfg(feature = "fs")]
+async fn read_player(path: &Path) -> Result<String> {
+    tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("Could not read {}", path.display()))
+}
+
+/// Reads the previously written player file at the given [path].
+///
+/// Without filesystem access, this always fails.
+#[cfg(not(feature = "fs"))]
+#[allow(clippy::unused_async)]
+async fn read_player(path: &Path) -> Result<String> {
+    anyhow::bail!("Cannot read {} without filesystem access.", path.display())
+}
+
+/// Returns the path to the given [target] relative to the given [base] directory, separated by
+/// `/` (as used in URLs).
+///
+/// Returns `None` if there is no such path (e.g., because they're on different drives).
+pub(crate) fn relative_path(base: &Path, target: &Path) -> Option<String> {
+    let (base, target) = if base.is_absolute() == target.is_absolute() {
+        (base.to_path_buf(), target.to_path_buf())
+    } else {
+        (
+            std::path::absolute(base).ok()?,
+            std::path::absolute(target).ok()?,
+        )
+    };
+    if base.is_absolute() && base.components().next() != target.components().next() {
+        return None;
+    }
+    let normal = |x: &Path| -> Vec<String> {
+        x.components()
+            .filter(|x| *x != Component::CurDir)
+            .map(|x| x.as_os_str().to_string_lossy().into_owned())
+            .collect()
+    };
+    let (base, target) = (normal(&base), normal(&target));
+    let common = base.iter().zip(&target).take_while(|(a, b)| a == b).count();
+    Some(
+        iter::repeat_n("..".to_string(), base.len() - common)
+            .chain(target[common..].iter().cloned())
+            .join("/"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::relative_path;
+
+    #[test]
+    fn relative_path_between_directories() {
+        let cases = [
+            ("library", "library/Case_1/index.html", "Case_1/index.html"),
+            ("./library", "library/Case_1.html", "Case_1.html"),
+            (".", "Case_1/index.html", "Case_1/index.html"),
+            (
+                "library/Case_1",
+                "library/Case_2/index.html",
+                "../Case_2/index.html",
+            ),
+            ("a/b/c", "a/d/index.html", "../../d/index.html"),
+            (
+                "/srv/library",
+                "/srv/library/Case_1/index.html",
+                "Case_1/index.html",
+            ),
+            (
+                "/srv/library/Case_1",
+                "/srv/other/index.html",
+                "../../other/index.html",
+            ),
+            ("library", "library", ""),
+        ];
+        for (base, target, expected) in cases {
+            assert_eq!(
+                relative_path(Path::new(base), Path::new(target)).as_deref(),
+                Some(expected),
+                "path from {base} to {target}"
+            );
+        }
+    }
+
+    #[test]
+    fn relative_path_mixing_absolute_and_relative() {
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(
+            relative_path(&cwd.join("library"), Path::new("library/Case_1/index.html")).as_deref(),
+            Some("Case_1/index.html")
+        );
+        assert_eq!(
+            relative_path(Path::new("library"), &cwd.join("other/index.html")).as_deref(),
+            Some("../other/index.html")
+        );
+    }
+}
@@ -1,24 +1,44 @@
 //! Contains middleware for the [reqwest] client.
 
 use std::str::FromStr;
+use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::Mutex;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{Duration, Instant};
 
+#[cfg(not(target_arch = "wasm32"))]
+use http::Extensions;
+#[cfg(not(target_arch = "wasm32"))]
+use log::{debug, warn};
 use reqwest::Url;
+use reqwest::header::COOKIE;
+#[cfg(not(target_arch = "wasm32"))]
+use reqwest::{Request, Response};
 use reqwest_middleware::RequestInitialiser;
+#[cfg(not(target_arch = "wasm32"))]
+use reqwest_middleware::{Middleware, Next};
 
 use crate::args::Args;
+use crate::session::Session;
 
 /// A middleware that modifies outgoing HTTP requests from aaoffline.
 pub(crate) struct AaofflineMiddleware {
     /// Whether photobucket watermarks should be automatically removed.
     fix_photobucket: bool,
+    /// The partial URL of the proxy that all requests are routed through, if any.
     proxy: Option<String>,
+    /// The session whose cookies are sent along with requests to Ace Attorney Online.
+    session: Arc<Session>,
 }
 
-impl From<&Args> for AaofflineMiddleware {
-    fn from(args: &Args) -> Self {
+impl AaofflineMiddleware {
+    /// Creates a new middleware from the given [args], using the given [session].
+    pub(crate) fn new(args: &Args, session: Arc<Session>) -> Self {
         AaofflineMiddleware {
             fix_photobucket: !args.disable_photobucket_fix,
             proxy: args.proxy.clone(),
+            session,
         }
     }
 }
@@ -41,6 +61,13 @@ impl RequestInitialiser for AaofflineMiddleware {
             {
                 req = req.header("Referer", "https://photobucket.com/");
             }
+            // The session must never be sent to a proxy, which would get to see the cookies.
+            if self.proxy.is_none()
+                && let Some(cookies) = self.session.cookie_header(request.url())
+            {
+                req = req.header(COOKIE, cookies.clone());
+                request.headers_mut().insert(COOKIE, cookies);
+            }
             if let Some(proxy) = self.proxy.as_ref() {
                 let url = request.url_mut();
                 *url =
@@ -51,3 +78,235 @@ impl RequestInitialiser for AaofflineMiddleware {
         req
     }
 }
+
+/// A middleware that fails over to equivalent mirrors of the Ace Attorney Online host when a
+/// request to one of them fails.
+///
+/// Mirrors that failed are avoided for a while, so that not every request has to wait for a
+/// dead mirror first. This needs to run before retrying, so that a mirror is only given up on
+/// once its retries are exhausted.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) struct MirrorMiddleware {
+    /// The equivalent hosts, in the order in which they should be tried.
+    mirrors: Vec<Url>,
+    /// The proxy that all requests are routed through, if any.
+    proxy: Option<String>,
+    /// The session whose cookies are sent along with requests to the mirrors.
+    session: Arc<Session>,
+    /// When each of the [mirrors] last failed, if it did.
+    failures: Mutex<Vec<Option<Instant>>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl MirrorMiddleware {
+    /// How long to avoid a mirror after it failed.
+    const COOLDOWN: Duration = Duration::from_secs(60);
+
+    /// Creates a new mirror middleware from the given [args], if any mirrors are configured,
+    /// using the given [session].
+    ///
+    /// The base URL is always tried first.
+    pub(crate) fn new(args: &Args, session: Arc<Session>) -> Option<MirrorMiddleware> {
+        if args.mirrors.is_empty() {
+            return None;
+        }
+        let mut mirrors = vec![args.base_url.clone()];
+        mirrors.extend(
+            args.mirrors
+                .iter()
+                .filter(|x| **x != args.base_url)
+                .cloned(),
+        );
+        Some(MirrorMiddleware {
+            failures: Mutex::new(vec![None; mirrors.len()]),
+            mirrors,
+            proxy: args.proxy.clone(),
+            session,
+        })
+    }
+
+    /// Returns the index of the mirror that the given [url] points to, along with the rest of
+    /// the URL after the mirror.
+    fn split<'a>(&self, url: &'a str) -> Option<(usize, &'a str)> {
+        let url = self
+            .proxy
+            .as_deref()
+            .and_then(|x| url.strip_prefix(x))
+            .unwrap_or(url);
+        self.mirrors
+            .iter()
+            .enumerate()
+            .find_map(|(i, mirror)| url.strip_prefix(mirror.as_str()).map(|rest| (i, rest)))
+    }
+
+    /// Returns the indices of the mirrors in the order in which they should be tried, starting
+    /// with the given [original] one.
+    ///
+    /// Mirrors that recently failed are only tried after all others.
+    fn candidates(&self, original: usize) -> Vec<usize> {
+        let failures = self.failures.lock().expect("lock must not be poisoned");
+        let failed = |i: &usize| failures[*i].is_some_and(|x| x.elapsed() < Self::COOLDOWN);
+        let order =
+            std::iter::once(original).chain((0..self.mirrors.len()).filter(|x| *x != original));
+        let (failed, healthy): (Vec<_>, Vec<_>) = order.partition(failed);
+        healthy.into_iter().chain(failed).collect()
+    }
+
+    /// Returns a copy of the given [request] that is sent to the mirror with the given [index]
+    /// instead, where [rest] is the part of its URL after its original mirror.
+    ///
+    /// Returns `None` if the request can't be copied.
+    fn redirect(&self, request: &Request, index: usize, rest: &str) -> Option<Request> {
+        let mut attempt = request.try_clone()?;
+        let mirror_url = format!(
+            "{}{}{rest}",
+            self.proxy.as_deref().unwrap_or_default(),
+            self.mirrors[index]
+        );
+        debug!("Trying mirror URL {mirror_url}...");
+        *attempt.url_mut() = Url::from_str(&mirror_url).expect("mirror URL must be valid");
+        // The cookies were chosen for the original host, so the mirror may only get its own.
+        attempt.headers_mut().remove(COOKIE);
+        if self.proxy.is_none()
+            && let Some(cookies) = self.session.cookie_header(attempt.url())
+        {
+            attempt.headers_mut().insert(COOKIE, cookies);
+        }
+        Some(attempt)
+    }
+
+    /// Records whether the mirror with the given [index] has [failed].
+    fn record(&self, index: usize, failed: bool) {
+        let mut failures = self.failures.lock().expect("lock must not be poisoned");
+        if !failed {
+            failures[index] = None;
+        } else if failures[index].is_none_or(|x| x.elapsed() >= Self::COOLDOWN) {
+            warn!(
+                "Host {} seems to be unavailable, trying other mirrors.",
+                self.mirrors[index]
+            );
+            failures[index] = Some(Instant::now());
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[async_trait::async_trait]
+impl Middleware for MirrorMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let url = req.url().to_string();
+        let Some((original, rest)) = self.split(&url) else {
+            return next.run(req, extensions).await;
+        };
+        let mut last_result = None;
+        for index in self.candidates(original) {
+            let attempt = if index == original {
+                req.try_clone()
+            } else {
+                self.redirect(&req, index, rest)
+            };
+            let Some(attempt) = attempt else {
+                // Requests that can't be cloned can't be retried either.
+                return next.run(req, extensions).await;
+            };
+            let result = next.clone().run(attempt, extensions).await;
+            let failed = result.as_ref().is_ok_and(|x| x.status().is_server_error())
+                || result
+                    .as_ref()
+                    .is_err_and(|x| x.is_connect() || x.is_timeout());
+            self.record(index, failed);
+            if !failed {
+                return result;
+            }
+            last_result = Some(result);
+        }
+        last_result.expect("there must be at least one mirror")
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use reqwest::Method;
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    fn mirrors(proxy: Option<&str>) -> MirrorMiddleware {
+        let mirrors: Vec<_> = [
+            "https://aaonline.fr/",
+            "https://a.example/",
+            "https://b.example/",
+        ]
+        .into_iter()
+        .map(|x| Url::parse(x).unwrap())
+        .collect();
+        MirrorMiddleware {
+            failures: Mutex::new(vec![None; mirrors.len()]),
+            session: Arc::new(Session::for_hosts(&mirrors[0], mirrors[1..].iter())),
+            mirrors,
+            proxy: proxy.map(String::from),
+        }
+    }
+
+    #[test]
+    fn split_finds_the_mirror() {
+        let middleware = mirrors(None);
+        assert_eq!(
+            middleware.split("https://b.example/trial.js.php?trial_id=1"),
+            Some((2, "trial.js.php?trial_id=1"))
+        );
+        assert_eq!(middleware.split("https://other.example/music.mp3"), None);
+
+        let middleware = mirrors(Some("https://proxy.example/?url="));
+        assert_eq!(
+            middleware.split("https://proxy.example/?url=https://aaonline.fr/bridge.js.php"),
+            Some((0, "bridge.js.php"))
+        );
+    }
+
+    #[test]
+    fn failed_mirrors_are_tried_last_until_they_cool_down() {
+        let middleware = mirrors(None);
+        assert_eq!(middleware.candidates(0), [0, 1, 2]);
+        assert_eq!(middleware.candidates(1), [1, 0, 2]);
+
+        middleware.record(0, true);
+        assert_eq!(middleware.candidates(0), [1, 2, 0]);
+        middleware.record(1, true);
+        assert_eq!(middleware.candidates(0), [2, 0, 1]);
+
+        // Once the cooldown has passed, the mirror is tried in its usual place again.
+        middleware.failures.lock().unwrap()[0] =
+            Instant::now().checked_sub(MirrorMiddleware::COOLDOWN + Duration::from_secs(1));
+        assert_eq!(middleware.candidates(0), [0, 2, 1]);
+
+        // A successful request makes the mirror healthy right away.
+        middleware.record(1, false);
+        assert_eq!(middleware.candidates(0), [0, 1, 2]);
+    }
+
+    #[test]
+    fn redirect_replaces_host_and_cookies() {
+        let middleware = mirrors(None);
+        let mut request = Request::new(
+            Method::GET,
+            Url::parse("https://aaonline.fr/trial.js.php?trial_id=1").unwrap(),
+        );
+        request
+            .headers_mut()
+            .insert(COOKIE, HeaderValue::from_static("sid=secret"));
+        let attempt = middleware
+            .redirect(&request, 1, "trial.js.php?trial_id=1")
+            .unwrap();
+        assert_eq!(
+            attempt.url().as_str(),
+            "https://a.example/trial.js.php?trial_id=1"
+        );
+        assert_eq!(attempt.headers().get(COOKIE), None);
+    }
+}
@@ -0,0 +1,568 @@
+//! Contains the asset pack used for compressed single-file output.
+//!
+//! Instead of encoding each asset as a data URL wherever it is referenced, the assets are
+//! compressed and embedded only once into the HTML file, outside of any script. This keeps the
+//! player's scripts (and the case data within them) small, so that browsers don't have to parse
+//! megabytes of data URLs before the player can start. A small bootstrap script decodes and
+//! decompresses each asset on first use and hands out blob URLs for it.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use flate2::Compression;
+use flate2::write::DeflateEncoder;
+use log::{debug, warn};
+use regex::Captures;
+use serde_json::json;
+
+use crate::constants::re;
+
+/// The prefix of the placeholders that stand in for packed assets until the player is written.
+///
+/// Note that [`re::ASSET_PLACEHOLDER_REGEX`] needs to match this.
+const PLACEHOLDER_PREFIX: &str = "aaoffline-asset:";
+
+/// Defines `aaofflineAsset(key, variant)`, which returns a blob URL for the packed asset with the
+/// given key. Passing a variant returns a distinct URL for the same asset, which is needed for
+/// animations that should restart (such as psyche-locks).
+///
+/// Needs `AAOFFLINE_ASSETS` (mapping keys to the offset and length of the base64 text of each
+/// asset, along with its size, MIME type, extension, and whether it is deflated) to be defined
+/// before it. The text is read from the `aaoffline-assets` element right away (as bundles replace
+/// the page containing it, see [`crate::bundle`]), but each asset is only decoded when it is first
+/// used. This happens synchronously, since the player refers to its assets as soon as its scripts
+/// run.
+const BOOTSTRAP_JS: &str = r"
+var aaofflineAsset = (function () {
+    var LENGTH_BASE = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59,
+        67, 83, 99, 115, 131, 163, 195, 227, 258];
+    var LENGTH_EXTRA = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4,
+        5, 5, 5, 5, 0];
+    var DIST_BASE = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513,
+        769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+    var DIST_EXTRA = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10,
+        11, 11, 12, 12, 13, 13];
+    var CODE_LENGTH_ORDER = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+    var blobs = {};
+    var urls = {};
+
+    var element = document.getElementById('aaoffline-assets');
+    var packed = element.textContent;
+    element.textContent = '';
+
+    // Builds a canonical Huffman table from the given code lengths.
+    function huffman(lengths) {
+        var counts = new Uint16Array(16);
+        var offsets = new Uint16Array(16);
+        var symbols = new Uint16Array(lengths.length);
+        for (var i = 0; i < lengths.length; i++) {
+            counts[lengths[i]]++;
+        }
+        counts[0] = 0;
+        for (i = 1; i < 16; i++) {
+            offsets[i] = offsets[i - 1] + counts[i - 1];
+        }
+        for (i = 0; i < lengths.length; i++) {
+            if (lengths[i]) {
+                symbols[offsets[lengths[i]]++] = i;
+            }
+        }
+        return { counts: counts, symbols: symbols };
+    }
+
+    var fixedLiterals = null;
+    var fixedDistances = null;
+
+    // Decompresses the given raw DEFLATE stream into a buffer of the given size.
+    function inflate(src, size) {
+        var pos = 0;
+        var out = new Uint8Array(size);
+        var outPos = 0;
+        var bitBuffer = 0;
+        var bitCount = 0;
+
+        function bits(n) {
+            while (bitCount < n) {
+                bitBuffer |= src[pos++] << bitCount;
+                bitCount += 8;
+            }
+            var value = bitBuffer & ((1 << n) - 1);
+            bitBuffer >>>= n;
+            bitCount -= n;
+            return value;
+        }
+
+        function decode(table) {
+            var code = 0;
+            var first = 0;
+            var index = 0;
+            for (var length = 1; length < 16; length++) {
+                code |= bits(1);
+                var count = table.counts[length];
+                if (code < first + count) {
+                    return table.symbols[index + code - first];
+                }
+                index += count;
+                first = (first + count) << 1;
+                code <<= 1;
+            }
+            throw new Error('Invalid asset data');
+        }
+
+        var last;
+        do {
+            last = bits(1);
+            var type = bits(2);
+            if (type === 0) {
+                // Stored blocks start at the next byte.
+                bitBuffer = 0;
+                bitCount = 0;
+                var stored = src[pos] | (src[pos + 1] << 8);
+                var check = src[pos + 2] | (src[pos + 3] << 8);
+                pos += 4;
+                if ((stored ^ check) !== 0xffff || pos + stored > src.length) {
+                    throw new Error('Invalid asset data');
+                }
+                out.set(src.subarray(pos, pos + stored), outPos);
+                pos += stored;
+                outPos += stored;
+                continue;
+            }
+            var literals;
+            var distances;
+            if (type === 1) {
+                if (fixedLiterals === null) {
+                    var lengths = new Uint8Array(288);
+                    lengths.fill(8, 0, 144);
+                    lengths.fill(9, 144, 256);
+                    lengths.fill(7, 256, 280);
+                    lengths.fill(8, 280, 288);
+                    fixedLiterals = huffman(lengths);
+                    fixedDistances = huffman(new Uint8Array(30).fill(5));
+                }
+                literals = fixedLiterals;
+                distances = fixedDistances;
+            } else if (type === 2) {
+                var literalCount = bits(5) + 257;
+                var distanceCount = bits(5) + 1;
+                var codeLengthCount = bits(4) + 4;
+                var codeLengths = new Uint8Array(19);
+                for (var i = 0; i < codeLengthCount; i++) {
+                    codeLengths[CODE_LENGTH_ORDER[i]] = bits(3);
+                }
+                var codeLengthTable = huffman(codeLengths);
+                var dynamicLengths = new Uint8Array(literalCount + distanceCount);
+                i = 0;
+                while (i < dynamicLengths.length) {
+                    var symbol = decode(codeLengthTable);
+                    if (symbol < 16) {
+                        dynamicLengths[i++] = symbol;
+                        continue;
+                    }
+                    var previous = 0;
+                    var repeat;
+                    if (symbol === 16) {
+                        previous = dynamicLengths[i - 1];
+                        repeat = 3 + bits(2);
+                    } else if (symbol === 17) {
+                        repeat = 3 + bits(3);
+                    } else {
+                        repeat = 11 + bits(7);
+                    }
+                    while (repeat--) {
+                        dynamicLengths[i++] = previous;
+                    }
+                }
+                literals = huffman(dynamicLengths.subarray(0, literalCount));
+                distances = huffman(dynamicLengths.subarray(literalCount));
+            } else {
+                throw new Error('Invalid asset data');
+            }
+            for (;;) {
+                var value = decode(literals);
+                if (value < 256) {
+                    out[outPos++] = value;
+                } else if (value === 256) {
+                    break;
+                } else {
+                    value -= 257;
+                    var length = LENGTH_BASE[value] + bits(LENGTH_EXTRA[value]);
+                    var distance = decode(distances);
+                    distance = DIST_BASE[distance] + bits(DIST_EXTRA[distance]);
+                    for (; length > 0; length--, outPos++) {
+                        out[outPos] = out[outPos - distance];
+                    }
+                }
+            }
+        } while (!last);
+        return out;
+    }
+
+    // Decodes the given asset from its base64 text, inflating it if needed.
+    function unpack(asset) {
+        var text = atob(packed.substr(asset[0], asset[1]));
+        var bytes = new Uint8Array(text.length);
+        for (var i = 0; i < text.length; i++) {
+            bytes[i] = text.charCodeAt(i);
+        }
+        return asset[5] ? inflate(bytes, asset[2]) : bytes;
+    }
+
+    return function (key, variant) {
+        var id = variant === undefined ? key : key + '/' + variant;
+        if (!(id in urls)) {
+            var asset = AAOFFLINE_ASSETS[key];
+            if (!asset) {
+                return '';
+            }
+            if (!(key in blobs)) {
+                blobs[key] = new Blob([unpack(asset)], { type: asset[3] });
+            }
+            // Howler.js determines the format of sounds by their extension.
+            urls[id] = URL.createObjectURL(blobs[key]) + '#.' + asset[4];
+        }
+        return urls[id];
+    };
+})();
+";
+
+/// An asset stored in the [`AssetPack`].
+#[derive(Debug)]
+struct PackedAsset {
+    /// The MIME type of the asset.
+    mime: String,
+    /// The file extension of the asset.
+    extension: String,
+    /// The size of the uncompressed asset.
+    size: usize,
+    /// Whether the [data](PackedAsset::data) is deflated, which isn't the case for assets that
+    /// can't be compressed any further (e.g., most images and music).
+    deflated: bool,
+    /// The (possibly compressed) content of the asset.
+    data: Vec<u8>,
+}
+
+/// Compressed assets that are embedded into single HTML files.
+#[derive(Debug, Default)]
+pub(crate) struct AssetPack {
+    /// The packed assets, keyed by the hash of their URL.
+    assets: Mutex<HashMap<String, PackedAsset>>,
+}
+
+impl AssetPack {
+    /// Compresses and adds the given [content] (of the given [mime] type and saved under the
+    /// given [filename]) to this pack under the given [key], returning the placeholder that
+    /// stands in for its URL.
+    pub(crate) fn add(
+        &self,
+        key: u64,
+        filename: &str,
+        mime: &str,
+        content: &[u8],
+    ) -> Result<String> {
+        let key = format!("{key:016x}");
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(content)?;
+        let mut data = encoder.finish()?;
+        // Assets that don't get any smaller are kept as they are, which also saves inflating them.
+        let deflated = data.len() < content.len();
+        if !deflated {
+            data = content.to_vec();
+        }
+        let extension = Path::new(filename)
+            .extension()
+            .and_then(|x| x.to_str())
+            .unwrap_or("bin")
+            .to_lowercase();
+        self.assets.lock().unwrap().insert(
+            key.clone(),
+            PackedAsset {
+                mime: mime.to_string(),
+                extension,
+                size: content.len(),
+                deflated,
+                data,
+            },
+        );
+        Ok(format!("{PLACEHOLDER_PREFIX}{key}"))
+    }
+
+    /// Returns the key of the asset whose placeholder is the given [url], if it is one.
+    pub(crate) fn key(url: &str) -> Option<&str> {
+        url.strip_prefix(PLACEHOLDER_PREFIX)
+    }
+
+    /// Removes all assets from this pack.
+    pub(crate) fn clear(&self) {
+        self.assets.lock().unwrap().clear();
+    }
+
+    /// Replaces the placeholders in the given [player] by references to the packed assets, and
+    /// embeds the assets that are used by it (along with the bootstrap script) into it.
+    pub(crate) fn embed(&self, player: &mut String) -> Result<()> {
+        let used = Self::link(player);
+        self.insert(player, &used)
+    }
+
+    /// Replaces the placeholders in the given [player] by references to the packed assets,
+    /// returning the sorted keys of the assets that it uses.
+    pub(crate) fn link(player: &mut String) -> Vec<String> {
+        let replaced = re::ASSET_PLACEHOLDER_REGEX
+            .replace_all(player, |caps: &Captures| {
+                if caps[1] != caps[4] {
+                    // This isn't a string literal of its own, so we leave it alone.
+                    return caps[0].to_string();
+                }
+                let reference = format!("aaofflineAsset('{}')", &caps[3]);
+                if caps[2].is_empty() {
+                    reference
+                } else {
+                    // Point areas prefix their background with `val=`.
+                    format!("{0}{1}{0} + {reference}", &caps[1], &caps[2])
+                }
+            })
+            .into_owned();
+        let mut used: Vec<_> = re::ASSET_REFERENCE_REGEX
+            .captures_iter(&replaced)
+            .map(|x| x[1].to_string())
+            .collect();
+        used.sort_unstable();
+        used.dedup();
+        *player = replaced;
+        used
+    }
+
+    /// Embeds the packed assets with the given [used] keys (along with the bootstrap script) into
+    /// the given [document], before any of its scripts.
+    pub(crate) fn insert(&self, document: &mut String, used: &[String]) -> Result<()> {
+        let assets = self.assets.lock().unwrap();
+        // Each asset is encoded on its own, so that it can be decoded without the others.
+        let mut text = String::new();
+        let mut index = serde_json::Map::new();
+        let mut size = 0;
+        for key in used {
+            let Some(asset) = assets.get(key) else {
+                warn!("Packed asset {key} is missing, it won't be shown.");
+                continue;
+            };
+            let encoded = BASE64_STANDARD.encode(&asset.data);
+            index.insert(
+                key.clone(),
+                json!([
+                    text.len(),
+                    encoded.len(),
+                    asset.size,
+                    asset.mime,
+                    asset.extension,
+                    asset.deflated
+                ]),
+            );
+            text.push_str(&encoded);
+            size += asset.size;
+        }
+        debug!(
+            "Packed {} assets of {size} bytes into {} bytes of text.",
+            index.len(),
+            text.len()
+        );
+        let bootstrap = format!(
+            "<script type=\"application/octet-stream\" id=\"aaoffline-assets\">{text}</script>\n<script>var AAOFFLINE_ASSETS = {};{BOOTSTRAP_JS}</script>\n",
+            serde_json::to_string(&index)?,
+        );
+        // The assets need to be available before any of the player's scripts run.
+        let position = re::HEAD_START_REGEX
+            .find(document)
+            .map(|x| x.end())
+            .or_else(|| document.find("<script"))
+            .context("Could not find a place for the packed assets in the player.")?;
+        document.insert_str(position, &bootstrap);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::iter;
+    use std::process::{Command, Stdio};
+
+    use flate2::read::DeflateDecoder;
+
+    use super::*;
+
+    /// Returns pseudo-random bytes of the given [length], which can't be compressed.
+    fn random(length: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491_u32;
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state.to_le_bytes()[0]
+            })
+            .collect()
+    }
+
+    /// Returns text that compresses well, ending in a long run that uses the longest possible
+    /// match length.
+    fn text() -> Vec<u8> {
+        (0..5000)
+            .map(|i| {
+                format!(
+                    "Witness testimony #{i}: I saw the defendant at {}:00.\n",
+                    i % 24
+                )
+            })
+            .chain(iter::once("=".repeat(2000)))
+            .collect::<String>()
+            .into_bytes()
+    }
+
+    /// Packs the given [contents] into an empty document, returning it along with their keys.
+    fn pack(contents: &[Vec<u8>]) -> (AssetPack, String, Vec<String>) {
+        let pack = AssetPack::default();
+        for (i, content) in (0u64..).zip(contents) {
+            pack.add(i, "asset.bin", "application/octet-stream", content)
+                .unwrap();
+        }
+        let mut document = String::from("<html><head></head><body></body></html>");
+        let keys: Vec<_> = (0..contents.len()).map(|i| format!("{i:016x}")).collect();
+        pack.insert(&mut document, &keys).unwrap();
+        (pack, document, keys)
+    }
+
+    /// Returns the packed text and the index of the assets embedded into the given [document].
+    fn embedded(document: &str) -> (&str, serde_json::Map<String, serde_json::Value>) {
+        let text = document
+            .split_once("id=\"aaoffline-assets\">")
+            .unwrap()
+            .1
+            .split_once("</script>")
+            .unwrap()
+            .0;
+        let index = document
+            .split_once("var AAOFFLINE_ASSETS = ")
+            .unwrap()
+            .1
+            .split_once(";\nvar aaofflineAsset")
+            .unwrap()
+            .0;
+        (text, serde_json::from_str(index).unwrap())
+    }
+
+    /// Returns the contents of the assets packed into the given [document] (keyed as in
+    /// `AAOFFLINE_ASSETS`), as decompressed by the bootstrap script in Node.js.
+    fn unpack_with_node(document: &str) -> serde_json::Map<String, serde_json::Value> {
+        let (data, _) = embedded(document);
+        let script = document
+            .split_once("<script>")
+            .unwrap()
+            .1
+            .split_once("</script>")
+            .unwrap()
+            .0;
+        let test = format!(
+            r"
+            var document = {{ getElementById: () => ({{ textContent: '{data}' }}) }};
+            var blobs = [];
+            URL.createObjectURL = (blob) => blobs.push(blob) && 'blob:test';
+            {script}
+            (async () => {{
+                var result = {{}};
+                for (var key of Object.keys(AAOFFLINE_ASSETS)) {{
+                    aaofflineAsset(key);
+                    var content = await blobs[blobs.length - 1].arrayBuffer();
+                    result[key] = Buffer.from(content).toString('base64');
+                }}
+                console.log(JSON.stringify(result));
+            }})();
+            "
+        );
+        let mut node = Command::new("node")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Node.js is needed to run the bootstrap script");
+        node.stdin
+            .take()
+            .unwrap()
+            .write_all(test.as_bytes())
+            .unwrap();
+        let output = node.wait_with_output().unwrap();
+        assert!(output.status.success(), "bootstrap script failed");
+        serde_json::from_slice(&output.stdout).unwrap()
+    }
+
+    #[test]
+    fn assets_are_encoded_separately() {
+        let contents = [text(), random(10_000), b"Hold it!".to_vec()];
+        let (_, document, keys) = pack(&contents);
+        let (text, index) = embedded(&document);
+        let mut deflated = vec![];
+        for (key, content) in keys.iter().zip(&contents) {
+            let entry = index[key].as_array().unwrap();
+            let offset = entry[0].as_u64().unwrap() as usize;
+            let length = entry[1].as_u64().unwrap() as usize;
+            let data = BASE64_STANDARD
+                .decode(&text[offset..offset + length])
+                .unwrap();
+            let decoded = if entry[5].as_bool().unwrap() {
+                let mut decoded = vec![];
+                DeflateDecoder::new(data.as_slice())
+                    .read_to_end(&mut decoded)
+                    .unwrap();
+                decoded
+            } else {
+                data
+            };
+            assert_eq!(entry[2].as_u64().unwrap() as usize, content.len());
+            assert!(decoded == *content, "asset {key} was not packed correctly");
+            deflated.push(entry[5].as_bool().unwrap());
+        }
+        // Assets that can't be compressed are kept as they are.
+        assert_eq!(deflated, [true, false, false]);
+    }
+
+    #[test]
+    #[ignore = "needs Node.js"]
+    fn bootstrap_inflates_all_block_types() {
+        // Pseudo-random bytes can't be compressed, so they're put into (several) stored blocks.
+        let mixed = [random(150_000), text()].concat();
+        let contents = [
+            mixed,
+            b"Objection! Objection! Objection!".to_vec(),
+            text(),
+            random(1000),
+        ];
+        let (pack, document, keys) = pack(&contents);
+
+        let assets = pack.assets.lock().unwrap();
+        // The type of the first block is stored in the second and third bit.
+        let block_types: Vec<_> = keys[..3]
+            .iter()
+            .map(|x| (assets[x].data[0] >> 1) & 0b11)
+            .collect();
+        assert_eq!(
+            block_types,
+            [0, 1, 2],
+            "expected stored, fixed, and dynamic blocks"
+        );
+        assert!(!assets[&keys[3]].deflated);
+
+        let unpacked = unpack_with_node(&document);
+        for (key, content) in keys.iter().zip(&contents) {
+            let decoded = BASE64_STANDARD
+                .decode(unpacked[key].as_str().unwrap())
+                .unwrap();
+            assert!(
+                decoded == *content,
+                "asset {key} was not inflated correctly"
+            );
+        }
+    }
+}
@@ -0,0 +1,447 @@
+//! Contains methods related to authenticating with Ace Attorney Online.
+//!
+//! Session cookies are only ever sent to the hosts of Ace Attorney Online that are in use (and
+//! only to those matching the domain of each cookie), and are never written to the output or
+//! logged.
+
+use std::collections::HashSet;
+use std::sync::RwLock;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use log::{debug, info};
+use reqwest::Url;
+use reqwest::header::{COOKIE, HeaderValue, SET_COOKIE};
+#[cfg(not(target_arch = "wasm32"))]
+use reqwest_retry::RetryTransientMiddleware;
+#[cfg(not(target_arch = "wasm32"))]
+use reqwest_retry::policies::ExponentialBackoff;
+
+use crate::AaofflineClient;
+use crate::args::{Args, Authentication};
+use crate::constants::re;
+use crate::data::site::SitePaths;
+
+/// A session on Ace Attorney Online, consisting of the cookies that are sent along with requests
+/// to its hosts.
+pub(crate) struct Session {
+    /// The host of the main instance of Ace Attorney Online, which cookies without a domain
+    /// belong to.
+    base_host: String,
+    /// The hosts that the session cookies may be sent to.
+    hosts: HashSet<String>,
+    /// The session cookies.
+    cookies: RwLock<Vec<Cookie>>,
+}
+
+/// A cookie of a [Session].
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Cookie {
+    /// The domain the cookie belongs to, in lowercase and without a leading dot.
+    domain: String,
+    /// Whether the cookie is also sent to the subdomains of its [domain](Cookie::domain).
+    include_subdomains: bool,
+    /// The name of the cookie.
+    name: String,
+    /// The value of the cookie.
+    value: String,
+}
+
+impl Cookie {
+    /// Returns whether this cookie shall be sent to the given (lowercase) [host].
+    fn matches(&self, host: &str) -> bool {
+        host == self.domain
+            || (self.include_subdomains
+                && host
+                    .strip_suffix(&self.domain)
+                    .is_some_and(|x| x.ends_with('.')))
+    }
+}
+
+impl std::fmt::Debug for Session {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The cookies themselves must never end up in any logs.
+        f.debug_struct("Session")
+            .field("hosts", &self.hosts)
+            .field(
+                "cookies",
+                &format!(
+                    "<{} redacted>",
+                    self.cookies
+                        .read()
+                        .expect("lock must not be poisoned")
+                        .len()
+                ),
+            )
+            .finish()
+    }
+}
+
+impl Session {
+    /// Creates a new, empty session for the Ace Attorney Online hosts configured in [args].
+    pub(crate) fn new(args: &Args) -> Session {
+        Self::for_hosts(
+            &args.base_url,
+            args.case_hosts.values().chain(&args.mirrors),
+        )
+    }
+
+    /// Creates a new, empty session for the given [base_url] and the [other] hosts of Ace
+    /// Attorney Online.
+    pub(crate) fn for_hosts<'a>(
+        base_url: &'a Url,
+        other: impl Iterator<Item = &'a Url>,
+    ) -> Session {
+        Session {
+            base_host: base_url.host_str().unwrap_or_default().to_lowercase(),
+            hosts: std::iter::once(base_url)
+                .chain(other)
+                .filter_map(Url::host_str)
+                .map(str::to_lowercase)
+                .collect(),
+            cookies: RwLock::default(),
+        }
+    }
+
+    /// Returns the `Cookie` header that shall be sent along with a request to the given [url],
+    /// if any.
+    pub(crate) fn cookie_header(&self, url: &Url) -> Option<HeaderValue> {
+        let host = url.host_str()?.to_lowercase();
+        if !self.hosts.contains(&host) {
+            return None;
+        }
+        let cookies = self.cookies.read().expect("lock must not be poisoned");
+        let header = cookies
+            .iter()
+            .filter(|x| x.matches(&host))
+            .map(|x| format!("{}={}", x.name, x.value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        if header.is_empty() {
+            return None;
+        }
+        let mut header = HeaderValue::from_str(&header).ok()?;
+        header.set_sensitive(true);
+        Some(header)
+    }
+
+    /// Sets the given [cookie], replacing any previous value it had for its domain.
+    fn set_cookie(&self, cookie: Cookie) {
+        let mut cookies = self.cookies.write().expect("lock must not be poisoned");
+        cookies.retain(|x| x.name != cookie.name || x.domain != cookie.domain);
+        cookies.push(cookie);
+    }
+
+    /// Sets up this session using the given [authentication] method.
+    pub(crate) async fn authenticate(
+        &self,
+        authentication: &Authentication,
+        args: &Args,
+        client: &AaofflineClient,
+    ) -> Result<()> {
+        if args.proxy.is_some() {
+            // The proxy would get to see the session cookies (and possibly the password).
+            bail!("Authenticating with Ace Attorney Online is not possible when using a proxy.");
+        }
+        match authentication {
+            Authentication::CookieFile(path) => {
+                let content = Self::read_cookie_file(path).await?;
+                self.load_cookies(&content);
+                if self
+                    .cookies
+                    .read()
+                    .expect("lock must not be poisoned")
+                    .is_empty()
+                {
+                    bail!(
+                        "Cookie file {} does not contain any cookies for Ace Attorney Online.",
+                        path.display()
+                    );
+                }
+                debug!("Loaded session cookies from {}.", path.display());
+                Ok(())
+            }
+            Authentication::Login { username, password } => {
+                self.login(username, password, args, client).await?;
+                info!("Logged in to Ace Attorney Online as {username}.");
+                Ok(())
+            }
+        }
+    }
+
+    /// Reads the cookie file at the given [path].
+    #[cfg(feature = "fs")]
+    async fn read_cookie_file(path: &std::path::Path) -> Result<String> {
+        tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("Could not read cookie file {}", path.display()))
+    }
+
+    /// Reads the cookie file at the given [path].
+    ///
+    /// Without filesystem access, this always fails.
+    #[cfg(not(feature = "fs"))]
+    #[allow(clippy::unused_async)]
+    async fn read_cookie_file(path: &std::path::Path) -> Result<String> {
+        bail!(
+            "Cannot read cookie file {} without filesystem access.",
+            path.display()
+        )
+    }
+
+    /// Loads the cookies for our hosts from the given [content] of a cookie file.
+    ///
+    /// The file is either in the Netscape format (as exported by most browser extensions) or
+    /// simply contains the value of a `Cookie` header. The latter has no domains, so its cookies
+    /// are only sent to the main instance.
+    fn load_cookies(&self, content: &str) {
+        for line in content.lines().map(str::trim) {
+            // Cookies only sent via HTTP are prefixed like this, but are otherwise normal lines.
+            let line = line.strip_prefix("#HttpOnly_").unwrap_or(line);
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<_> = line.split('\t').collect();
+            if let [domain, subdomains, _, _, _, name, value] = fields[..] {
+                let cookie = Cookie {
+                    domain: domain.trim_start_matches('.').to_lowercase(),
+                    include_subdomains: domain.starts_with('.')
+                        || subdomains.eq_ignore_ascii_case("TRUE"),
+                    name: name.to_string(),
+                    value: value.to_string(),
+                };
+                if self.hosts.iter().any(|x| cookie.matches(x)) {
+                    self.set_cookie(cookie);
+                }
+            } else {
+                for (name, value) in line.split(';').filter_map(|x| x.trim().split_once('=')) {
+                    self.set_cookie(Cookie {
+                        domain: self.base_host.clone(),
+                        include_subdomains: false,
+                        name: name.to_string(),
+                        value: value.to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Logs in to the forum of Ace Attorney Online with the given [username] and [password],
+    /// storing the resulting session cookies.
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn login(
+        &self,
+        username: &str,
+        password: &str,
+        args: &Args,
+        client: &AaofflineClient,
+    ) -> Result<()> {
+        let site_paths = SitePaths::retrieve_from_bridge(client).await?;
+        let login_url = client
+            .base_url
+            .join(&format!("{}/", site_paths.forum_path.trim_end_matches('/')))?
+            .join("ucp.php?mode=login")?;
+        // We need to see the cookies that are set by redirect responses, so we can't use the
+        // usual client here.
+        let mut builder = reqwest::Client::builder()
+            .user_agent("aaoffline")
+            .redirect(reqwest::redirect::Policy::none());
+        if args.connect_timeout > 0 {
+            builder = builder.connect_timeout(Duration::from_secs(args.connect_timeout));
+        }
+        if args.read_timeout > 0 {
+            builder = builder.read_timeout(Duration::from_secs(args.read_timeout));
+        }
+        let login_client = reqwest_middleware::ClientBuilder::new(builder.build()?)
+            .with(RetryTransientMiddleware::new_with_policy(
+                ExponentialBackoff::builder().build_with_max_retries(args.retries),
+            ))
+            .build();
+
+        let login_page = login_client
+            .get(login_url.clone())
+            .send()
+            .await
+            .context("Could not reach the login page of Ace Attorney Online.")?
+            .error_for_status()
+            .context("Login page of Ace Attorney Online seems to be inaccessible.")?;
+        self.store_cookies(&login_page);
+        let login_page = login_page.text().await?;
+
+        // The login form contains hidden fields (e.g., to prevent CSRF) that we need to send back.
+        let mut form: Vec<(String, String)> = re::HIDDEN_INPUT_REGEX
+            .captures_iter(&login_page)
+            .map(|x| (x["name"].to_string(), x["value"].to_string()))
+            .filter(|(name, _)| name != "username" && name != "password")
+            .collect();
+        form.extend([
+            ("username".to_string(), username.to_string()),
+            ("password".to_string(), password.to_string()),
+            ("login".to_string(), "Login".to_string()),
+        ]);
+        let body = form
+            .iter()
+            .map(|(name, value)| {
+                format!(
+                    "{}={}",
+                    urlencoding::encode(name),
+                    urlencoding::encode(value)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("&");
+        let mut request = login_client
+            .post(login_url.clone())
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body);
+        if let Some(cookies) = self.cookie_header(&login_url) {
+            request = request.header(COOKIE, cookies);
+        }
+        let response = request
+            .send()
+            .await
+            .context("Could not log in to Ace Attorney Online.")?;
+        self.store_cookies(&response);
+
+        // The forum identifies anonymous users by the user ID 1.
+        let logged_in = self
+            .cookies
+            .read()
+            .expect("lock must not be poisoned")
+            .iter()
+            .any(|x| x.name.ends_with("_u") && x.value != "1" && !x.value.is_empty());
+        if !logged_in {
+            bail!(
+                "Could not log in to Ace Attorney Online. Please check your username and password."
+            );
+        }
+        Ok(())
+    }
+
+    /// Logs in to the forum of Ace Attorney Online.
+    ///
+    /// This is not supported in the browser, where the browser's own session is used instead.
+    #[cfg(target_arch = "wasm32")]
+    #[allow(clippy::unused_async)]
+    async fn login(&self, _: &str, _: &str, _: &Args, _: &AaofflineClient) -> Result<()> {
+        bail!("Logging in is not supported here.")
+    }
+
+    /// Stores the cookies set by the given [response].
+    #[cfg(not(target_arch = "wasm32"))]
+    fn store_cookies(&self, response: &reqwest::Response) {
+        let host = response.url().host_str().unwrap_or_default().to_lowercase();
+        for header in response.headers().get_all(SET_COOKIE) {
+            if let Some(cookie) = header
+                .to_str()
+                .ok()
+                .and_then(|x| Self::parse_set_cookie(x, &host))
+            {
+                self.set_cookie(cookie);
+            }
+        }
+    }
+
+    /// Parses the given value of a `Set-Cookie` [header] sent by the given [host].
+    ///
+    /// Returns `None` if it is invalid, or if it is meant for a domain the host doesn't belong
+    /// to.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn parse_set_cookie(header: &str, host: &str) -> Option<Cookie> {
+        let mut parts = header.split(';');
+        let (name, value) = parts.next()?.trim().split_once('=')?;
+        let domain = parts
+            .filter_map(|x| x.trim().split_once('='))
+            .find(|(key, _)| key.trim().eq_ignore_ascii_case("Domain"))
+            .map(|(_, domain)| domain.trim().trim_start_matches('.').to_lowercase());
+        let cookie = Cookie {
+            include_subdomains: domain.is_some(),
+            domain: domain.unwrap_or_else(|| host.to_string()),
+            name: name.trim().to_string(),
+            value: value.trim().to_string(),
+        };
+        cookie.matches(host).then_some(cookie)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> Session {
+        let mirror = Url::parse("https://mirror.example/").unwrap();
+        let instance = Url::parse("https://aao.example.org/").unwrap();
+        Session::for_hosts(
+            &Url::parse("https://aaonline.fr/").unwrap(),
+            [mirror, instance].iter(),
+        )
+    }
+
+    fn header(session: &Session, url: &str) -> Option<String> {
+        session
+            .cookie_header(&Url::parse(url).unwrap())
+            .map(|x| x.to_str().unwrap().to_string())
+    }
+
+    #[test]
+    fn netscape_cookies_only_go_to_their_domain() {
+        let session = session();
+        session.load_cookies(
+            "# Netscape HTTP Cookie File\n\
+             .aaonline.fr\tTRUE\t/\tTRUE\t0\tsid\tsecret\n\
+             #HttpOnly_mirror.example\tFALSE\t/\tTRUE\t0\tmirror_sid\tother\n\
+             tracker.example\tFALSE\t/\tFALSE\t0\ttrack\tme\n",
+        );
+        assert_eq!(
+            header(&session, "https://aaonline.fr/trial.js.php"),
+            Some("sid=secret".to_string())
+        );
+        assert_eq!(
+            header(&session, "https://mirror.example/trial.js.php"),
+            Some("mirror_sid=other".to_string())
+        );
+        assert_eq!(header(&session, "https://aao.example.org/"), None);
+        assert_eq!(header(&session, "https://tracker.example/"), None);
+        // Hosts that aren't ours never get any cookies, even if a cookie's domain matches.
+        assert_eq!(header(&session, "https://www.aaonline.fr/"), None);
+    }
+
+    #[test]
+    fn plain_cookies_only_go_to_the_base_url() {
+        let session = session();
+        session.load_cookies("sid=secret; user_u=42");
+        assert_eq!(
+            header(&session, "https://aaonline.fr/"),
+            Some("sid=secret; user_u=42".to_string())
+        );
+        assert_eq!(header(&session, "https://mirror.example/"), None);
+        assert_eq!(header(&session, "https://aao.example.org/"), None);
+    }
+
+    #[test]
+    fn set_cookie_respects_the_domain_attribute() {
+        assert_eq!(
+            Session::parse_set_cookie("sid=abc; Path=/; Domain=.aaonline.fr", "www.aaonline.fr"),
+            Some(Cookie {
+                domain: "aaonline.fr".to_string(),
+                include_subdomains: true,
+                name: "sid".to_string(),
+                value: "abc".to_string(),
+            })
+        );
+        assert_eq!(
+            Session::parse_set_cookie("sid=abc; HttpOnly", "aaonline.fr"),
+            Some(Cookie {
+                domain: "aaonline.fr".to_string(),
+                include_subdomains: false,
+                name: "sid".to_string(),
+                value: "abc".to_string(),
+            })
+        );
+        assert_eq!(
+            Session::parse_set_cookie("sid=abc; domain=mirror.example", "aaonline.fr"),
+            None
+        );
+    }
+}
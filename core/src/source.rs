@@ -0,0 +1,296 @@
+//! Contains methods related to retrieving the code of the Ace Attorney Online player.
+
+use anyhow::{Context, Result};
+#[cfg(feature = "fs")]
+use fnv::FnvHasher;
+use log::{debug, trace, warn};
+use reqwest::Url;
+use serde::Deserialize;
+#[cfg(feature = "fs")]
+use std::hash::Hasher;
+
+use crate::GlobalContext;
+use crate::constants::{BITBUCKET_API_URL, BITBUCKET_URL};
+use crate::download::Download;
+#[cfg(feature = "fs")]
+use crate::fs::{LocalCheckout, PlayerCache};
+
+/// The source from which the files of the AAO player are retrieved.
+#[derive(Debug)]
+pub(crate) enum PlayerSource {
+    /// The files are downloaded from a copy of the AAO repository that is available online
+    /// (by default, the official one on Bitbucket).
+    Remote {
+        /// The URL under which the files of the requested version of the AAO repository can be
+        /// found.
+        code_url: Url,
+        /// Whether any static files (i.e., anything except PHP templates) shall be retrieved from
+        /// the AAO instance itself instead of from the [code_url].
+        from_instance: bool,
+        /// The on-disk cache for the player files, if enabled.
+        ///
+        /// This is only used if the player version could be resolved to a concrete commit.
+        /// Files retrieved from the AAO instance itself are never cached, since we can't know which
+        /// commit the instance is running.
+        #[cfg(feature = "fs")]
+        cache: Option<PlayerCache>,
+    },
+    /// The files are read from a local copy of the AAO repository.
+    #[cfg(feature = "fs")]
+    Local(LocalCheckout),
+}
+
+/// The relevant part of a commit as returned by the Bitbucket API.
+#[derive(Debug, Deserialize)]
+struct BitbucketCommit {
+    /// The full hash of the commit.
+    hash: String,
+}
+
+/// Returns whether the given [version] is a full commit hash.
+fn is_commit_hash(version: &str) -> bool {
+    version.len() == 40 && version.chars().all(|x| x.is_ascii_hexdigit())
+}
+
+impl PlayerSource {
+    /// Creates a new player source for the player version configured in [ctx].
+    ///
+    /// The player version (e.g., a branch name) is resolved to a concrete commit first.
+    pub(crate) async fn new(ctx: &GlobalContext) -> Result<PlayerSource> {
+        if let Some(root) = &ctx.args.player_source {
+            #[cfg(feature = "fs")]
+            {
+                let checkout = LocalCheckout::open(root, &ctx.args.player_version).await?;
+                if let Some(commit) = checkout.commit() {
+                    debug!("Using player from commit {commit} of {}.", root.display());
+                }
+                return Ok(PlayerSource::Local(checkout));
+            }
+            #[cfg(not(feature = "fs"))]
+            anyhow::bail!(
+                "Cannot use local player source {} without filesystem access.",
+                root.display()
+            );
+        }
+        let version = if ctx.args.player_code_url.is_some() {
+            // We can only resolve versions for the official repository, so we'll have to use the
+            // version as it is here.
+            ctx.args.player_version.clone()
+        } else {
+            Self::resolve_version(ctx).await
+        };
+        let template = ctx
+            .args
+            .player_code_url
+            .clone()
+            .unwrap_or_else(|| format!("{BITBUCKET_URL}{{version}}/"));
+        let code_url = Self::code_url(&template, &version)?;
+        #[cfg(feature = "fs")]
+        let cache = ctx
+            .args
+            .player_cache_dir
+            .as_deref()
+            .filter(|_| is_commit_hash(&version))
+            .map(|root| {
+                if let Some(template) = &ctx.args.player_code_url {
+                    // Different repositories may have different contents for the same commit.
+                    // The hash needs to stay the same across versions of aaoffline (and Rust).
+                    let mut hasher = FnvHasher::default();
+                    hasher.write(template.as_bytes());
+                    PlayerCache::new(root, &format!("{version}-{:x}", hasher.finish()))
+                } else {
+                    PlayerCache::new(root, &version)
+                }
+            });
+        Ok(PlayerSource::Remote {
+            code_url,
+            from_instance: ctx.args.player_from_instance,
+            #[cfg(feature = "fs")]
+            cache,
+        })
+    }
+
+    /// Returns the URL under which the files of the given [version] can be found, according to
+    /// the given URL [template].
+    ///
+    /// The template may contain a `{version}` placeholder, which will be replaced by the version.
+    fn code_url(template: &str, version: &str) -> Result<Url> {
+        let mut url = template.replace("{version}", version);
+        if !url.ends_with('/') {
+            url.push('/');
+        }
+        Url::parse(&url).with_context(|| format!("Invalid player code URL {url}"))
+    }
+
+    /// Resolves the configured player version to a concrete commit.
+    ///
+    /// If the version cannot be resolved via the Bitbucket API, we fall back to the commit it was
+    /// last resolved to (if known), or to the version itself otherwise.
+    async fn resolve_version(ctx: &GlobalContext) -> String {
+        let version = &ctx.args.player_version;
+        if is_commit_hash(version) {
+            return version.to_lowercase();
+        }
+        match Self::retrieve_commit(ctx, version).await {
+            Ok(commit) => {
+                debug!("Player version {version} resolved to commit {commit}.");
+                #[cfg(feature = "fs")]
+                if let Some(root) = &ctx.args.player_cache_dir
+                    && let Err(e) = PlayerCache::store_ref(root, version, &commit).await
+                {
+                    warn!("Could not remember commit for player version {version}: {e}");
+                }
+                commit
+            }
+            Err(e) => {
+                #[cfg(feature = "fs")]
+                if let Some(root) = &ctx.args.player_cache_dir
+                    && let Some(commit) = PlayerCache::cached_ref(root, version).await
+                {
+                    warn!(
+                        "Could not resolve player version {version} ({e:#}), using previously resolved commit {commit} instead."
+                    );
+                    return commit;
+                }
+                warn!("Could not resolve player version {version} to a commit: {e:#}");
+                version.clone()
+            }
+        }
+    }
+
+    /// Retrieves the commit that the given [version] currently points to from the Bitbucket API.
+    async fn retrieve_commit(ctx: &GlobalContext, version: &str) -> Result<String> {
+        let response = ctx
+            .client
+            .get(&format!("{BITBUCKET_API_URL}{version}"))?
+            .send()
+            .await
+            .context("Could not reach the Bitbucket API")?
+            .error_for_status()
+            .context("Player version seems to be unknown")?
+            .bytes()
+            .await?;
+        let commit: BitbucketCommit =
+            serde_json::from_slice(&response).context("Could not parse commit information")?;
+        Ok(commit.hash)
+    }
+
+    /// Retrieves the file at the given [path] (relative to the root of the AAO repository).
+    pub(crate) async fn retrieve_file(&self, path: &str, ctx: &GlobalContext) -> Result<Download> {
+        match self {
+            PlayerSource::Remote {
+                from_instance: true,
+                ..
+            } if !path.ends_with(".php") => {
+                // PHP files are rendered by the instance, but we need their source code.
+                let url = ctx.client.base_url.join(path)?;
+                Download::retrieve_url(url.as_str(), &ctx.args.http_handling, &ctx.client).await
+            }
+            PlayerSource::Remote { code_url, .. } => {
+                let url = code_url.join(path)?;
+                self.retrieve_cached(path, url.as_str(), None, ctx).await
+            }
+            #[cfg(feature = "fs")]
+            PlayerSource::Local(checkout) => {
+                trace!("Reading player file {path} from local player source.");
+                let url = ctx.client.base_url.join(path)?;
+                Download::from_content(url.as_str(), checkout.read(path).await?)
+            }
+        }
+    }
+
+    /// Retrieves a resource of the player (such as CSS or language files) from the given [url],
+    /// which is usually served by Ace Attorney Online itself.
+    ///
+    /// Resources served from the AAO instance are part of the AAO repository, so they are cached
+    /// for the player's commit (or read from the local copy of the repository, if one is used).
+    /// Since instances may serve files that differ from the repository, the cache is kept
+    /// separately per instance. Any other resources are always downloaded.
+    pub(crate) async fn retrieve_resource(
+        &self,
+        url: &str,
+        ctx: &GlobalContext,
+    ) -> Result<Download> {
+        let full_url = ctx.client.base_url.join(url)?;
+        let Some(path) = Self::repository_path(&full_url, &ctx.client.base_url) else {
+            return Download::retrieve_url(url, &ctx.args.http_handling, &ctx.client).await;
+        };
+        match self {
+            PlayerSource::Remote {
+                from_instance: false,
+                ..
+            } => {
+                let host = full_url.host_str().unwrap_or_default();
+                let instance = match full_url.port() {
+                    Some(port) => format!("{host}_{port}"),
+                    None => host.to_string(),
+                };
+                self.retrieve_cached(&path, full_url.as_str(), Some(&instance), ctx)
+                    .await
+            }
+            PlayerSource::Remote { .. } => {
+                Download::retrieve_url(full_url.as_str(), &ctx.args.http_handling, &ctx.client)
+                    .await
+            }
+            #[cfg(feature = "fs")]
+            PlayerSource::Local(checkout) => match checkout.read(&path).await {
+                Ok(content) => Download::from_content(full_url.as_str(), content),
+                Err(e) => {
+                    // Resources such as images may legitimately be missing from a local copy
+                    // that only contains the code, so we try the AAO instance instead.
+                    debug!("{e:#}, downloading it from {full_url} instead.");
+                    Download::retrieve_url(full_url.as_str(), &ctx.args.http_handling, &ctx.client)
+                        .await
+                }
+            },
+        }
+    }
+
+    /// Returns the path within the AAO repository that the given [url] of the AAO instance at
+    /// [base] corresponds to, or `None` if the URL doesn't belong to the instance.
+    ///
+    /// URLs with query parameters or pointing to PHP files are dynamically generated, so they
+    /// also don't correspond to any file in the repository.
+    fn repository_path(url: &Url, base: &Url) -> Option<String> {
+        if url.origin() != base.origin() || url.query().is_some() || url.path().ends_with(".php") {
+            return None;
+        }
+        url.path()
+            .strip_prefix(base.path())
+            .map(|x| x.trim_start_matches('/').to_string())
+            .filter(|x| !x.is_empty())
+    }
+
+    /// Retrieves the file at the given [path] from the cache, or downloads it from the given [url]
+    /// (putting it into the cache) if it isn't cached yet.
+    ///
+    /// Files served by an AAO [instance] (given as its host) are cached separately for it.
+    async fn retrieve_cached(
+        &self,
+        path: &str,
+        url: &str,
+        #[cfg_attr(not(feature = "fs"), allow(unused_variables))] instance: Option<&str>,
+        ctx: &GlobalContext,
+    ) -> Result<Download> {
+        #[cfg(feature = "fs")]
+        if let PlayerSource::Remote {
+            cache: Some(cache), ..
+        } = self
+        {
+            let instance_cache = instance.map(|x| cache.for_instance(x));
+            let cache = instance_cache.as_ref().unwrap_or(cache);
+            if let Some(content) = cache.get(path).await {
+                trace!("Using cached player file {path}.");
+                return Download::from_content(url, content);
+            }
+            let download =
+                Download::retrieve_url(url, &ctx.args.http_handling, &ctx.client).await?;
+            if let Err(e) = cache.put(path, &download.content).await {
+                warn!("Could not cache player file {path}: {e}");
+            }
+            return Ok(download);
+        }
+        trace!("Not caching player file {path}.");
+        Download::retrieve_url(url, &ctx.args.http_handling, &ctx.client).await
+    }
+}
@@ -0,0 +1,409 @@
+//! Contains methods for making downloaded cases installable as progressive web apps.
+//!
+//! A web app consists of a manifest (describing the name and icons of the app) and a service
+//! worker that caches all files of the case, so that it can be played offline once it has been
+//! opened from a web server.
+
+use std::collections::BTreeSet;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, ImageFormat, RgbaImage};
+use log::{debug, warn};
+use serde_json::{Value, json};
+
+use crate::FileWriter;
+use crate::data::case::Case;
+
+/// The name of the web app manifest within the directory of a case.
+pub(crate) const MANIFEST_FILE: &str = "manifest.webmanifest";
+
+/// The name of the service worker within the directory of a case.
+pub(crate) const SERVICE_WORKER_FILE: &str = "service-worker.js";
+
+/// The sizes of the icons to generate, which are the ones browsers require for installation.
+const ICON_SIZES: [u32; 2] = [192, 512];
+
+/// The service worker, which needs the constants `CACHE` (the name of the cache for this version
+/// of the case), `PREFIX` (the prefix of all caches for this case), and `FILES` (the files to
+/// cache) to be defined before it.
+///
+/// The player page is fetched from the network first (as it may be changed when other cases of
+/// its sequence are downloaded), while all assets are served from the cache.
+const SERVICE_WORKER_JS: &str = r"
+self.addEventListener('install', (event) => {
+    event.waitUntil(
+        caches.open(CACHE)
+            .then((cache) => cache.addAll(FILES))
+            .then(() => self.skipWaiting())
+    );
+});
+
+self.addEventListener('activate', (event) => {
+    event.waitUntil(
+        caches.keys()
+            .then((keys) => Promise.all(keys
+                .filter((key) => key.startsWith(PREFIX) && key !== CACHE)
+                .map((key) => caches.delete(key))))
+            .then(() => self.clients.claim())
+    );
+});
+
+// Media elements request ranges of files, which the cache doesn't handle on its own.
+function sliceResponse(request, response) {
+    const range = /^bytes=(\d*)-(\d*)$/.exec(request.headers.get('range') || '');
+    if (!range || response.status !== 200) {
+        return response;
+    }
+    return response.blob().then((blob) => {
+        const start = range[1] ? Number(range[1]) : Math.max(blob.size - Number(range[2]), 0);
+        const end = range[1] && range[2] ? Math.min(Number(range[2]) + 1, blob.size) : blob.size;
+        return new Response(blob.slice(start, end), {
+            status: 206,
+            headers: {
+                'Content-Type': response.headers.get('Content-Type') || '',
+                'Content-Range': `bytes ${start}-${end - 1}/${blob.size}`,
+            },
+        });
+    });
+}
+
+self.addEventListener('fetch', (event) => {
+    const request = event.request;
+    if (request.method !== 'GET') {
+        return;
+    }
+    if (request.mode === 'navigate') {
+        event.respondWith(
+            fetch(request)
+                .then((response) => {
+                    const copy = response.clone();
+                    caches.open(CACHE).then((cache) => cache.put(request, copy));
+                    return response;
+                })
+                .catch(() => caches.match(request, { ignoreSearch: true }))
+        );
+        return;
+    }
+    event.respondWith(
+        caches.match(request, { ignoreSearch: true })
+            .then((cached) => cached ? sliceResponse(request, cached) : fetch(request))
+    );
+});
+";
+
+/// The code registering the service worker, added to the player.
+const REGISTER_JS: &str = r"
+if ('serviceWorker' in navigator && location.protocol !== 'file:') {
+    navigator.serviceWorker.register('service-worker.js');
+}
+";
+
+/// Writes the web app files for the given [case], whose player will be written to the given
+/// [output], using the given [writer].
+///
+/// The service worker caches the player, the web app files, and those of the [assets] (i.e.,
+/// the asset files that were written) that belong to the case.
+/// Returns the tags that need to be added to the head of the player to link them.
+pub(crate) async fn write_web_app(
+    case: &Case,
+    output: &Path,
+    assets: &[PathBuf],
+    writer: &dyn FileWriter,
+) -> Result<String> {
+    let dir = output
+        .parent()
+        .filter(|x| !x.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let page = output
+        .file_name()
+        .and_then(|x| x.to_str())
+        .context("Player has no valid file name")?;
+    let icons = write_icons(case, dir, writer).await;
+
+    let mut tags = format!("<link rel=\"manifest\" href=\"{MANIFEST_FILE}\">\n");
+    if let Some(icon) = icons.first() {
+        tags.push_str(&format!(
+            "<link rel=\"apple-touch-icon\" href=\"{icon}\">\n"
+        ));
+    }
+    tags.push_str(&format!("<script>{REGISTER_JS}</script>\n"));
+
+    let info = &case.case_information;
+    let manifest = json!({
+        "name": info.title,
+        "short_name": info.title,
+        "description": info
+            .author
+            .as_ref()
+            .map_or_else(|| info.title.clone(), |x| format!("{} by {x}", info.title)),
+        "lang": info.language,
+        "start_url": urlencoding::encode(page),
+        "scope": "./",
+        "display": "standalone",
+        "background_color": "#000000",
+        "theme_color": "#000000",
+        "icons": ICON_SIZES
+            .iter()
+            .zip(&icons)
+            .map(|(size, src)| json!({
+                "src": src,
+                "sizes": format!("{size}x{size}"),
+                "type": "image/png",
+            }))
+            .collect::<Vec<_>>(),
+    });
+    writer
+        .write(
+            &dir.join(MANIFEST_FILE),
+            &serde_json::to_vec_pretty(&manifest)?,
+        )
+        .await
+        .with_context(|| format!("Could not write web app manifest to {}", dir.display()))?;
+
+    // The player itself isn't written yet, but it will be by the time the app is installed.
+    let mut files = vec![];
+    if page == "index.html" {
+        // The player is also reachable under the directory itself.
+        files.push(String::from("./"));
+    }
+    files.extend([
+        urlencoding::encode(page).into_owned(),
+        MANIFEST_FILE.to_string(),
+    ]);
+    let asset_dir = dir.join("assets");
+    let names: BTreeSet<_> = assets
+        .iter()
+        .filter(|x| x.parent() == Some(asset_dir.as_path()))
+        .filter_map(|x| x.file_name()?.to_str())
+        .chain(icons.iter().filter_map(|x| x.strip_prefix("assets/")))
+        .collect();
+    files.extend(
+        names
+            .into_iter()
+            .map(|x| format!("assets/{}", urlencoding::encode(x))),
+    );
+    let mut hasher = DefaultHasher::new();
+    files.hash(&mut hasher);
+    info.last_edit_date.hash(&mut hasher);
+    let prefix = format!("aaoffline-{}-", case.id());
+    let cache = format!("{prefix}{:x}", hasher.finish());
+    let worker = format!(
+        "const CACHE = {};\nconst PREFIX = {};\nconst FILES = {};\n{SERVICE_WORKER_JS}",
+        Value::String(cache),
+        Value::String(prefix),
+        serde_json::to_string_pretty(&files)?,
+    );
+    writer
+        .write(&dir.join(SERVICE_WORKER_FILE), worker.as_bytes())
+        .await
+        .with_context(|| format!("Could not write service worker to {}", dir.display()))?;
+    Ok(tags)
+}
+
+/// Writes the icons for the given [case] into the assets of the given [dir] using the given
+/// [writer], returning their paths (relative to the [dir]) in the order of [`ICON_SIZES`].
+///
+/// The icons are based on the first profile or evidence of the case. If there is none (or it
+/// can't be read), no icons are written.
+async fn write_icons(case: &Case, dir: &Path, writer: &dyn FileWriter) -> Vec<String> {
+    let Some(source) = icon_source(&case.case_data) else {
+        warn!(
+            "Case {} has neither profiles nor evidence to use as an icon for the web app.",
+            case.case_information.title
+        );
+        return vec![];
+    };
+    let image = match read_asset(&dir.join(source))
+        .await
+        .and_then(|x| image::load_from_memory(&x).context("Could not decode image"))
+    {
+        Ok(image) => image,
+        Err(e) => {
+            warn!("Could not use {source} as an icon for the web app: {e:#}");
+            return vec![];
+        }
+    };
+    let mut icons = Vec::with_capacity(ICON_SIZES.len());
+    for size in ICON_SIZES {
+        let path = format!("assets/aaoffline-icon-{size}.png");
+        let mut content = Vec::new();
+        let result = render_icon(&image, size)
+            .write_to(&mut Cursor::new(&mut content), ImageFormat::Png)
+            .context("Could not encode icon");
+        let result = match result {
+            Ok(()) => writer.write_asset(&dir.join(&path), &content).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            warn!("Could not write icon for the web app: {e:#}");
+            return vec![];
+        }
+        icons.push(path);
+    }
+    debug!("Created web app icons from {source}.");
+    icons
+}
+
+/// Returns the path of the image to use as an icon in the given case [data], which is the icon of
+/// the first profile, or of the first evidence if there are no profiles.
+fn icon_source(data: &Value) -> Option<&str> {
+    ["profiles", "evidence"]
+        .iter()
+        .filter_map(|x| data[x].as_array())
+        .flatten()
+        .filter_map(|x| x["icon"].as_str())
+        // Assets that could not be downloaded still point to their original URL.
+        .find(|x| !x.is_empty() && !x.starts_with("data:") && !x.contains("://"))
+}
+
+/// Renders the given [image] as a square icon of the given [size], keeping its aspect ratio.
+fn render_icon(image: &DynamicImage, size: u32) -> RgbaImage {
+    // Most sprites are pixel art, which would become blurry when scaled up smoothly.
+    let filter = if image.width().max(image.height()) <= size {
+        FilterType::Nearest
+    } else {
+        FilterType::Lanczos3
+    };
+    let scaled = image.resize(size, size, filter).to_rgba8();
+    let mut icon = RgbaImage::new(size, size);
+    imageops::overlay(
+        &mut icon,
+        &scaled,
+        i64::from((size - scaled.width()) / 2),
+        i64::from((size - scaled.height()) / 2),
+    );
+    icon
+}
+
+/// Reads the previously written asset at the given [path].
+#[cfg(feature = "fs")]
+async fn read_asset(path: &Path) -> Result<Vec<u8>> {
+    tokio::fs::read(path)
+        .await
+        .with_context(|| format!("Could not read {}", path.display()))
+}
+
+/// Reads the previously written asset at the given [path].
+///
+/// Without filesystem access, this always fails.
+#[cfg(not(feature = "fs"))]
+#[allow(clippy::unused_async)]
+async fn read_asset(path: &Path) -> Result<Vec<u8>> {
+    anyhow::bail!("Cannot read {} without filesystem access.", path.display())
+}
+
+#[cfg(all(test, feature = "fs"))]
+mod tests {
+    use reqwest::Url;
+
+    use super::*;
+    use crate::fs::TokioFsWriter;
+
+    fn case(data: Value) -> Case {
+        Case {
+            case_information: serde_json::from_value(json!({
+                "id": 1,
+                "title": "My Case",
+                "author": "Someone",
+                "language": "en",
+            }))
+            .unwrap(),
+            case_data: data,
+            base_url: Url::parse("https://aaonline.fr/").unwrap(),
+            revision: None,
+        }
+    }
+
+    /// Returns the files the given service [worker] caches.
+    fn cached_files(worker: &str) -> Vec<String> {
+        let start = worker.find("const FILES = ").unwrap() + "const FILES = ".len();
+        let end = start + worker[start..].find(";\n").unwrap();
+        serde_json::from_str(&worker[start..end]).unwrap()
+    }
+
+    #[tokio::test]
+    async fn writes_manifest_and_service_worker() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("My Case_1");
+        std::fs::create_dir_all(dir.join("assets")).unwrap();
+        let mut icon = Vec::new();
+        DynamicImage::ImageRgba8(RgbaImage::new(40, 60))
+            .write_to(&mut Cursor::new(&mut icon), ImageFormat::Png)
+            .unwrap();
+        std::fs::write(dir.join("assets/face.png"), icon).unwrap();
+        let assets = [
+            dir.join("assets/face.png"),
+            dir.join("assets/my song.mp3"),
+            // Assets of other cases aren't cached for this one.
+            root.path().join("Other_2/assets/other.png"),
+        ];
+        let case = case(json!({
+            "profiles": [{ "icon": "assets/face.png" }],
+            "evidence": [],
+        }));
+
+        let tags = write_web_app(&case, &dir.join("index.html"), &assets, &TokioFsWriter)
+            .await
+            .unwrap();
+        assert!(tags.contains(r#"<link rel="manifest" href="manifest.webmanifest">"#));
+        assert!(
+            tags.contains(r#"<link rel="apple-touch-icon" href="assets/aaoffline-icon-192.png">"#)
+        );
+
+        let manifest: Value =
+            serde_json::from_slice(&std::fs::read(dir.join(MANIFEST_FILE)).unwrap()).unwrap();
+        assert_eq!(manifest["name"], "My Case");
+        assert_eq!(manifest["description"], "My Case by Someone");
+        assert_eq!(manifest["start_url"], "index.html");
+        assert_eq!(manifest["icons"][1]["sizes"], "512x512");
+        for size in ICON_SIZES {
+            let icon = image::open(dir.join(format!("assets/aaoffline-icon-{size}.png"))).unwrap();
+            assert_eq!((icon.width(), icon.height()), (size, size));
+        }
+
+        let worker = std::fs::read_to_string(dir.join(SERVICE_WORKER_FILE)).unwrap();
+        assert!(worker.contains("const PREFIX = \"aaoffline-1-\";"));
+        assert_eq!(
+            cached_files(&worker),
+            [
+                "./",
+                "index.html",
+                "manifest.webmanifest",
+                "assets/aaoffline-icon-192.png",
+                "assets/aaoffline-icon-512.png",
+                "assets/face.png",
+                "assets/my%20song.mp3",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn works_without_icon() {
+        let root = tempfile::tempdir().unwrap();
+        let case = case(json!({ "profiles": [], "evidence": [] }));
+        let tags = write_web_app(&case, &root.path().join("case.html"), &[], &TokioFsWriter)
+            .await
+            .unwrap();
+        assert!(!tags.contains("apple-touch-icon"));
+        let manifest: Value =
+            serde_json::from_slice(&std::fs::read(root.path().join(MANIFEST_FILE)).unwrap())
+                .unwrap();
+        assert_eq!(manifest["icons"], json!([]));
+        let worker = std::fs::read_to_string(root.path().join(SERVICE_WORKER_FILE)).unwrap();
+        assert_eq!(cached_files(&worker), ["case.html", "manifest.webmanifest"]);
+    }
+
+    #[test]
+    fn icon_source_skips_remote_icons() {
+        let data = json!({
+            "profiles": [{ "icon": "https://example.com/face.png" }, { "icon": "" }],
+            "evidence": [{ "icon": "assets/item.png" }],
+        });
+        assert_eq!(icon_source(&data), Some("assets/item.png"));
+        assert_eq!(icon_source(&json!({ "profiles": [] })), None);
+    }
+}
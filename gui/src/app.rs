@@ -9,7 +9,7 @@ use crate::{
     messenger::{GuiMessenger, ProgressMessage},
 };
 
-const ENABLED_CATEGORIES: [&str; 13] = [
+const ENABLED_CATEGORIES: [&str; 14] = [
     "aaoffline",
     "aaoffline::args",
     "aaoffline::data",
@@ -19,6 +19,7 @@ const ENABLED_CATEGORIES: [&str; 13] = [
     "aaoffline::download",
     "aaoffline::fs",
     "aaoffline::middleware",
+    "aaoffline::source",
     "aaoffline::transform",
     "aaoffline_gui::messenger",
     "aaoffline_gui::app",
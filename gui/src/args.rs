@@ -1,13 +1,20 @@
-use std::{collections::HashSet, path::PathBuf};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    path::{Path, PathBuf},
+};
 
-use aaoffline::args::{Args, DownloadSequence, HttpHandling, SequenceErrorHandling, Userscripts};
+use aaoffline::args::{
+    Args, AssetCategory, Authentication, AuthorFilter, CaseEntry, CaseOverrides, DownloadSequence,
+    HttpHandling, OmittedAssetHandling, SequenceErrorHandling, SequenceSelection, Userscripts,
+    VoiceFormat,
+};
 use egui::{Button, Checkbox, CollapsingHeader, Slider, TextEdit, Widget, vec2};
 use egui_form::{
     Form, FormField,
     garde::{GardeReport, field_path},
 };
 use garde::Validate;
-use log::LevelFilter;
+use log::{LevelFilter, error};
 use rfd::FileDialog;
 
 /// Arguments that configure how aaoffline runs.
@@ -19,6 +26,22 @@ pub(crate) struct GuiArgs {
     #[garde(length(min = 1), custom(Self::validate_case))]
     pub(crate) cases: Vec<String>,
 
+    /// The language that the cases of an author need to be in, if any.
+    pub(crate) author_language: String,
+
+    /// The day (in the form `YYYY-MM-DD`) on or after which the cases of an author need to have
+    /// been last edited, if any.
+    #[garde(custom(Self::validate_date))]
+    pub(crate) edited_since: String,
+
+    /// The backed-up revision of the case that shall be downloaded instead of its current version,
+    /// given either by its name or by a date in the form `YYYY-MM-DD`.
+    pub(crate) revision: String,
+
+    /// The URL at which the revisions of a case are listed, with `{id}` being replaced by the ID
+    /// of the case.
+    pub(crate) revisions_url: String,
+
     /// The output directory (or filename, if `-1` was used) for the case.
     ///
     /// If this is not passed, will use the title + ID of the case.
@@ -31,6 +54,13 @@ pub(crate) struct GuiArgs {
     #[garde(length(min = 1))]
     pub(crate) player_version: String,
 
+    /// Whether to cache the code of the player between runs.
+    pub(crate) cache_player: bool,
+
+    /// A local checkout of the AAO repository to use as the source for the player's code.
+    #[garde(custom(Self::validate_source))]
+    pub(crate) player_source: Option<PathBuf>,
+
     /// The language to download the player in.
     #[garde(length(min = 2))]
     pub(crate) language: String,
@@ -41,13 +71,39 @@ pub(crate) struct GuiArgs {
     /// Whether to replace any existing output files.
     pub(crate) replace_existing: bool,
 
+    /// Whether to start a library at the root of the output if there is none yet.
+    pub(crate) create_library: bool,
+
     /// Whether to download all trials contained in a sequence (if the given case is part of a
     /// sequence).
     pub(crate) sequence: DownloadSequence,
 
+    /// The positions of the cases in the sequence to download (e.g., `9-12`), if not all of them.
+    #[garde(custom(Self::validate_sequence_range))]
+    pub(crate) sequence_range: String,
+
     /// Whether to output only a single HTML file, with the assets embedded as data URLs.
     pub(crate) one_html_file: bool,
 
+    /// Whether to pack the assets of the single HTML file into a compressed blob.
+    pub(crate) compress_assets: bool,
+
+    /// The size above which assets are written next to the single HTML file (e.g., `2M`), if any.
+    #[garde(custom(Self::validate_size))]
+    pub(crate) sidecar_threshold: String,
+
+    /// Whether to put all cases into the single HTML file.
+    pub(crate) bundle_cases: bool,
+
+    /// Whether to make the downloaded case installable as a progressive web app.
+    pub(crate) web_app: bool,
+
+    /// The categories of assets that shall not be downloaded.
+    pub(crate) omit_assets: BTreeSet<AssetCategory>,
+
+    /// What to put in place of the omitted assets.
+    pub(crate) omitted_asset_handling: OmittedAssetHandling,
+
     /// Whether to apply any userscripts to the downloaded case. Can be passed multiple times.
     ///
     /// Scripts were created by Time Axis, with only the expanded keyboard controls written by me,
@@ -91,6 +147,12 @@ pub(crate) struct GuiArgs {
     /// option if you plan to use Firefox.)
     pub(crate) disable_html5_audio: bool,
 
+    /// Whether to embed voice blips and short sound effects into the case data.
+    pub(crate) embed_short_audio: bool,
+
+    /// The format in which the voice blips shall be downloaded.
+    pub(crate) voice_format: VoiceFormat,
+
     /// Whether to disable the automatic fixing of photobucket watermarks.
     pub(crate) disable_photobucket_fix: bool,
 
@@ -106,6 +168,43 @@ pub(crate) struct GuiArgs {
     /// This can be useful for testing with a local instance of AAO, for example.
     /// If not set, will default to the official AAO URL (aaonline.fr).
     pub(crate) base_url: String,
+
+    /// Hosts that are equivalent to the base URL (one per line), to be tried in order if a
+    /// request to the base URL fails.
+    pub(crate) mirrors: String,
+
+    /// The URL under which the raw files of the AAO repository can be found.
+    ///
+    /// Any `{version}` in the URL is replaced by the player version.
+    /// If not set, will default to the official AAO repository on Bitbucket.
+    pub(crate) player_code_url: String,
+
+    /// Whether to retrieve the player's scripts from the AAO instance instead of from the AAO
+    /// repository.
+    pub(crate) player_from_instance: bool,
+
+    /// The URL under which the userscripts can be found, if they shall not be retrieved from
+    /// their usual locations.
+    pub(crate) userscripts_url: String,
+
+    /// A cookie file containing a session on Ace Attorney Online.
+    pub(crate) cookie_file: Option<PathBuf>,
+
+    /// The name of the forum user to log in to Ace Attorney Online as, if any.
+    pub(crate) login_username: String,
+
+    /// The password of the forum user to log in to Ace Attorney Online as.
+    pub(crate) login_password: Password,
+}
+
+/// A password that is never shown in debug output.
+#[derive(Clone, Default)]
+pub(crate) struct Password(String);
+
+impl std::fmt::Debug for Password {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<redacted>")
+    }
 }
 
 impl GuiArgs {
@@ -113,15 +212,18 @@ impl GuiArgs {
         Self {
             cases: vec![String::new()],
             player_version: String::from("master"),
+            cache_player: true,
             language: String::from("en"),
             continue_on_asset_error: false,
             replace_existing: false,
+            create_library: false,
             one_html_file: false,
             concurrent_downloads: 5,
             retries: 3,
             connect_timeout: 10,
             read_timeout: 30,
             disable_html5_audio: false,
+            embed_short_audio: false,
             disable_photobucket_fix: false,
             sequence: DownloadSequence::Every,
             sequence_error_handling: SequenceErrorHandling::Continue,
@@ -129,18 +231,94 @@ impl GuiArgs {
         }
     }
 
+    /// Splits the given [cases] into local trial files and case IDs / URLs.
+    ///
+    /// Author profile URLs are part of neither.
+    fn case_inputs(cases: &[String]) -> (Vec<PathBuf>, impl Iterator<Item = &String>) {
+        let is_file = |x: &&String| Path::new(x.as_str()).is_file();
+        (
+            cases.iter().filter(is_file).map(PathBuf::from).collect(),
+            cases
+                .iter()
+                .filter(move |x| !is_file(x) && !Self::is_author(x)),
+        )
+    }
+
+    /// Parses the given case [inputs], each of which may be followed by settings for the case.
+    fn case_entries<'a>(
+        inputs: impl Iterator<Item = &'a String>,
+    ) -> Result<Vec<CaseEntry>, String> {
+        inputs
+            .filter_map(|x| Args::accept_case_line(x).transpose())
+            .collect()
+    }
+
+    /// Returns whether the given [input] is the profile URL of an author (and not a case).
+    fn is_author(input: &str) -> bool {
+        Args::accept_case_line(input).is_err() && Args::accept_author(input).is_ok()
+    }
+
+    /// Returns the authors whose profile URLs are among the given [cases].
+    fn author_inputs(cases: &[String]) -> Vec<u32> {
+        cases
+            .iter()
+            .filter(|x| Self::is_author(x))
+            .filter_map(|x| Args::accept_author(x).ok())
+            .collect()
+    }
+
+    #[allow(clippy::trivially_copy_pass_by_ref)] // Generated by garde
+    fn validate_date(value: &str, (): &()) -> garde::Result {
+        if value.is_empty() {
+            Ok(())
+        } else {
+            Args::accept_date(value)
+                .map(|_| ())
+                .map_err(garde::Error::new)
+        }
+    }
+
+    #[allow(clippy::trivially_copy_pass_by_ref)] // Generated by garde
+    fn validate_sequence_range(value: &str, (): &()) -> garde::Result {
+        if value.is_empty() {
+            Ok(())
+        } else {
+            Args::accept_sequence_range(value)
+                .map(|_| ())
+                .map_err(garde::Error::new)
+        }
+    }
+
+    #[allow(clippy::trivially_copy_pass_by_ref)] // Generated by garde
+    fn validate_size(value: &str, (): &()) -> garde::Result {
+        if value.is_empty() {
+            Ok(())
+        } else {
+            Args::accept_size(value)
+                .map(|_| ())
+                .map_err(garde::Error::new)
+        }
+    }
+
     #[allow(clippy::ref_option, clippy::trivially_copy_pass_by_ref)] // Generated by garde
     fn validate_case(cases: &[String], (): &()) -> garde::Result {
         if cases.iter().any(String::is_empty) {
             return Err(garde::Error::new("Please enter a case ID / URL."));
         }
+        if Self::author_inputs(cases).len() > 1 {
+            return Err(garde::Error::new(
+                "Only the cases of a single author can be downloaded at once.",
+            ));
+        }
 
-        let parsed: Vec<_> = cases
-            .iter()
-            .map(|x| Args::accept_case(x).map_err(garde::Error::new))
-            .collect::<Result<_, _>>()?;
+        let parsed: Vec<_> = Self::case_entries(Self::case_inputs(cases).1)
+            .map_err(garde::Error::new)?
+            .into_iter()
+            .map(|x| (x.id, x.host))
+            .collect();
 
-        Args::resolve_base_url(None, &parsed)
+        let (base_url, _) = Args::resolve_base_url(None, &parsed).map_err(garde::Error::new)?;
+        Args::resolve_case_hosts(None, &parsed, &base_url)
             .map(|_| ())
             .map_err(garde::Error::new)
     }
@@ -164,6 +342,17 @@ impl GuiArgs {
         }
     }
 
+    #[allow(clippy::ref_option, clippy::trivially_copy_pass_by_ref)] // Generated by garde
+    fn validate_source(value: &Option<PathBuf>, (): &()) -> garde::Result {
+        match value.as_ref().map(std::fs::metadata) {
+            Some(Err(e)) => Err(garde::Error::new(e)),
+            Some(Ok(x)) if !x.is_dir() => {
+                Err(garde::Error::new("This is a file. I need a directory."))
+            }
+            _ => Ok(()),
+        }
+    }
+
     pub(crate) fn clicked_download(&mut self, ui: &mut egui::Ui, download_active: bool) -> bool {
         if download_active {
             ui.disable();
@@ -174,7 +363,7 @@ impl GuiArgs {
             for (i, case) in self.cases.iter_mut().enumerate() {
                 ui.horizontal(|ui| {
                     FormField::new(&mut form, field_path!("cases", i))
-                        .label("Case ID / URL")
+                        .label("Case ID / URL / trial file / author profile")
                         .ui(
                             ui,
                             TextEdit::singleline(case)
@@ -189,7 +378,74 @@ impl GuiArgs {
                 if self.cases.len() > 1 && ui.button("Remove case").clicked() {
                     self.cases.pop();
                 }
+                if ui
+                    .button("Import trial file")
+                    .on_hover_text("Import a local trial file (the output of AAO's trial.js.php, or JSON containing trial_information and initial_trial_data) instead of downloading the case.")
+                    .clicked()
+                    && let Some(file) = FileDialog::new()
+                        .add_filter("Trial file", &["js", "php", "json", "txt"])
+                        .pick_file()
+                {
+                    let file = file.to_string_lossy().into_owned();
+                    match self.cases.last_mut() {
+                        Some(last) if last.is_empty() => *last = file,
+                        _ => self.cases.push(file),
+                    }
+                }
+                if ui
+                    .button("Import list")
+                    .on_hover_text("Import a list of cases from a text file, with one case ID / URL per line. Lines starting with # are ignored. Each case may be followed by settings such as output=\"My Case\" or version=master.")
+                    .clicked()
+                    && let Some(file) = FileDialog::new()
+                        .add_filter("Case list", &["txt", "list"])
+                        .pick_file()
+                {
+                    match std::fs::read_to_string(&file)
+                        .map_err(|e| e.to_string())
+                        .and_then(|x| Args::accept_case_list(&x).map(|_| x))
+                    {
+                        Ok(content) => {
+                            if self.cases.last().is_some_and(String::is_empty) {
+                                self.cases.pop();
+                            }
+                            self.cases.extend(
+                                content
+                                    .lines()
+                                    .map(str::trim)
+                                    .filter(|x| !x.is_empty() && !x.starts_with('#'))
+                                    .map(String::from),
+                            );
+                            if self.cases.is_empty() {
+                                self.cases.push(String::new());
+                            }
+                        }
+                        Err(e) => error!("Could not import case list {}: {e}", file.display()),
+                    }
+                }
             });
+            if self.cases.len() == 1 {
+                FormField::new(&mut form, field_path!("revision"))
+                    .label("Revision")
+                    .ui(
+                        ui,
+                        TextEdit::singleline(&mut self.revision)
+                            .hint_text("Leave empty for the current version"),
+                    )
+                    .on_hover_text("A backed-up revision of the case to download instead of its current version.\n\nThis is either the name of a revision or a date (YYYY-MM-DD), in which case the latest revision backed up on or before that day is used.");
+            }
+            if !Self::author_inputs(&self.cases).is_empty() {
+                ui.group(|ui| {
+                    ui.label("Author filters").on_hover_text("Filters that the cases of the author need to match to be downloaded. The cases are put into a directory for the author, with a subdirectory for each sequence.");
+                    FormField::new(&mut form, field_path!("author_language"))
+                        .label("Language")
+                        .ui(ui, TextEdit::singleline(&mut self.author_language).hint_text("Any language"))
+                        .on_hover_text("Only download the cases of the author that are in the given language (e.g., en).");
+                    FormField::new(&mut form, field_path!("edited_since"))
+                        .label("Last edited since")
+                        .ui(ui, TextEdit::singleline(&mut self.edited_since).hint_text("YYYY-MM-DD"))
+                        .on_hover_text("Only download the cases of the author that were last edited on or after the given day.");
+                });
+            }
             // Display cross-case validation errors (e.g., mismatched base URLs).
             if let Err(e) = Self::validate_case(&self.cases, &()) {
                 ui.colored_label(ui.style().visuals.error_fg_color, e.to_string());
@@ -215,6 +471,15 @@ impl GuiArgs {
             )
             .on_hover_text("Whether to replace any existing output files.");
 
+        FormField::new(&mut form, field_path!("create_library"))
+            .ui(
+                ui,
+                Checkbox::new(&mut self.create_library, "Start a library"),
+            )
+            .on_hover_text("Whether to start a library at the root of the output (i.e., the output directory, or the directory containing the case if only one is downloaded) if there is none yet.
+
+A library keeps track of the cases downloaded to it and lists them in an index page, so that cases downloaded later can be linked to the other cases of their sequence. Libraries that already exist are always updated.");
+
         FormField::new(&mut form, field_path!("one_html_file"))
             .ui(
                 ui,
@@ -224,6 +489,31 @@ impl GuiArgs {
 
 WARNING: Browsers may not like HTML files very much that are multiple dozens of megabytes large. Your mileage may vary.");
 
+        ui.add_enabled_ui(self.one_html_file, |ui| {
+            FormField::new(&mut form, field_path!("compress_assets"))
+                .ui(ui, Checkbox::new(&mut self.compress_assets, "Compress assets"))
+                .on_hover_text("Whether to pack the assets of the single HTML file into a compressed blob.
+
+A small script in the HTML file decompresses the assets when the player needs them, which makes the file considerably smaller and faster to load than one with data URLs.");
+            FormField::new(&mut form, field_path!("sidecar_threshold"))
+                .label("Sidecar threshold")
+                .ui(ui, TextEdit::singleline(&mut self.sidecar_threshold).hint_text("None (or e.g. 2M)"))
+                .on_hover_text("The size above which assets (such as music) are not embedded into the HTML file, but written into a folder next to it. This keeps the HTML file small enough for browsers, while the case stays nearly a single file.");
+            FormField::new(&mut form, field_path!("bundle_cases"))
+                .ui(ui, Checkbox::new(&mut self.bundle_cases, "Bundle cases into one file"))
+                .on_hover_text("Whether to put all cases into the single HTML file, with a list to choose a case from.
+
+Assets used by several cases are only embedded once, and the next case of a sequence is opened from the same file. When downloading the cases of an author, each sequence gets its own file.");
+        });
+
+        ui.add_enabled_ui(!self.one_html_file, |ui| {
+            FormField::new(&mut form, field_path!("web_app"))
+                .ui(ui, Checkbox::new(&mut self.web_app, "Output installable web app"))
+                .on_hover_text("Whether to make the downloaded case installable as a progressive web app (not possible with a single HTML file).
+
+Once the case has been opened from a web server (e.g., on an intranet), it can be installed and played offline, such as on phones or tablets.");
+        });
+
         FormField::new(&mut form, field_path!("concurrent_downloads"))
             .label("Concurrent downloads")
             .ui(ui, Slider::new(&mut self.concurrent_downloads, 1..=10))
@@ -243,6 +533,12 @@ WARNING: Browsers may not like HTML files very much that are multiple dozens of
                     "Single case",
                 ).on_hover_text("Only download the cases that are passed.");
             });
+            if self.sequence == DownloadSequence::Every {
+                FormField::new(&mut form, field_path!("sequence_range"))
+                    .label("Positions")
+                    .ui(ui, TextEdit::singleline(&mut self.sequence_range).hint_text("All (or e.g. 9-12)"))
+                    .on_hover_text("Only download the cases at the given positions of the sequence. Cases of the sequence that were already downloaded to the same directory before are still linked to.");
+            }
         });
 
         if self.sequence != DownloadSequence::Single {
@@ -316,6 +612,47 @@ WARNING: Browsers may not like HTML files very much that are multiple dozens of
             }
         });
 
+        ui.group(|ui| {
+            ui.label("Omit assets").on_hover_text("Categories of assets that shall not be downloaded, e.g., to get a lighter copy of a case without its music.");
+            ui.horizontal_wrapped(|ui| {
+                for category in AssetCategory::ALL {
+                    let mut omitted = self.omit_assets.contains(&category);
+                    let label = match category {
+                        AssetCategory::Profiles => "Profiles",
+                        AssetCategory::Evidence => "Evidence",
+                        AssetCategory::Places => "Places",
+                        AssetCategory::Popups => "Popups",
+                        AssetCategory::Music => "Music",
+                        AssetCategory::Sounds => "Sounds",
+                        AssetCategory::Voices => "Voices",
+                    };
+                    if ui.checkbox(&mut omitted, label).changed() {
+                        if omitted {
+                            self.omit_assets.insert(category);
+                        } else {
+                            self.omit_assets.remove(&category);
+                        }
+                    }
+                }
+            });
+            ui.add_enabled_ui(!self.omit_assets.is_empty(), |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    ui.radio_value(
+                        &mut self.omitted_asset_handling,
+                        OmittedAssetHandling::Online,
+                        "Keep online",
+                    )
+                    .on_hover_text("Keep referencing the omitted assets at their online location, so that they're still available while there is an internet connection.");
+                    ui.radio_value(
+                        &mut self.omitted_asset_handling,
+                        OmittedAssetHandling::Placeholder,
+                        "Use placeholders",
+                    )
+                    .on_hover_text("Replace the omitted assets with empty images and silent sounds, so that the case never accesses the internet.");
+                });
+            });
+        });
+
         let to_single_file = self.one_html_file && self.cases.len() == 1;
         let component = if to_single_file { "file" } else { "directory" };
         ui.horizontal(|ui| {
@@ -361,6 +698,24 @@ WARNING: Browsers may not like HTML files very much that are multiple dozens of
                     .ui(ui, TextEdit::singleline(&mut self.player_version).hint_text("master"))
                 .on_hover_text("The branch or commit name of Ace Attorney Online that shall be used for the player.");
 
+                ui.horizontal(|ui| {
+                    ui.label("Player source");
+                    if ui.button("Select directory").clicked() {
+                        self.player_source = FileDialog::new().pick_folder();
+                    }
+                    if self.player_source.is_some() && ui.button("Clear").clicked() {
+                        self.player_source = None;
+                    }
+                });
+                let mut source_text = self.player_source.as_ref().and_then(|x| x.to_str()).unwrap_or("");
+                FormField::new(&mut form, field_path!("player_source"))
+                    .ui(ui, TextEdit::singleline(&mut source_text).interactive(false).hint_text("AAO repository on Bitbucket"))
+                    .on_hover_text("A local checkout of the AAO repository to use as the source for the player's code. If this is a git checkout, the player version is resolved within it. Otherwise, the files in the directory are used as they are.");
+
+                FormField::new(&mut form, field_path!("cache_player"))
+                    .ui(ui, Checkbox::new(&mut self.cache_player, "Cache player code"))
+                    .on_hover_text("Whether to cache the code of the player between runs, so that it doesn't need to be downloaded again for the same version of Ace Attorney Online.");
+
                 FormField::new(&mut form, field_path!("language"))
                     .label("Player Language")
                     .ui(ui, TextEdit::singleline(&mut self.language).hint_text("en"))
@@ -410,6 +765,29 @@ WARNING: Browsers may not like HTML files very much that are multiple dozens of
                         "Whether to disable the use of HTML5 audio for Howler.js.\n\nEnabling this will lead to CORS errors appearing in your browser's console when you open the HTML file locally, since it isn't allowed to access other files. Howler.js will then switch to HTML5 audio automatically. However, if you plan to use a local web server to open the player, it is recommended to enable this option, since those errors won't appear there (and there's a problem with how Firefox handles HTML5 audio, making this the better option if you plan to use Firefox.)",
                     );
 
+                FormField::new(&mut form, field_path!("embed_short_audio"))
+                    .ui(
+                        ui,
+                        Checkbox::new(&mut self.embed_short_audio, "Embed voices and sound effects"),
+                    )
+                    .on_hover_text(
+                        "Whether to embed voice blips and short sound effects into the case data, even when not outputting a single HTML file.\n\nBrowsers can then play these sounds via Web Audio when opening the HTML file locally, avoiding both the CORS errors and the problems Firefox has with HTML5 audio. Music is still saved as separate files.",
+                    );
+
+                ui.group(|ui| {
+                    ui.label("Voice format").on_hover_text("The format in which the voice blips shall be downloaded.\n\nBy default, every format is downloaded, and the browser plays the first one it supports. Choosing a single format makes each case smaller, and the player then uses it everywhere.");
+                    ui.horizontal_wrapped(|ui| {
+                        ui.radio_value(&mut self.voice_format, VoiceFormat::All, "All")
+                            .on_hover_text("Download every format, so that each browser can pick the one it supports best.");
+                        ui.radio_value(&mut self.voice_format, VoiceFormat::Opus, "Opus")
+                            .on_hover_text("The smallest format, but not supported by some older browsers.");
+                        ui.radio_value(&mut self.voice_format, VoiceFormat::Wav, "WAV")
+                            .on_hover_text("Supported by every browser, but the largest format.");
+                        ui.radio_value(&mut self.voice_format, VoiceFormat::Mp3, "MP3")
+                            .on_hover_text("Supported by every browser.");
+                    });
+                });
+
                 FormField::new(&mut form, field_path!("disable_photobucket_fix"))
                     .ui(
                         ui,
@@ -430,6 +808,65 @@ WARNING: Browsers may not like HTML files very much that are multiple dozens of
                         ui,
                         TextEdit::singleline(&mut self.base_url).hint_text("Leave empty for default (aaonline.fr)"),
                     ).on_hover_text("The base URL to use for Ace Attorney Online.\n\nThis can be useful for testing with a local instance of AAO, for example. If not set, will default to the official AAO URL (aaonline.fr).");
+
+                FormField::new(&mut form, field_path!("mirrors"))
+                    .label("Mirrors")
+                    .ui(
+                        ui,
+                        TextEdit::multiline(&mut self.mirrors).desired_rows(2).hint_text("One URL per line"),
+                    ).on_hover_text("Hosts that are equivalent to the base URL (i.e., mirrors of it), to be used if a request to the base URL fails. Mirrors are tried in the given order.");
+
+                FormField::new(&mut form, field_path!("player_code_url"))
+                    .label("Player code URL")
+                    .ui(
+                        ui,
+                        TextEdit::singleline(&mut self.player_code_url).hint_text("Leave empty for default (Bitbucket)"),
+                    ).on_hover_text("The URL under which the raw files of the AAO repository can be found.\n\nThis is useful for self-hosted AAO instances whose code differs from the official one. Any `{version}` in the URL is replaced by the player version. If not set, will default to the official AAO repository on Bitbucket.");
+
+                FormField::new(&mut form, field_path!("player_from_instance"))
+                    .ui(
+                        ui,
+                        Checkbox::new(&mut self.player_from_instance, "Player scripts from instance"),
+                    )
+                    .on_hover_text("Whether to retrieve the player's scripts from the AAO instance (see base URL) instead of from the AAO repository.\n\nThe player itself is still retrieved from the repository, since the instance only serves its rendered output.");
+
+                FormField::new(&mut form, field_path!("userscripts_url"))
+                    .label("Userscripts URL")
+                    .ui(
+                        ui,
+                        TextEdit::singleline(&mut self.userscripts_url).hint_text("Leave empty for default locations"),
+                    ).on_hover_text("The URL under which the userscripts can be found, if they shall not be retrieved from their usual locations.\n\nUserscripts are looked up by their filename (e.g., `aaobacklog.user.js`) within this URL.");
+
+                FormField::new(&mut form, field_path!("revisions_url"))
+                    .label("Revisions URL")
+                    .ui(
+                        ui,
+                        TextEdit::singleline(&mut self.revisions_url).hint_text("Leave empty for the instance's backups directory"),
+                    ).on_hover_text("The URL at which the backed-up revisions of a case are listed, for AAO instances that make their backups available elsewhere.\n\nAny `{id}` in the URL is replaced by the ID of the case.");
+
+                ui.group(|ui| {
+                    ui.label("Authentication").on_hover_text("How to authenticate with Ace Attorney Online, e.g., to download cases that were privately shared with you.\n\nThe session is only used for requests to Ace Attorney Online and is never written to the output, so this can't be used together with a proxy.");
+                    ui.horizontal(|ui| {
+                        ui.label("Cookie file");
+                        if ui.button("Select file").clicked() {
+                            self.cookie_file = FileDialog::new().pick_file();
+                        }
+                        if self.cookie_file.is_some() && ui.button("Clear").clicked() {
+                            self.cookie_file = None;
+                        }
+                    });
+                    let mut cookie_text = self.cookie_file.as_ref().and_then(|x| x.to_str()).unwrap_or("");
+                    ui.add(TextEdit::singleline(&mut cookie_text).interactive(false).hint_text("No cookie file"))
+                        .on_hover_text("A cookie file containing a session on Ace Attorney Online, either in the Netscape format (as exported by most browser extensions) or containing the value of a `Cookie` header.");
+                    ui.add_enabled_ui(self.cookie_file.is_none(), |ui| {
+                        FormField::new(&mut form, field_path!("login_username"))
+                            .label("Forum username")
+                            .ui(ui, TextEdit::singleline(&mut self.login_username).hint_text("Leave empty to not log in"))
+                            .on_hover_text("The name of a forum user to log in to Ace Attorney Online as (instead of using a cookie file).");
+                        ui.label("Forum password");
+                        ui.add(TextEdit::singleline(&mut self.login_password.0).password(true));
+                    });
+                });
         });
         matches!(form.handle_submit(&response.inner, ui), Some(Ok(())))
     }
@@ -439,37 +876,119 @@ impl TryFrom<GuiArgs> for Args {
     type Error = String;
 
     fn try_from(value: GuiArgs) -> Result<Self, Self::Error> {
-        let cases: Vec<(u32, Option<String>)> = value
-            .cases
+        if !value.proxy.is_empty()
+            && (value.cookie_file.is_some() || !value.login_username.is_empty())
+        {
+            return Err(
+                "Authenticating with Ace Attorney Online is not possible when using a proxy."
+                    .to_string(),
+            );
+        }
+        let (case_files, case_inputs) = GuiArgs::case_inputs(&value.cases);
+        let entries = GuiArgs::case_entries(case_inputs)?;
+        let case_overrides: HashMap<u32, CaseOverrides> = entries
             .iter()
-            .map(|x| Self::accept_case(x))
-            .collect::<Result<_, _>>()?;
+            .map(|x| (x.id, x.overrides.clone()))
+            .collect();
+        let cases: Vec<(u32, Option<String>)> =
+            entries.into_iter().map(|x| (x.id, x.host)).collect();
         let case_ids = cases.iter().map(|case| case.0).collect::<Vec<_>>();
         let explicit_base = Some(value.base_url.as_str()).filter(|x| !x.is_empty());
         let (base_url, base_url_warning) = Args::resolve_base_url(explicit_base, &cases)?;
-        let warnings: Vec<String> = base_url_warning.into_iter().collect();
+        let (case_hosts, case_hosts_warning) =
+            Args::resolve_case_hosts(explicit_base, &cases, &base_url)?;
+        let warnings: Vec<String> = base_url_warning
+            .into_iter()
+            .chain(case_hosts_warning)
+            .collect();
 
         Ok(Args {
             cases: case_ids,
+            case_files,
+            case_overrides,
+            author: GuiArgs::author_inputs(&value.cases).first().copied(),
+            author_filter: AuthorFilter {
+                language: Some(value.author_language).filter(|x| !x.is_empty()),
+                edited_since: Some(value.edited_since.as_str())
+                    .filter(|x| !x.is_empty())
+                    .map(Args::accept_date)
+                    .transpose()?,
+            },
+            author_cases_url: None,
+            revision: Some(value.revision).filter(|x| !x.is_empty() && value.cases.len() == 1),
+            list_revisions: false,
+            library_command: None,
+            library_dir: None,
+            revisions_url: Some(value.revisions_url).filter(|x| !x.is_empty()),
             output: value.output,
             player_version: value.player_version,
+            player_cache_dir: value
+                .cache_player
+                .then(aaoffline::fs::default_cache_dir)
+                .flatten(),
+            player_source: value.player_source,
+            player_code_url: Some(value.player_code_url).filter(|x| !x.is_empty()),
+            player_from_instance: value.player_from_instance,
             language: value.language,
             continue_on_asset_error: value.continue_on_asset_error,
             replace_existing: value.replace_existing,
+            create_library: value.create_library,
+            skip_unchanged: false,
+            isolated: false,
             sequence: value.sequence,
+            sequence_selection: Some(value.sequence_range.as_str())
+                .filter(|x| !x.is_empty() && value.sequence == DownloadSequence::Every)
+                .map(Args::accept_sequence_range)
+                .transpose()?
+                .map(SequenceSelection::Range),
             one_html_file: value.one_html_file,
+            compress_assets: value.one_html_file && value.compress_assets,
+            sidecar_threshold: Some(value.sidecar_threshold.as_str())
+                .filter(|x| !x.is_empty() && value.one_html_file)
+                .map(Args::accept_size)
+                .transpose()?,
+            bundle_cases: value.one_html_file && value.bundle_cases,
+            web_app: value.web_app,
+            omit_assets: value.omit_assets,
+            omitted_asset_handling: value.omitted_asset_handling,
             with_userscripts: value.with_userscripts.into_iter().collect(),
+            userscripts_url: Some(value.userscripts_url.as_str())
+                .filter(|x| !x.is_empty())
+                .map(str::parse)
+                .transpose()
+                .map_err(|e| format!("Invalid userscripts URL: {e}"))?,
             concurrent_downloads: value.concurrent_downloads,
             retries: value.retries,
             connect_timeout: value.connect_timeout,
             read_timeout: value.read_timeout,
             http_handling: value.http_handling,
             disable_html5_audio: value.disable_html5_audio,
+            embed_short_audio: value.embed_short_audio,
+            voice_format: value.voice_format,
             disable_photobucket_fix: value.disable_photobucket_fix,
             proxy: Some(value.proxy).filter(|x| !x.is_empty()),
             log_level: LevelFilter::Debug,
             sequence_error_handling: value.sequence_error_handling,
             base_url,
+            case_hosts,
+            mirrors: value
+                .mirrors
+                .lines()
+                .map(str::trim)
+                .filter(|x| !x.is_empty())
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("Invalid mirror URL: {e}"))?,
+            authentication: if let Some(path) = value.cookie_file {
+                Some(Authentication::CookieFile(path))
+            } else if value.login_username.is_empty() {
+                None
+            } else {
+                Some(Authentication::Login {
+                    username: value.login_username,
+                    password: value.login_password.0,
+                })
+            },
             warnings,
         })
     }
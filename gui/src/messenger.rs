@@ -33,17 +33,22 @@ impl GuiMessenger {
             rt.block_on(async {
                 let writer = Box::new(TokioFsWriter);
                 let helper = GuiMessageSender { sender };
-                let num_cases = args.cases.len();
+                let num_cases = args.cases.len() + args.case_files.len();
+                let author = args.author;
                 let mut ctx = MainContext::new(
                     args,
                     writer,
                     Box::new(helper.clone()),
                     Box::new(helper.clone()),
                 );
-                info!(
-                    "Starting download for {num_cases} case{}...",
-                    if num_cases == 1 { "" } else { "s" }
-                );
+                if let Some(author) = author {
+                    info!("Starting download for the cases of author {author}...");
+                } else {
+                    info!(
+                        "Starting download for {num_cases} case{}...",
+                        if num_cases == 1 { "" } else { "s" }
+                    );
+                }
                 let success = if let Err(e) = ctx.run_all_steps().await {
                     error!("{e}");
                     false